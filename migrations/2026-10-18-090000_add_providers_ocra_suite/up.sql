@@ -0,0 +1 @@
+ALTER TABLE "providers" ADD COLUMN "ocra_suite" VARCHAR(255) NULL;
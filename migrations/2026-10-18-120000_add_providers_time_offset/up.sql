@@ -0,0 +1 @@
+ALTER TABLE "providers" ADD COLUMN "time_offset" INTEGER NOT NULL DEFAULT 0;
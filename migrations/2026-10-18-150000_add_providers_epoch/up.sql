@@ -0,0 +1 @@
+ALTER TABLE "providers" ADD COLUMN "epoch" BIGINT NOT NULL DEFAULT 0;
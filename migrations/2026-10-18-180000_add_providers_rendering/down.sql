@@ -0,0 +1,2 @@
+ALTER TABLE "providers" DROP COLUMN "alphabet";
+ALTER TABLE "providers" DROP COLUMN "group_size";
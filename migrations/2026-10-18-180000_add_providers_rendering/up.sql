@@ -0,0 +1,2 @@
+ALTER TABLE "providers" ADD COLUMN "group_size" INTEGER NOT NULL DEFAULT 3;
+ALTER TABLE "providers" ADD COLUMN "alphabet" VARCHAR(255) NULL;
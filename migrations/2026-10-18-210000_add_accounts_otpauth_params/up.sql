@@ -0,0 +1 @@
+ALTER TABLE "accounts" ADD COLUMN "otpauth_params" TEXT NULL;
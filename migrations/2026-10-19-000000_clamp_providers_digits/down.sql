@@ -0,0 +1 @@
+-- The number of digits a provider had before being clamped is unknown
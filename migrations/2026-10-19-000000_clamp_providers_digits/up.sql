@@ -0,0 +1,3 @@
+-- Codes are computed from a 31 bits value, 10 digits at most
+UPDATE "providers" SET "digits" = 10 WHERE "digits" > 10;
+UPDATE "providers" SET "digits" = 1 WHERE "digits" < 1;
@@ -225,14 +225,16 @@ mod imp {
 
         fn open(&self, files: &[gio::File], _hint: &str) {
             self.activate();
-            let uris = files
-                .iter()
-                .filter_map(|f| f.uri().parse::<OTPUri>().ok())
-                .collect::<Vec<OTPUri>>();
             // We only handle a single URI (see the desktop file)
-            if let Some(uri) = uris.first() {
+            if let Some(file) = files.first() {
                 let window = self.obj().active_window();
-                window.open_add_account(Some(uri))
+                match OTPUri::from_str_strict(&file.uri()) {
+                    Ok(uri) => window.open_add_account(Some(&uri)),
+                    Err(err) => {
+                        tracing::error!("Failed to parse OTP uri {err}");
+                        window.add_toast(adw::Toast::new(&err.to_string()));
+                    }
+                }
             }
         }
     }
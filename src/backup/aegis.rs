@@ -306,6 +306,8 @@ impl Item {
             digits: provider.digits(),
             period: None,
             counter: None,
+            pin: account.otp().pin().map(ToOwned::to_owned),
+            epoch: Some(provider.epoch()).filter(|epoch| *epoch != 0),
         };
 
         if provider.method().is_event_based() {
@@ -351,6 +353,13 @@ pub struct Detail {
     pub period: Option<u32>,
     #[zeroize(skip)]
     pub counter: Option<u32>,
+    // Only used by the Yandex and mOTP entries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<String>,
+    // Not part of the Aegis format, carries T0 for tokens that don't start at the UNIX epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[zeroize(skip)]
+    pub epoch: Option<u64>,
 }
 
 impl RestorableItem for Item {
@@ -388,6 +397,14 @@ impl RestorableItem for Item {
     fn counter(&self) -> Option<u32> {
         self.info.counter
     }
+
+    fn pin(&self) -> Option<String> {
+        self.info.pin.clone()
+    }
+
+    fn epoch(&self) -> Option<u64> {
+        self.info.epoch
+    }
 }
 
 impl Backupable for Aegis {
@@ -698,5 +715,36 @@ mod tests {
         assert_eq!(items[6].method(), Method::Steam);
     }
 
+    #[test]
+    fn yandex_round_trip() {
+        let data = std::fs::read_to_string("./src/backup/tests/aegis_yandex.json").unwrap();
+        let items = Aegis::restore_from_data(data.as_bytes(), None).unwrap();
+
+        assert_eq!(items[0].account(), "alice");
+        assert_eq!(items[0].issuer(), "Yandex");
+        assert_eq!(
+            items[0].secret(),
+            "6SB2IKNM6OBZPAVBVTOHDKS4FAAAAAAADFUTQMBTRY"
+        );
+        assert_eq!(items[0].period(), Some(30));
+        assert_eq!(items[0].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[0].digits(), Some(8));
+        assert_eq!(items[0].counter(), None);
+        assert_eq!(items[0].method(), Method::Yandex);
+        assert_eq!(items[0].pin(), Some("5239".to_owned()));
+
+        let mut aegis = Aegis::default();
+        for item in items {
+            aegis.add_item(item);
+        }
+        let data = serde_json::ser::to_string_pretty(&aegis).unwrap();
+        assert!(data.contains("\"type\": \"yandex\""));
+
+        let items = Aegis::restore_from_data(data.as_bytes(), None).unwrap();
+        assert_eq!(items[0].method(), Method::Yandex);
+        assert_eq!(items[0].pin(), Some("5239".to_owned()));
+        assert_eq!(items[0].digits(), Some(8));
+    }
+
     // TODO: add tests for importing
 }
@@ -1,10 +1,11 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use gettextrs::gettext;
 use gtk::prelude::*;
+use rand::{Rng, RngCore};
 use serde::{Deserialize, Serialize};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
-use super::{Backupable, Restorable, RestorableItem};
+use super::{crypto, Backupable, Restorable, RestorableItem};
 use crate::models::{Account, Algorithm, Method, Provider, ProvidersModel};
 
 #[allow(clippy::upper_case_acronyms)]
@@ -34,6 +35,10 @@ pub struct AndOTP {
     pub tags: Vec<String>,
     #[zeroize(skip)]
     pub period: Option<u32>,
+    // Not part of the andOTP format, carries T0 for tokens that don't start at the UNIX epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[zeroize(skip)]
+    pub epoch: Option<u64>,
 }
 
 impl RestorableItem for AndOTP {
@@ -68,10 +73,85 @@ impl RestorableItem for AndOTP {
     fn counter(&self) -> Option<u32> {
         self.counter
     }
+
+    fn epoch(&self) -> Option<u64> {
+        self.epoch
+    }
+}
+
+impl AndOTP {
+    // Layout of the header of encrypted backups, followed by the ciphertext
+    const ITERATIONS_SIZE: usize = 4;
+    const SALT_SIZE: usize = 12;
+    const IV_SIZE: usize = 12;
+    const KEY_SIZE: usize = 32;
+    // andOTP picks a random number of iterations in this range
+    const MIN_ITERATIONS: u32 = 140_000;
+    const MAX_ITERATIONS: u32 = 160_000;
+    // Read from the unauthenticated header, bounded to not hang on a crafted file
+    const MAX_RESTORE_ITERATIONS: u32 = 10_000_000;
+
+    fn derive_key(password: &str, salt: &[u8], iterations: u32) -> Result<[u8; Self::KEY_SIZE]> {
+        let mut key = [0u8; Self::KEY_SIZE];
+        crypto::pbkdf2(
+            ring::pbkdf2::PBKDF2_HMAC_SHA1,
+            password.as_bytes(),
+            salt,
+            iterations,
+            &mut key,
+        )?;
+        Ok(key)
+    }
+
+    /// Encrypts the content of a backup the same way andOTP does for its
+    /// `.json.aes` files.
+    fn encrypt(plaintext: &[u8], password: &str) -> Result<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+        let iterations = rng.gen_range(Self::MIN_ITERATIONS..=Self::MAX_ITERATIONS);
+        let mut salt = [0u8; Self::SALT_SIZE];
+        rng.fill_bytes(&mut salt);
+        let mut iv = [0u8; Self::IV_SIZE];
+        rng.fill_bytes(&mut iv);
+
+        let mut key = Self::derive_key(password, &salt, iterations)?;
+        let ciphertext = crypto::aes_gcm_encrypt(&key, &iv, plaintext);
+        key.zeroize();
+        let ciphertext = ciphertext?;
+
+        let mut data = Vec::with_capacity(
+            Self::ITERATIONS_SIZE + Self::SALT_SIZE + Self::IV_SIZE + ciphertext.len(),
+        );
+        data.extend_from_slice(&iterations.to_be_bytes());
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&iv);
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>> {
+        anyhow::ensure!(
+            data.len() > Self::ITERATIONS_SIZE + Self::SALT_SIZE + Self::IV_SIZE,
+            "The encrypted backup is too short"
+        );
+        let (iterations, data) = data.split_at(Self::ITERATIONS_SIZE);
+        let (salt, data) = data.split_at(Self::SALT_SIZE);
+        let (iv, ciphertext) = data.split_at(Self::IV_SIZE);
+        let iterations = u32::from_be_bytes(iterations.try_into().unwrap());
+        anyhow::ensure!(
+            iterations <= Self::MAX_RESTORE_ITERATIONS,
+            "Too many key derivation iterations: {iterations}"
+        );
+
+        let mut key = Self::derive_key(password, salt, iterations)?;
+        let plaintext = crypto::aes_gcm_decrypt(&key, iv, ciphertext)
+            .context("Cannot decrypt the backup, is the password correct?");
+        key.zeroize();
+        plaintext
+    }
 }
 
 impl Backupable for AndOTP {
-    const ENCRYPTABLE: bool = false;
+    const ENCRYPTABLE: bool = true;
     const IDENTIFIER: &'static str = "andotp";
 
     fn title() -> String {
@@ -80,10 +160,10 @@ impl Backupable for AndOTP {
     }
 
     fn subtitle() -> String {
-        gettext("Into a plain-text JSON file")
+        gettext("Into a plain-text or encrypted JSON file")
     }
 
-    fn backup(model: &ProvidersModel, _key: Option<&str>) -> Result<Vec<u8>> {
+    fn backup(model: &ProvidersModel, key: Option<&str>) -> Result<Vec<u8>> {
         let mut items = Vec::new();
 
         for i in 0..model.n_items() {
@@ -106,18 +186,26 @@ impl Backupable for AndOTP {
                     counter: Some(account.counter()),
                     tags: vec![],
                     period: Some(provider.period()),
+                    epoch: Some(provider.epoch()).filter(|epoch| *epoch != 0),
                 };
                 items.push(otp_item);
             }
         }
 
-        let content = serde_json::ser::to_string_pretty(&items)?;
-        Ok(content.as_bytes().to_vec())
+        let mut content = serde_json::ser::to_vec_pretty(&items)?;
+        match key.filter(|key| !key.is_empty()) {
+            Some(password) => {
+                let encrypted = Self::encrypt(&content, password);
+                content.zeroize();
+                encrypted
+            }
+            None => Ok(content),
+        }
     }
 }
 
 impl Restorable for AndOTP {
-    const ENCRYPTABLE: bool = false;
+    const ENCRYPTABLE: bool = true;
     const SCANNABLE: bool = false;
     const IDENTIFIER: &'static str = "andotp";
     type Item = Self;
@@ -128,12 +216,24 @@ impl Restorable for AndOTP {
     }
 
     fn subtitle() -> String {
-        gettext("From a plain-text JSON file")
+        gettext("From a plain-text or encrypted JSON file")
     }
 
-    fn restore_from_data(from: &[u8], _key: Option<&str>) -> Result<Vec<Self::Item>> {
-        let items: Vec<AndOTP> = serde_json::de::from_slice(from)?;
-        Ok(items)
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        // Plain-text backups are a JSON array, encrypted ones start with the
+        // number of iterations
+        if from.trim_ascii_start().starts_with(b"[") {
+            let items: Vec<AndOTP> = serde_json::de::from_slice(from)?;
+            return Ok(items);
+        }
+
+        let password = key
+            .filter(|key| !key.is_empty())
+            .context("The backup is encrypted, a password is needed")?;
+        let mut content = Self::decrypt(from, password)?;
+        let items = serde_json::de::from_slice::<Vec<AndOTP>>(&content);
+        content.zeroize();
+        Ok(items?)
     }
 }
 
@@ -209,5 +309,48 @@ mod tests {
         assert_eq!(items[6].algorithm(), Algorithm::SHA1);
         assert_eq!(items[6].digits(), Some(5));
         assert_eq!(items[6].counter(), None);
+        assert_eq!(items[6].epoch(), None);
+    }
+
+    #[test]
+    fn epoch() {
+        let data = r#"[{"secret":"4SJHB4GSD43FZBAI7C2HLRJGPQ","issuer":"Deno","label":"Mason","digits":6,"type":"TOTP","algorithm":"SHA1","thumbnail":"Default","last_used":0,"used_frequency":0,"period":30,"tags":[],"epoch":1000}]"#;
+        let items = AndOTP::restore_from_data(data.as_bytes(), None).unwrap();
+        assert_eq!(items[0].epoch(), Some(1000));
+
+        let data = serde_json::ser::to_string(&items).unwrap();
+        assert!(data.contains("\"epoch\":1000"));
+    }
+
+    #[test]
+    fn parse_encrypted() {
+        let data = std::fs::read("./src/backup/tests/andotp_encrypted.json.aes").unwrap();
+        let items = AndOTP::restore_from_data(&data, Some("AndOTPTest123")).unwrap();
+
+        assert_eq!(items.len(), 7);
+        assert_eq!(items[0].account(), "Mason");
+        assert_eq!(items[0].issuer(), "Deno");
+        assert_eq!(items[0].secret(), "4SJHB4GSD43FZBAI7C2HLRJGPQ");
+        assert_eq!(items[5].counter(), Some(10300));
+        assert_eq!(items[6].method(), Method::Steam);
+
+        assert!(AndOTP::restore_from_data(&data, Some("wrong password")).is_err());
+        assert!(AndOTP::restore_from_data(&data, None).is_err());
+    }
+
+    #[test]
+    fn encrypt() {
+        let data = std::fs::read("./src/backup/tests/andotp_plain.json").unwrap();
+        let encrypted = AndOTP::encrypt(&data, "password").unwrap();
+        let iterations = u32::from_be_bytes(encrypted[..4].try_into().unwrap());
+        assert!((AndOTP::MIN_ITERATIONS..=AndOTP::MAX_ITERATIONS).contains(&iterations));
+
+        let items = AndOTP::restore_from_data(&encrypted, Some("password")).unwrap();
+        assert_eq!(items.len(), 7);
+        assert_eq!(items[1].secret(), "5OM4WOOGPLQEF6UGN3CPEOOLWU");
+
+        let mut tampered = encrypted;
+        tampered[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(AndOTP::restore_from_data(&tampered, Some("password")).is_err());
     }
 }
@@ -0,0 +1,345 @@
+//! Authenticator Pro Import Module
+//!
+//! Authenticator Pro, now Stratum, backups are `.authpro` JSON files. The
+//! encrypted ones start with a 16 bytes header telling the format apart:
+//! - `AUTHENTICATORPRO`: a key derived with Argon2id and AES-GCM
+//! - `AuthenticatorPro`: the legacy one, PBKDF2 and AES-CBC
+//!
+//! Categories are kept as tags, shown in the account details, the custom icons
+//! are lost.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use data_encoding::{BASE32_NOPAD, HEXLOWER_PERMISSIVE};
+use gettextrs::gettext;
+use serde::Deserialize;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use super::{crypto, Restorable, SkippedItem};
+use crate::models::{i18n, Algorithm, Method, OTPUri, OTP};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AuthenticatorPro {
+    authenticators: Vec<Authenticator>,
+    #[serde(default)]
+    categories: Vec<Category>,
+    #[serde(default)]
+    authenticator_categories: Vec<AuthenticatorCategory>,
+}
+
+#[derive(Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(rename_all = "PascalCase")]
+struct Authenticator {
+    #[zeroize(skip)]
+    r#type: u32,
+    #[zeroize(skip)]
+    issuer: String,
+    #[zeroize(skip)]
+    username: Option<String>,
+    secret: String,
+    pin: Option<String>,
+    #[zeroize(skip)]
+    algorithm: u32,
+    #[zeroize(skip)]
+    digits: u32,
+    #[zeroize(skip)]
+    period: u32,
+    #[zeroize(skip)]
+    counter: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Category {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(rename_all = "PascalCase")]
+struct AuthenticatorCategory {
+    #[zeroize(skip)]
+    category_id: String,
+    // Authenticators are identified by their secret
+    authenticator_secret: String,
+}
+
+impl AuthenticatorPro {
+    const HEADER: &'static [u8] = b"AUTHENTICATORPRO";
+    const SALT_SIZE: usize = 16;
+    const IV_SIZE: usize = 12;
+    const LEGACY_HEADER: &'static [u8] = b"AuthenticatorPro";
+    const LEGACY_SALT_SIZE: usize = 20;
+    const LEGACY_IV_SIZE: usize = 16;
+    const LEGACY_ITERATIONS: u32 = 64_000;
+    const KEY_SIZE: usize = 32;
+
+    fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>> {
+        let mut key = Zeroizing::new([0u8; Self::KEY_SIZE]);
+        let (header, data) = data.split_at(Self::HEADER.len());
+        if header == Self::HEADER {
+            anyhow::ensure!(
+                data.len() > Self::SALT_SIZE + Self::IV_SIZE,
+                "The backup is too short"
+            );
+            let (salt, data) = data.split_at(Self::SALT_SIZE);
+            let (iv, ciphertext) = data.split_at(Self::IV_SIZE);
+            let config = argon2::Config {
+                variant: argon2::Variant::Argon2id,
+                mem_cost: 65_536,
+                time_cost: 3,
+                lanes: 4,
+                hash_length: Self::KEY_SIZE as u32,
+                ..argon2::Config::default()
+            };
+            key.copy_from_slice(&Zeroizing::new(argon2::hash_raw(
+                password.as_bytes(),
+                salt,
+                &config,
+            )?));
+            crypto::aes_gcm_decrypt(key.as_slice(), iv, ciphertext)
+        } else {
+            anyhow::ensure!(
+                data.len() > Self::LEGACY_SALT_SIZE + Self::LEGACY_IV_SIZE,
+                "The backup is too short"
+            );
+            let (salt, data) = data.split_at(Self::LEGACY_SALT_SIZE);
+            let (iv, ciphertext) = data.split_at(Self::LEGACY_IV_SIZE);
+            crypto::pbkdf2(
+                ring::pbkdf2::PBKDF2_HMAC_SHA1,
+                password.as_bytes(),
+                salt,
+                Self::LEGACY_ITERATIONS,
+                key.as_mut_slice(),
+            )?;
+            crypto::aes_cbc_decrypt(key.as_slice(), iv, ciphertext)
+        }
+        .context("Cannot decrypt the backup, is the password correct?")
+    }
+
+    fn into_items(self) -> (Vec<OTPUri>, Vec<SkippedItem>) {
+        let categories = self
+            .categories
+            .iter()
+            .map(|category| (category.id.as_str(), category.name.as_str()))
+            .collect::<HashMap<_, _>>();
+
+        let mut items = Vec::new();
+        let mut skipped = Vec::new();
+        for authenticator in &self.authenticators {
+            let tags = self
+                .authenticator_categories
+                .iter()
+                .filter(|binding| binding.authenticator_secret == authenticator.secret)
+                .filter_map(|binding| categories.get(binding.category_id.as_str()))
+                .map(|name| name.to_string())
+                .collect();
+            match authenticator.to_uri(tags) {
+                Ok(uri) => items.push(uri),
+                Err(err) => {
+                    let name = match authenticator.username.as_deref() {
+                        Some(username) if !username.is_empty() => {
+                            format!("{}/{username}", authenticator.issuer)
+                        }
+                        _ => authenticator.issuer.clone(),
+                    };
+                    tracing::warn!("Skipping the authenticator {name}: {err}");
+                    skipped.push(SkippedItem {
+                        name,
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+        (items, skipped)
+    }
+}
+
+impl Authenticator {
+    fn to_uri(&self, tags: Vec<String>) -> Result<OTPUri> {
+        let method = match self.r#type {
+            1 => Method::HOTP,
+            2 => Method::TOTP,
+            3 => Method::MOTP,
+            4 => Method::Steam,
+            5 => Method::Yandex,
+            r#type => anyhow::bail!(i18n::i18n_f(
+                "Unsupported authenticator type {}",
+                &[&r#type.to_string()]
+            )),
+        };
+        let algorithm = match self.algorithm {
+            0 => Algorithm::SHA1,
+            1 => Algorithm::SHA256,
+            2 => Algorithm::SHA512,
+            algorithm => anyhow::bail!(i18n::i18n_f(
+                "Unsupported algorithm {}",
+                &[&algorithm.to_string()]
+            )),
+        };
+        let secret = if method == Method::MOTP {
+            // mOTP secrets are hex encoded, we store them as Base32
+            let secret = Zeroizing::new(
+                HEXLOWER_PERMISSIVE
+                    .decode(self.secret.as_bytes())
+                    .ok()
+                    .with_context(|| gettext("The secret is not valid hex"))?,
+            );
+            BASE32_NOPAD.encode(&secret)
+        } else {
+            self.secret.replace(' ', "").to_uppercase()
+        };
+        if method.requires_pin() {
+            anyhow::ensure!(
+                self.pin.as_deref().is_some_and(|pin| !pin.is_empty()),
+                gettext("Missing the PIN")
+            );
+        }
+
+        Ok(OTPUri {
+            algorithm,
+            label: self.username.clone().unwrap_or_default(),
+            secret,
+            issuer: self.issuer.clone(),
+            method,
+            digits: Some(if method == Method::Steam {
+                OTP::STEAM_DEFAULT_DIGITS
+            } else {
+                self.digits
+            }),
+            period: method.is_time_based().then_some(self.period),
+            counter: method.is_event_based().then_some(self.counter),
+            pin: self.pin.clone().filter(|_| method.requires_pin()),
+            epoch: None,
+            image: None,
+            color: None,
+            tags,
+            extra: Vec::new(),
+        })
+    }
+}
+
+impl Restorable for AuthenticatorPro {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "authenticatorpro";
+    type Item = OTPUri;
+
+    fn title() -> String {
+        // Translators: This is for restoring a backup from the Authenticator Pro app, also known as Stratum.
+        gettext("Authenticator _Pro")
+    }
+
+    fn subtitle() -> String {
+        gettext("From a plain-text or encrypted backup file")
+    }
+
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        Ok(Self::restore_from_data_with_skipped(from, key)?.0)
+    }
+
+    fn restore_from_data_with_skipped(
+        from: &[u8],
+        key: Option<&str>,
+    ) -> Result<(Vec<Self::Item>, Vec<SkippedItem>)> {
+        let is_encrypted = from.starts_with(Self::HEADER) || from.starts_with(Self::LEGACY_HEADER);
+        let mut content = if is_encrypted {
+            let password = key
+                .filter(|key| !key.is_empty())
+                .context("The backup is encrypted, a password is needed")?;
+            Self::decrypt(from, password)?
+        } else {
+            from.to_vec()
+        };
+
+        let backup = serde_json::de::from_slice::<AuthenticatorPro>(&content);
+        content.zeroize();
+        Ok(backup?.into_items())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+
+    #[test]
+    fn parse() {
+        let data = std::fs::read("./src/backup/tests/authenticatorpro_plain.authpro").unwrap();
+        let (items, skipped) =
+            AuthenticatorPro::restore_from_data_with_skipped(&data, None).unwrap();
+
+        // The authenticator of an unknown type is skipped
+        assert_eq!(items.len(), 5);
+        assert_eq!(
+            skipped,
+            [SkippedItem {
+                name: "Future/g-man".to_owned(),
+                reason: "Unsupported authenticator type 9".to_owned(),
+            }]
+        );
+
+        assert_eq!(items[0].issuer(), "GitHub");
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[0].algorithm(), Algorithm::SHA1);
+        assert_eq!(items[0].digits(), Some(6));
+        assert_eq!(items[0].period(), Some(30));
+        assert_eq!(items[0].tags, ["Work", "Dev"]);
+
+        assert_eq!(items[1].issuer(), "Black Mesa");
+        assert_eq!(items[1].account(), "gordon");
+        assert_eq!(items[1].method(), Method::HOTP);
+        assert_eq!(items[1].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[1].digits(), Some(8));
+        assert_eq!(items[1].counter(), Some(12));
+        assert_eq!(items[1].period(), None);
+        assert_eq!(items[1].tags, ["Work"]);
+
+        assert_eq!(items[2].issuer(), "Steam");
+        assert_eq!(items[2].account(), "barney");
+        assert_eq!(items[2].method(), Method::Steam);
+        assert_eq!(items[2].digits(), Some(5));
+        assert!(items[2].tags.is_empty());
+
+        assert_eq!(items[3].issuer(), "VPN");
+        assert_eq!(items[3].method(), Method::MOTP);
+        assert_eq!(items[3].secret(), "2VXZ2VMJUH5Q");
+        assert_eq!(items[3].pin(), Some("1234".to_owned()));
+
+        assert_eq!(items[4].issuer(), "Yandex");
+        assert_eq!(items[4].method(), Method::Yandex);
+        assert_eq!(items[4].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[4].pin(), Some("5239".to_owned()));
+    }
+
+    #[test]
+    fn parse_encrypted() {
+        let data = std::fs::read("./src/backup/tests/authenticatorpro_encrypted.authpro").unwrap();
+        let items = AuthenticatorPro::restore_from_data(&data, Some("AuthProTest123")).unwrap();
+
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[1].counter(), Some(12));
+        assert_eq!(items[4].method(), Method::Yandex);
+
+        assert!(AuthenticatorPro::restore_from_data(&data, Some("wrong password")).is_err());
+        assert!(AuthenticatorPro::restore_from_data(&data, None).is_err());
+    }
+
+    #[test]
+    fn parse_legacy_encrypted() {
+        let data =
+            std::fs::read("./src/backup/tests/authenticatorpro_legacy_encrypted.authpro").unwrap();
+        let items = AuthenticatorPro::restore_from_data(&data, Some("AuthProTest123")).unwrap();
+
+        assert_eq!(items.len(), 5);
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[3].pin(), Some("1234".to_owned()));
+
+        assert!(AuthenticatorPro::restore_from_data(&data, Some("wrong password")).is_err());
+    }
+}
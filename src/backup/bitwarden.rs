@@ -1,31 +1,45 @@
-use anyhow::Result;
+//! Bitwarden Import Module
+//!
+//! Supports both the plain-text JSON export and the password protected one.
+//! The latter wraps the plain-text export into an encrypted string, whose keys
+//! are derived from the password with either PBKDF2 or Argon2id.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use data_encoding::BASE64;
 use gettextrs::gettext;
+use ring::{digest, hmac};
 use serde::Deserialize;
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
-use super::{Restorable, RestorableItem};
-use crate::models::{Algorithm, Method, OTPUri, OTP};
+use super::{crypto, Restorable};
+use crate::models::{Algorithm, Method, OTPUri};
 
 #[derive(Deserialize)]
 pub struct Bitwarden {
+    #[serde(default)]
+    folders: Vec<Group>,
+    // Only part of organization exports
+    #[serde(default)]
+    collections: Vec<Group>,
     items: Vec<BitwardenItem>,
 }
 
+/// Used for both folders and collections.
 #[derive(Deserialize)]
-pub struct BitwardenItem {
-    #[serde(rename = "name")]
-    issuer: Option<String>,
+struct Group {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    name: Option<String>,
+    folder_id: Option<String>,
+    collection_ids: Option<Vec<String>>,
     login: Option<BitwardenDetails>,
-    #[serde(skip)]
-    algorithm: Algorithm,
-    #[serde(skip)]
-    method: Method,
-    #[serde(skip)]
-    digits: Option<u32>,
-    #[serde(skip)]
-    period: Option<u32>,
-    #[serde(skip)]
-    counter: Option<u32>,
 }
 
 #[derive(Deserialize, ZeroizeOnDrop, Zeroize)]
@@ -35,85 +49,219 @@ struct BitwardenDetails {
     totp: Option<String>,
 }
 
-impl RestorableItem for BitwardenItem {
-    fn account(&self) -> String {
-        if let Some(account) = self
-            .login
-            .as_ref()
-            .and_then(|login| login.username.as_ref())
-        {
-            account.clone()
-        } else {
-            gettext("Unknown account")
-        }
-    }
+/// The fields shared by all exports, to tell them apart.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    password_protected: bool,
+}
 
-    fn issuer(&self) -> String {
-        if let Some(issuer) = self.issuer.clone() {
-            issuer
-        } else {
-            gettext("Unknown issuer")
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptedExport {
+    salt: String,
+    kdf_type: u32,
+    kdf_iterations: u32,
+    // In MiB
+    kdf_memory: Option<u32>,
+    kdf_parallelism: Option<u32>,
+    data: String,
+}
+
+impl EncryptedExport {
+    const KDF_PBKDF2: u32 = 0;
+    const KDF_ARGON2ID: u32 = 1;
+    const KEY_SIZE: usize = 32;
+    // The type of the encrypted strings using AES-256-CBC with an HMAC-SHA256
+    const ENC_TYPE_AES_CBC_HMAC: &'static str = "2";
+    // The parameters aren't authenticated, bound them to what Bitwarden allows
+    // to not hang on a crafted file
+    const MAX_PBKDF2_ITERATIONS: u32 = 2_000_000;
+    // In MiB
+    const MAX_ARGON2_MEMORY: u32 = 1024;
+    const MAX_ARGON2_ITERATIONS: u32 = 10;
+    const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+    fn master_key(&self, password: &str) -> Result<Zeroizing<[u8; Self::KEY_SIZE]>> {
+        let mut key = Zeroizing::new([0u8; Self::KEY_SIZE]);
+        match self.kdf_type {
+            Self::KDF_PBKDF2 => {
+                anyhow::ensure!(
+                    self.kdf_iterations <= Self::MAX_PBKDF2_ITERATIONS,
+                    "Too many key derivation iterations: {}",
+                    self.kdf_iterations
+                );
+                crypto::pbkdf2(
+                    ring::pbkdf2::PBKDF2_HMAC_SHA256,
+                    password.as_bytes(),
+                    self.salt.as_bytes(),
+                    self.kdf_iterations,
+                    &mut *key,
+                )?
+            }
+            Self::KDF_ARGON2ID => {
+                let memory = self.kdf_memory.context("Missing the Argon2 memory")?;
+                let parallelism = self
+                    .kdf_parallelism
+                    .context("Missing the Argon2 parallelism")?;
+                anyhow::ensure!(
+                    memory <= Self::MAX_ARGON2_MEMORY
+                        && self.kdf_iterations <= Self::MAX_ARGON2_ITERATIONS
+                        && parallelism <= Self::MAX_ARGON2_PARALLELISM,
+                    "Unreasonable Argon2 parameters"
+                );
+                let salt = digest::digest(&digest::SHA256, self.salt.as_bytes());
+                let config = argon2::Config {
+                    variant: argon2::Variant::Argon2id,
+                    // In MiB in the export, in KiB for Argon2
+                    mem_cost: memory * 1024,
+                    time_cost: self.kdf_iterations,
+                    lanes: parallelism,
+                    hash_length: Self::KEY_SIZE as u32,
+                    ..argon2::Config::default()
+                };
+                let mut hash = argon2::hash_raw(password.as_bytes(), salt.as_ref(), &config)?;
+                key.copy_from_slice(&hash);
+                hash.zeroize();
+            }
+            kdf_type => anyhow::bail!("Unsupported key derivation function {kdf_type}"),
         }
+        Ok(key)
     }
 
-    fn secret(&self) -> String {
-        self.login
-            .as_ref()
-            .unwrap()
-            .totp
-            .as_ref()
-            .unwrap()
-            .to_owned()
+    /// Stretches the master key into an encryption and a MAC key, using
+    /// HKDF-Expand with SHA-256.
+    fn stretch(master_key: &[u8]) -> (Zeroizing<Vec<u8>>, hmac::Key) {
+        let prk = hmac::Key::new(hmac::HMAC_SHA256, master_key);
+        // A single block of output is enough for 32 bytes keys
+        let expand = |info: &[u8]| {
+            let mut context = hmac::Context::with_key(&prk);
+            context.update(info);
+            context.update(&[1]);
+            Zeroizing::new(context.sign().as_ref().to_vec())
+        };
+        let enc_key = expand(b"enc");
+        let mac_key = expand(b"mac");
+        (enc_key, hmac::Key::new(hmac::HMAC_SHA256, &mac_key))
     }
 
-    fn period(&self) -> Option<u32> {
-        self.period
-    }
+    fn decrypt(&self, password: &str) -> Result<Vec<u8>> {
+        let (enc_type, data) = self
+            .data
+            .split_once('.')
+            .context("Invalid encrypted data")?;
+        anyhow::ensure!(
+            enc_type == Self::ENC_TYPE_AES_CBC_HMAC,
+            "Unsupported encryption type {enc_type}"
+        );
+        let parts = data
+            .split('|')
+            .map(|part| BASE64.decode(part.as_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let [iv, ciphertext, mac] = parts.as_slice() else {
+            anyhow::bail!("Invalid encrypted data");
+        };
 
-    fn method(&self) -> Method {
-        self.method
-    }
+        let master_key = self.master_key(password)?;
+        let (enc_key, mac_key) = Self::stretch(&*master_key);
 
-    fn algorithm(&self) -> Algorithm {
-        self.algorithm
-    }
+        hmac::verify(&mac_key, &[iv.as_slice(), ciphertext].concat(), mac)
+            .map_err(|_| anyhow::anyhow!("Cannot decrypt the export, is the password correct?"))?;
 
-    fn digits(&self) -> Option<u32> {
-        self.digits
+        crypto::aes_cbc_decrypt(&enc_key, iv, ciphertext)
     }
+}
 
-    fn counter(&self) -> Option<u32> {
-        self.counter
+impl Bitwarden {
+    fn group_names(groups: &[Group]) -> HashMap<&str, &str> {
+        groups
+            .iter()
+            .map(|group| (group.id.as_str(), group.name.as_str()))
+            .collect()
     }
-}
 
-impl BitwardenItem {
-    fn overwrite_with(&mut self, uri: OTPUri) {
-        self.issuer = Some(uri.issuer());
+    fn parse_totp(totp: &str, item: &BitwardenItem) -> Result<OTPUri> {
+        if totp.starts_with("otpauth://") {
+            return totp.parse::<OTPUri>();
+        }
 
-        if let Some(ref mut login) = self.login {
-            login.totp = Some(uri.secret());
-            login.username = Some(uri.account());
+        let mut uri = if totp.starts_with("steam://") {
+            totp.parse::<OTPUri>()?
         } else {
-            self.login = Some(BitwardenDetails {
-                username: Some(uri.account()),
-                totp: Some(uri.secret()),
-            });
-        }
+            // A bare secret, using the default parameters
+            OTPUri {
+                algorithm: Algorithm::default(),
+                label: String::new(),
+                secret: totp.replace(' ', "").to_uppercase(),
+                issuer: String::new(),
+                method: Method::TOTP,
+                digits: None,
+                period: None,
+                counter: None,
+                pin: None,
+                epoch: None,
+                image: None,
+                color: None,
+                tags: Vec::new(),
+                extra: Vec::new(),
+            }
+        };
+        // Neither of them has a label, use the item's details instead
+        uri.issuer = item
+            .name
+            .clone()
+            .unwrap_or_else(|| gettext("Unknown issuer"));
+        uri.label = item
+            .login
+            .as_ref()
+            .and_then(|login| login.username.clone())
+            .unwrap_or_else(|| gettext("Unknown account"));
+        Ok(uri)
+    }
+
+    fn into_items(self) -> Vec<OTPUri> {
+        let folders = Self::group_names(&self.folders);
+        let collections = Self::group_names(&self.collections);
+
+        self.items
+            .iter()
+            .filter_map(|item| {
+                let totp = item.login.as_ref()?.totp.as_deref()?.trim();
+                if totp.is_empty() {
+                    return None;
+                }
+                let mut uri = Self::parse_totp(totp, item)
+                    .map_err(|err| {
+                        tracing::warn!("Skipping {:?}, invalid TOTP: {err}", item.name);
+                    })
+                    .ok()?;
 
-        self.algorithm = uri.algorithm();
-        self.method = uri.method();
-        self.digits = uri.digits();
-        self.period = uri.period();
-        self.counter = uri.counter();
+                // Folders and collections are kept as tags
+                let folder = item.folder_id.as_deref().and_then(|id| folders.get(id));
+                let item_collections = item
+                    .collection_ids
+                    .iter()
+                    .flatten()
+                    .filter_map(|id| collections.get(id.as_str()));
+                for name in folder.into_iter().chain(item_collections) {
+                    if !uri.tags.iter().any(|tag| tag == name) {
+                        uri.tags.push(name.to_string());
+                    }
+                }
+                Some(uri)
+            })
+            .collect()
     }
 }
 
 impl Restorable for Bitwarden {
-    const ENCRYPTABLE: bool = false;
+    const ENCRYPTABLE: bool = true;
     const SCANNABLE: bool = false;
     const IDENTIFIER: &'static str = "bitwarden";
-    type Item = BitwardenItem;
+    type Item = OTPUri;
 
     fn title() -> String {
         // Translators: This is for restoring a backup from Bitwarden.
@@ -121,39 +269,37 @@ impl Restorable for Bitwarden {
     }
 
     fn subtitle() -> String {
-        gettext("From a plain-text JSON file")
+        gettext("From a plain-text or password protected JSON file")
     }
 
-    fn restore_from_data(from: &[u8], _key: Option<&str>) -> Result<Vec<Self::Item>> {
-        let bitwarden_root: Bitwarden = serde_json::de::from_slice(from)?;
-
-        let mut items = Vec::new();
-
-        for mut item in bitwarden_root.items {
-            if let Some(ref mut login) = item.login {
-                if let Some(ref totp) = login.totp {
-                    if totp.starts_with("steam://") {
-                        login.totp = Some(totp.trim_start_matches("steam://").to_owned());
-                        item.algorithm = Algorithm::SHA1;
-                        item.method = Method::Steam;
-                        item.period = Some(OTP::STEAM_DEFAULT_PERIOD);
-                        item.digits = Some(OTP::STEAM_DEFAULT_DIGITS);
-                        items.push(item);
-                    } else if let Ok(uri) = totp.parse::<OTPUri>() {
-                        item.overwrite_with(uri);
-                        items.push(item);
-                    }
-                }
-            }
-        }
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let header: Header = serde_json::de::from_slice(from)?;
 
-        Ok(items)
+        let bitwarden_root: Bitwarden = if header.password_protected {
+            let password = key
+                .filter(|key| !key.is_empty())
+                .context("The export is password protected, a password is needed")?;
+            let export: EncryptedExport = serde_json::de::from_slice(from)?;
+            let mut data = export.decrypt(password)?;
+            let root = serde_json::de::from_slice(&data);
+            data.zeroize();
+            root?
+        } else if header.encrypted {
+            anyhow::bail!(
+                "Exports encrypted with the account key are not supported, use a password protected one instead"
+            );
+        } else {
+            serde_json::de::from_slice(from)?
+        };
+
+        Ok(bitwarden_root.into_items())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use super::{super::RestorableItem, *};
+
     #[test]
     fn parse() {
         let data = std::fs::read_to_string("./src/backup/tests/bitwarden.json").unwrap();
@@ -195,4 +341,53 @@ mod tests {
         assert_eq!(items[3].digits(), Some(5));
         assert_eq!(items[3].counter(), None);
     }
+
+    #[test]
+    fn parse_encrypted() {
+        let data = std::fs::read("./src/backup/tests/bitwarden_encrypted.json").unwrap();
+        let items = Bitwarden::restore_from_data(&data, Some("BitwardenTest123")).unwrap();
+
+        // The item without a TOTP is skipped
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].account(), "gordon@example.com");
+        assert_eq!(items[0].issuer(), "Black Mesa");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[0].period(), None);
+        assert_eq!(items[0].otpauth_params(), Some("tags=Work".to_owned()));
+
+        assert_eq!(items[1].account(), "alyx");
+        assert_eq!(items[1].issuer(), "GitHub");
+        assert_eq!(items[1].secret(), "5OM4WOOGPLQEF6UGN3CPEOOLWU");
+        assert_eq!(items[1].digits(), Some(8));
+        assert_eq!(items[1].otpauth_params(), None);
+
+        assert!(Bitwarden::restore_from_data(&data, Some("wrong password")).is_err());
+        assert!(Bitwarden::restore_from_data(&data, None).is_err());
+        let tampered = String::from_utf8(data.clone()).unwrap().replace(
+            "\"kdfIterations\": 100000",
+            &format!("\"kdfIterations\": {}", u32::MAX),
+        );
+        assert!(
+            Bitwarden::restore_from_data(tampered.as_bytes(), Some("BitwardenTest123")).is_err()
+        );
+
+        let data = std::fs::read("./src/backup/tests/bitwarden_encrypted_argon2.json").unwrap();
+        let items = Bitwarden::restore_from_data(&data, Some("BitwardenTest123")).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+
+        let data = String::from_utf8(data).unwrap();
+        for (param, value) in [
+            ("\"kdfMemory\": 16", 4_000_000),
+            ("\"kdfIterations\": 3", 1_000_000),
+            ("\"kdfParallelism\": 4", u32::MAX),
+        ] {
+            let (name, _) = param.split_once(':').unwrap();
+            let data = data.replace(param, &format!("{name}: {value}"));
+            assert!(
+                Bitwarden::restore_from_data(data.as_bytes(), Some("BitwardenTest123")).is_err()
+            );
+        }
+    }
 }
@@ -0,0 +1,171 @@
+//! Primitives shared by the encrypted backup formats.
+
+use std::num::NonZeroU32;
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    aes::{cipher::generic_array::GenericArray, Aes256},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use anyhow::{Context, Result};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut};
+use chacha20::{
+    cipher::{consts::U10, KeyIvInit, StreamCipher},
+    hchacha, ChaCha20,
+};
+use chacha20poly1305::XChaCha20Poly1305;
+use poly1305::Poly1305;
+
+const CHACHA_BLOCK_SIZE: usize = 64;
+const POLY1305_TAG_SIZE: usize = 16;
+// The tag of the last message of a secretstream
+const SECRETSTREAM_TAG_FINAL: u8 = 3;
+
+/// Derives `key.len()` bytes from a password with PBKDF2.
+pub fn pbkdf2(
+    algorithm: ring::pbkdf2::Algorithm,
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    key: &mut [u8],
+) -> Result<()> {
+    let iterations = NonZeroU32::new(iterations).context("Invalid number of iterations")?;
+    ring::pbkdf2::derive(algorithm, iterations, salt, password, key);
+    Ok(())
+}
+
+/// Encrypts data with AES-256 in GCM mode, the tag is appended to the
+/// ciphertext.
+pub fn aes_gcm_encrypt(key: &[u8], nonce: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| anyhow::anyhow!("Invalid key size"))?;
+    cipher
+        .encrypt(Nonce::from_slice(nonce), data)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the data"))
+}
+
+/// Decrypts data encrypted with AES-256 in GCM mode, followed by its tag.
+pub fn aes_gcm_decrypt(key: &[u8], nonce: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| anyhow::anyhow!("Invalid key size"))?;
+    // A wrong key fails the authentication
+    cipher
+        .decrypt(Nonce::from_slice(nonce), data)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt the data, the key is probably wrong"))
+}
+
+/// Decrypts data encrypted with AES-256 in GCM mode, authenticating `aad`
+/// along the ciphertext.
+pub fn aes_gcm_decrypt_with_aad(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| anyhow::anyhow!("Invalid key size"))?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: data, aad })
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt the data, the key is probably wrong"))
+}
+
+/// Encrypts data with XChaCha20-Poly1305, authenticating `aad` along the
+/// ciphertext. The tag is appended to the ciphertext.
+pub fn xchacha20_poly1305_encrypt(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher =
+        XChaCha20Poly1305::new_from_slice(key).map_err(|_| anyhow::anyhow!("Invalid key size"))?;
+    anyhow::ensure!(nonce.len() == 24, "Invalid nonce size");
+    cipher
+        .encrypt(GenericArray::from_slice(nonce), Payload { msg: data, aad })
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt the data"))
+}
+
+/// Decrypts data encrypted with XChaCha20-Poly1305, followed by its tag.
+pub fn xchacha20_poly1305_decrypt(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher =
+        XChaCha20Poly1305::new_from_slice(key).map_err(|_| anyhow::anyhow!("Invalid key size"))?;
+    anyhow::ensure!(nonce.len() == 24, "Invalid nonce size");
+    cipher
+        .decrypt(GenericArray::from_slice(nonce), Payload { msg: data, aad })
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt the data, the key is probably wrong"))
+}
+
+/// Decrypts data encrypted with AES-256 in CBC mode and removes its PKCS#7
+/// padding.
+pub fn aes_cbc_decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let cipher = cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+        .map_err(|_| anyhow::anyhow!("Invalid key or IV size"))?;
+    // A wrong key ends up with garbage instead of a valid padding
+    cipher
+        .decrypt_padded_vec_mut::<Pkcs7>(data)
+        .map_err(|_| anyhow::anyhow!("Invalid padding, the key is probably wrong"))
+}
+
+/// Decrypts a stream made of a single message with libsodium's
+/// `crypto_secretstream_xchacha20poly1305`, as used by the apps relying on
+/// libsodium.
+pub fn secretstream_decrypt(key: &[u8], header: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    anyhow::ensure!(key.len() == 32, "Invalid key size");
+    anyhow::ensure!(header.len() == 24, "Invalid header size");
+    anyhow::ensure!(
+        data.len() > 1 + POLY1305_TAG_SIZE,
+        "Invalid ciphertext size"
+    );
+
+    let (key_nonce, stream_nonce) = header.split_at(16);
+    let subkey = hchacha::<U10>(
+        GenericArray::from_slice(key),
+        GenericArray::from_slice(key_nonce),
+    );
+    // The first message uses a counter of 1, followed by the rest of the header
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&1u32.to_le_bytes());
+    nonce[4..].copy_from_slice(stream_nonce);
+    let mut cipher = ChaCha20::new(&subkey, &nonce.into());
+
+    // The first block is the MAC key, the second one encrypts the tag
+    let mut mac_key = [0u8; CHACHA_BLOCK_SIZE];
+    cipher.apply_keystream(&mut mac_key);
+    let (encrypted_tag, data) = data.split_first().unwrap();
+    let (ciphertext, expected_mac) = data.split_at(data.len() - POLY1305_TAG_SIZE);
+    let mut block = [0u8; CHACHA_BLOCK_SIZE];
+    block[0] = *encrypted_tag;
+    cipher.apply_keystream(&mut block);
+    let tag = block[0];
+    block[0] = *encrypted_tag;
+
+    // libsodium pads the ciphertext with `len % 16` bytes, instead of padding
+    // it up to 16 bytes like the other constructions
+    let mut authenticated = Vec::with_capacity(block.len() + 2 * ciphertext.len() + 16);
+    authenticated.extend_from_slice(&block);
+    authenticated.extend_from_slice(ciphertext);
+    authenticated.resize(authenticated.len() + ciphertext.len() % 16, 0);
+    authenticated.extend_from_slice(&0u64.to_le_bytes());
+    authenticated.extend_from_slice(&((block.len() + ciphertext.len()) as u64).to_le_bytes());
+    let mac = <Poly1305 as KeyInit>::new(GenericArray::from_slice(&mac_key[..32]))
+        .compute_unpadded(&authenticated);
+    // Compare without leaking where the first difference is
+    let difference = mac
+        .iter()
+        .zip(expected_mac)
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    anyhow::ensure!(
+        difference == 0,
+        "Failed to decrypt the data, the key is probably wrong"
+    );
+    anyhow::ensure!(
+        tag == SECRETSTREAM_TAG_FINAL,
+        "Only streams made of a single message are supported"
+    );
+
+    let mut plaintext = ciphertext.to_vec();
+    cipher.apply_keystream(&mut plaintext);
+    Ok(plaintext)
+}
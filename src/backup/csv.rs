@@ -0,0 +1,500 @@
+//! CSV Import/Export Module
+//!
+//! Spreadsheets come with any set of columns, so the first row is read as a
+//! header and the role of each column, like the issuer or the secret, is
+//! guessed from its name. The GUI lets the user override the guess before
+//! restoring.
+//!
+//! Exports always use the columns of [`CsvColumn::EXPORTED`].
+
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use gettextrs::gettext;
+use gtk::prelude::*;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{Backupable, Restorable};
+use crate::models::{Account, Algorithm, Method, OTPUri, Provider, ProvidersModel};
+
+pub struct Csv;
+
+/// What a column of a CSV file holds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CsvColumn {
+    #[default]
+    Ignored,
+    Issuer,
+    Account,
+    Secret,
+    Method,
+    Algorithm,
+    Digits,
+    Period,
+    Counter,
+    Pin,
+    // A whole otpauth URI, taking over the other columns
+    Uri,
+}
+
+impl CsvColumn {
+    pub const ALL: [Self; 11] = [
+        Self::Ignored,
+        Self::Issuer,
+        Self::Account,
+        Self::Secret,
+        Self::Method,
+        Self::Algorithm,
+        Self::Digits,
+        Self::Period,
+        Self::Counter,
+        Self::Pin,
+        Self::Uri,
+    ];
+
+    pub const EXPORTED: [Self; 9] = [
+        Self::Issuer,
+        Self::Account,
+        Self::Secret,
+        Self::Method,
+        Self::Algorithm,
+        Self::Digits,
+        Self::Period,
+        Self::Counter,
+        Self::Pin,
+    ];
+
+    /// The name of the column when exporting.
+    fn header(self) -> &'static str {
+        match self {
+            Self::Ignored => "",
+            Self::Issuer => "issuer",
+            Self::Account => "account",
+            Self::Secret => "secret",
+            Self::Method => "type",
+            Self::Algorithm => "algorithm",
+            Self::Digits => "digits",
+            Self::Period => "period",
+            Self::Counter => "counter",
+            Self::Pin => "pin",
+            Self::Uri => "uri",
+        }
+    }
+
+    pub fn to_locale_string(self) -> String {
+        match self {
+            Self::Ignored => gettext("Ignored"),
+            Self::Issuer => gettext("Provider"),
+            Self::Account => gettext("Account"),
+            Self::Secret => gettext("Secret"),
+            Self::Method => gettext("Computing Method"),
+            Self::Algorithm => gettext("Algorithm"),
+            Self::Digits => gettext("Digits"),
+            Self::Period => gettext("Period"),
+            Self::Counter => gettext("Counter"),
+            Self::Pin => gettext("PIN"),
+            Self::Uri => gettext("otpauth URI"),
+        }
+    }
+
+    /// Guesses the role of a column from its header.
+    fn guess(header: &str) -> Self {
+        let header = header
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_lowercase();
+        match header.as_str() {
+            "issuer" | "provider" | "service" | "name" | "title" | "site" | "website" => {
+                Self::Issuer
+            }
+            "account" | "accountname" | "username" | "user" | "login" | "email" | "label" => {
+                Self::Account
+            }
+            "secret" | "secretkey" | "key" | "seed" | "totpsecret" | "otpsecret" => Self::Secret,
+            "type" | "method" | "kind" | "otptype" => Self::Method,
+            "algorithm" | "algo" | "hash" => Self::Algorithm,
+            "digits" | "length" => Self::Digits,
+            "period" | "interval" | "step" | "timestep" => Self::Period,
+            "counter" => Self::Counter,
+            "pin" => Self::Pin,
+            "uri" | "url" | "otpauth" | "otpauthuri" | "otpurl" => Self::Uri,
+            _ => Self::Ignored,
+        }
+    }
+}
+
+/// Splits CSV `content` into records, following RFC 4180.
+fn parse_records(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.next_if_eq(&'"').is_some() {
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.is_empty() => in_quotes = true,
+            ',' if !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes && chars.peek() == Some(&'\n') => (),
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    anyhow::ensure!(!in_quotes, "Unterminated quoted field");
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    // Spreadsheets tend to leave empty rows around
+    records.retain(|record| record.iter().any(|field| !field.trim().is_empty()));
+    Ok(records)
+}
+
+// The first characters of the fields spreadsheets evaluate as formulas
+const FORMULA_PREFIXES: [char; 4] = ['=', '+', '-', '@'];
+
+/// Quotes a field if it contains a separator, a quote or a line break.
+///
+/// Fields starting like a formula are prefixed with `'`, so a spreadsheet
+/// shows them as text instead of evaluating them.
+fn escape(field: &str) -> String {
+    let field = if field.starts_with(FORMULA_PREFIXES) {
+        format!("'{field}")
+    } else {
+        field.to_owned()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Removes the `'` [`escape`] adds in front of formulas.
+fn unescape_formula(field: &str) -> &str {
+    field
+        .strip_prefix('\'')
+        .filter(|field| field.starts_with(FORMULA_PREFIXES))
+        .unwrap_or(field)
+}
+
+impl Csv {
+    /// The headers of the file, along with the guessed role of each column.
+    pub fn guess_columns(from: &[u8]) -> Result<Vec<(String, CsvColumn)>> {
+        let content = Zeroizing::new(std::str::from_utf8(from)?.to_owned());
+        let mut records = parse_records(&content)?;
+        anyhow::ensure!(!records.is_empty(), "The file is empty");
+        let headers = records.remove(0);
+
+        let mut columns: Vec<(String, CsvColumn)> = Vec::new();
+        for (index, header) in headers.into_iter().enumerate() {
+            // Exports of other apps sometimes name the URI column `totp`
+            let is_uri = records.first().is_some_and(|record| {
+                record
+                    .get(index)
+                    .is_some_and(|field| field.trim_start().starts_with("otpauth://"))
+            });
+            let column = if is_uri {
+                CsvColumn::Uri
+            } else {
+                CsvColumn::guess(&header)
+            };
+            // A role can only be taken once
+            let column = if columns.iter().any(|(_, taken)| *taken == column) {
+                CsvColumn::Ignored
+            } else {
+                column
+            };
+            columns.push((header, column));
+        }
+        records.zeroize();
+        Ok(columns)
+    }
+
+    /// Restores the rows of `from` with the role of each column given by
+    /// `columns`. Rows that cannot be restored are skipped.
+    pub fn restore_with_columns(from: &[u8], columns: &[CsvColumn]) -> Result<Vec<OTPUri>> {
+        anyhow::ensure!(
+            columns.contains(&CsvColumn::Secret) || columns.contains(&CsvColumn::Uri),
+            "No column holds the secret"
+        );
+        let content = Zeroizing::new(std::str::from_utf8(from)?.to_owned());
+        let mut records = parse_records(&content)?;
+
+        let items = records
+            .iter()
+            .enumerate()
+            // The headers
+            .skip(1)
+            .filter_map(
+                |(index, record)| match Self::parse_record(record, columns) {
+                    Ok(item) => Some(item),
+                    Err(err) => {
+                        tracing::warn!("Skipping the row {}: {err}", index + 1);
+                        None
+                    }
+                },
+            )
+            .collect();
+        records.zeroize();
+        Ok(items)
+    }
+
+    fn parse_record(record: &[String], columns: &[CsvColumn]) -> Result<OTPUri> {
+        let field = |column: CsvColumn| {
+            columns
+                .iter()
+                .position(|c| *c == column)
+                .and_then(|index| record.get(index))
+                .map(|value| unescape_formula(value.trim()))
+                .filter(|value| !value.is_empty())
+        };
+        let number = |column: CsvColumn| -> Result<Option<u32>> {
+            field(column)
+                .map(|value| {
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid {column:?} {value}"))
+                })
+                .transpose()
+        };
+
+        if let Some(uri) = field(CsvColumn::Uri) {
+            return OTPUri::from_str(uri);
+        }
+
+        let method = field(CsvColumn::Method)
+            .map(Method::from_str)
+            .transpose()?
+            .unwrap_or_default();
+        let algorithm = field(CsvColumn::Algorithm)
+            // Spreadsheets often spell it `SHA-1`
+            .map(|value| Algorithm::from_str(&value.replace('-', "")))
+            .transpose()?
+            .unwrap_or_default();
+        let secret = field(CsvColumn::Secret)
+            .context("Missing the secret")?
+            .replace(' ', "")
+            .to_uppercase();
+
+        Ok(OTPUri {
+            algorithm,
+            label: field(CsvColumn::Account).unwrap_or_default().to_owned(),
+            secret,
+            issuer: field(CsvColumn::Issuer).unwrap_or_default().to_owned(),
+            method,
+            digits: number(CsvColumn::Digits)?,
+            period: number(CsvColumn::Period)?.filter(|_| method.is_time_based()),
+            counter: number(CsvColumn::Counter)?.filter(|_| method.is_event_based()),
+            pin: field(CsvColumn::Pin).map(ToOwned::to_owned),
+            epoch: None,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        })
+    }
+
+    fn record(account: &Account, provider: &Provider) -> String {
+        let method = provider.method();
+        CsvColumn::EXPORTED
+            .iter()
+            .map(|column| match column {
+                CsvColumn::Issuer => escape(&provider.name()),
+                CsvColumn::Account => escape(&account.name()),
+                CsvColumn::Secret => account.otp().secret(),
+                CsvColumn::Method => method.to_string(),
+                CsvColumn::Algorithm => provider.algorithm().to_string(),
+                CsvColumn::Digits => provider.digits().to_string(),
+                CsvColumn::Period if method.is_time_based() => provider.period().to_string(),
+                CsvColumn::Counter if method.is_event_based() => account.counter().to_string(),
+                CsvColumn::Pin => escape(account.otp().pin().unwrap_or_default()),
+                _ => String::new(),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl Backupable for Csv {
+    const ENCRYPTABLE: bool = false;
+    const IDENTIFIER: &'static str = "csv";
+
+    fn title() -> String {
+        // Translators: This is for making a backup into a spreadsheet file.
+        gettext("_CSV")
+    }
+
+    fn subtitle() -> String {
+        gettext("Into a plain-text CSV file, one account per row")
+    }
+
+    fn backup(model: &ProvidersModel, _key: Option<&str>) -> Result<Vec<u8>> {
+        let headers = CsvColumn::EXPORTED
+            .iter()
+            .map(|column| column.header())
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut rows = vec![headers];
+
+        for i in 0..model.n_items() {
+            let provider = model.item(i).and_downcast::<Provider>().unwrap();
+            let accounts = provider.accounts_model();
+
+            for j in 0..accounts.n_items() {
+                let account = accounts.item(j).and_downcast::<Account>().unwrap();
+                rows.push(Self::record(&account, &provider));
+            }
+        }
+
+        let content = rows.join("\r\n") + "\r\n";
+        rows.zeroize();
+        Ok(content.into_bytes())
+    }
+}
+
+impl Restorable for Csv {
+    const ENCRYPTABLE: bool = false;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "csv";
+    type Item = OTPUri;
+
+    fn title() -> String {
+        // Translators: This is for restoring the accounts listed in a spreadsheet file.
+        gettext("C_SV")
+    }
+
+    fn subtitle() -> String {
+        gettext("From a plain-text CSV file, with a header row")
+    }
+
+    fn restore_from_data(from: &[u8], _key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let columns = Self::guess_columns(from)?
+            .into_iter()
+            .map(|(_, column)| column)
+            .collect::<Vec<_>>();
+        Self::restore_with_columns(from, &columns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+
+    #[test]
+    fn guess_columns() {
+        let data = std::fs::read("./src/backup/tests/csv.csv").unwrap();
+        let columns = Csv::guess_columns(&data).unwrap();
+        assert_eq!(
+            columns
+                .iter()
+                .map(|(header, column)| (header.as_str(), *column))
+                .collect::<Vec<_>>(),
+            [
+                ("Service", CsvColumn::Issuer),
+                ("Username", CsvColumn::Account),
+                ("Secret Key", CsvColumn::Secret),
+                ("Type", CsvColumn::Method),
+                ("Algorithm", CsvColumn::Algorithm),
+                ("Digits", CsvColumn::Digits),
+                ("Interval", CsvColumn::Period),
+                ("Counter", CsvColumn::Counter),
+                ("Notes", CsvColumn::Ignored),
+                ("TOTP", CsvColumn::Uri),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let data = std::fs::read("./src/backup/tests/csv.csv").unwrap();
+        let items = Csv::restore_from_data(&data, None).unwrap();
+        // The row without a secret is skipped
+        assert_eq!(items.len(), 4);
+
+        // The URI takes over the other columns
+        assert_eq!(items[0].issuer(), "GitHub");
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[0].secret(), "5OM4WOOGPLQEF6UGN3CPEOOLWU");
+        assert_eq!(items[0].digits(), Some(8));
+
+        assert_eq!(items[1].issuer(), "Black Mesa, Inc.");
+        assert_eq!(items[1].account(), "gordon");
+        assert_eq!(items[1].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[1].method(), Method::TOTP);
+        assert_eq!(items[1].algorithm(), Algorithm::SHA1);
+        assert_eq!(items[1].digits(), Some(6));
+        assert_eq!(items[1].period(), Some(30));
+
+        assert_eq!(items[2].issuer(), "Aperture \"Science\"");
+        assert_eq!(items[2].account(), "chell");
+        assert_eq!(items[2].method(), Method::HOTP);
+        assert_eq!(items[2].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[2].counter(), Some(4));
+        assert_eq!(items[2].period(), None);
+
+        assert_eq!(items[3].issuer(), "Steam");
+        assert_eq!(items[3].account(), "barney");
+        assert_eq!(items[3].method(), Method::Steam);
+    }
+
+    #[test]
+    fn override_columns() {
+        let data = std::fs::read("./src/backup/tests/csv.csv").unwrap();
+        let mut columns = Csv::guess_columns(&data)
+            .unwrap()
+            .into_iter()
+            .map(|(_, column)| column)
+            .collect::<Vec<_>>();
+        // Use the notes as the account name, and not the URIs
+        columns[1] = CsvColumn::Ignored;
+        columns[8] = CsvColumn::Account;
+        columns[9] = CsvColumn::Ignored;
+
+        let items = Csv::restore_with_columns(&data, &columns).unwrap();
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].issuer(), "GitHub");
+        assert_eq!(items[0].account(), "Personal, main");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[1].account(), "Line one\nline two");
+
+        // Without any secret
+        columns[2] = CsvColumn::Ignored;
+        assert!(Csv::restore_with_columns(&data, &columns).is_err());
+    }
+
+    #[test]
+    fn escape_fields() {
+        assert_eq!(escape("GitHub"), "GitHub");
+        assert_eq!(escape("Black Mesa, Inc."), "\"Black Mesa, Inc.\"");
+        assert_eq!(
+            escape("Aperture \"Science\""),
+            "\"Aperture \"\"Science\"\"\""
+        );
+
+        assert_eq!(escape("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(escape("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(escape("-1+1"), "'-1+1");
+        assert_eq!(unescape_formula("'-1+1"), "-1+1");
+        assert_eq!(unescape_formula("'quoted"), "'quoted");
+
+        let row = ["Black Mesa, Inc.", "Aperture \"Science\"", "a\nb"]
+            .map(escape)
+            .join(",");
+        assert_eq!(
+            parse_records(&row).unwrap(),
+            [["Black Mesa, Inc.", "Aperture \"Science\"", "a\nb"]]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Ente Auth Import Module
+//!
+//! The plain-text export is a list of otpauth URIs, carrying the app's own
+//! metadata as JSON in a `codeDisplay` parameter. The encrypted export wraps
+//! it into a libsodium secretstream, keyed with Argon2id.
+//!
+//! Tags are kept, trashed codes are skipped and the rest of the metadata, like
+//! whether a code is pinned, is kept along the account as is.
+
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use data_encoding::BASE64;
+use gettextrs::gettext;
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{crypto, Restorable, SkippedItem};
+use crate::models::{i18n, OTPUri};
+
+pub struct Ente;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptedExport {
+    version: u32,
+    kdf_params: KdfParams,
+    encrypted_data: String,
+    encryption_nonce: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KdfParams {
+    // In bytes
+    mem_limit: u32,
+    ops_limit: u32,
+    salt: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeDisplay {
+    #[serde(default)]
+    trashed: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl EncryptedExport {
+    const KEY_SIZE: usize = 32;
+    // The parameters aren't authenticated, don't let a crafted export
+    // exhaust the memory or hang the app. libsodium's sensitive limit is 1 GiB.
+    const MAX_MEM_LIMIT: u32 = 1_073_741_824;
+    const MAX_OPS_LIMIT: u32 = 64;
+
+    fn decrypt(&self, password: &str) -> Result<Vec<u8>> {
+        anyhow::ensure!(
+            self.version == 1,
+            "Unsupported export version {}",
+            self.version
+        );
+        anyhow::ensure!(
+            self.kdf_params.mem_limit <= Self::MAX_MEM_LIMIT
+                && self.kdf_params.ops_limit <= Self::MAX_OPS_LIMIT,
+            "Unreasonable key derivation parameters"
+        );
+        let salt = BASE64.decode(self.kdf_params.salt.as_bytes())?;
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            mem_cost: self.kdf_params.mem_limit / 1024,
+            time_cost: self.kdf_params.ops_limit,
+            lanes: 1,
+            hash_length: Self::KEY_SIZE as u32,
+            ..argon2::Config::default()
+        };
+        let key = Zeroizing::new(argon2::hash_raw(password.as_bytes(), &salt, &config)?);
+
+        crypto::secretstream_decrypt(
+            &key,
+            &BASE64.decode(self.encryption_nonce.as_bytes())?,
+            &BASE64.decode(self.encrypted_data.as_bytes())?,
+        )
+        .context("Cannot decrypt the export, is the password correct?")
+    }
+}
+
+impl Ente {
+    fn parse_line(line: &str) -> Result<Option<OTPUri>> {
+        let mut uri = OTPUri::from_str(line)?;
+
+        let code_display = uri
+            .extra
+            .iter()
+            .find(|(key, _)| key == "codeDisplay")
+            .map(|(_, value)| serde_json::from_str::<CodeDisplay>(value))
+            .transpose()?
+            .unwrap_or_default();
+        if code_display.trashed {
+            return Ok(None);
+        }
+        for tag in code_display.tags {
+            if !uri.tags.contains(&tag) {
+                uri.tags.push(tag);
+            }
+        }
+        Ok(Some(uri))
+    }
+}
+
+impl Restorable for Ente {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "ente";
+    type Item = OTPUri;
+
+    fn title() -> String {
+        // Translators: This is for restoring a backup from the Ente Auth app.
+        gettext("_Ente Auth")
+    }
+
+    fn subtitle() -> String {
+        gettext("From a plain-text or encrypted export")
+    }
+
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        Ok(Self::restore_from_data_with_skipped(from, key)?.0)
+    }
+
+    fn restore_from_data_with_skipped(
+        from: &[u8],
+        key: Option<&str>,
+    ) -> Result<(Vec<Self::Item>, Vec<SkippedItem>)> {
+        // The encrypted export is a JSON object, the plain-text one a list of URIs
+        let mut content = if from.trim_ascii_start().starts_with(b"{") {
+            let password = key
+                .filter(|key| !key.is_empty())
+                .context("The export is encrypted, a password is needed")?;
+            let export: EncryptedExport = serde_json::de::from_slice(from)?;
+            export.decrypt(password)?
+        } else {
+            from.to_vec()
+        };
+
+        let items = std::str::from_utf8(&content)
+            .map_err(anyhow::Error::from)
+            .map(|content| {
+                let mut items = Vec::new();
+                let mut skipped = Vec::new();
+                for (index, line) in content.lines().map(str::trim).enumerate() {
+                    if line.is_empty() {
+                        continue;
+                    }
+                    match Self::parse_line(line) {
+                        Ok(item) => items.extend(item),
+                        Err(err) => {
+                            tracing::warn!("Skipping the line {}: {err}", index + 1);
+                            skipped.push(SkippedItem {
+                                name: i18n::i18n_f("Line {}", &[&(index + 1).to_string()]),
+                                reason: err.to_string(),
+                            });
+                        }
+                    }
+                }
+                (items, skipped)
+            });
+        content.zeroize();
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+    use crate::models::{Algorithm, Method};
+
+    #[test]
+    fn parse() {
+        let data = std::fs::read("./src/backup/tests/ente_plain.txt").unwrap();
+        let items = Ente::restore_from_data(&data, None).unwrap();
+
+        // The trashed code is skipped
+        assert_eq!(items.len(), 3);
+
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[0].issuer(), "GitHub");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[0].period(), Some(30));
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[0].algorithm(), Algorithm::SHA1);
+        assert_eq!(items[0].digits(), Some(6));
+        assert_eq!(items[0].tags, ["Work", "Dev"]);
+        assert!(items[0]
+            .otpauth_params()
+            .unwrap()
+            .contains("codeDisplay=%7B%22pinned%22%3Atrue"));
+
+        assert_eq!(items[1].account(), "barney");
+        assert_eq!(items[1].issuer(), "Black Mesa");
+        assert_eq!(items[1].method(), Method::HOTP);
+        assert_eq!(items[1].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[1].counter(), Some(12));
+        assert!(items[1].tags.is_empty());
+
+        assert_eq!(items[2].account(), "gordon");
+        assert_eq!(items[2].method(), Method::Steam);
+        assert_eq!(items[2].secret(), "JRZCL47CMXVOQMNPZR2F7J4RGI");
+
+        // A broken line doesn't prevent importing the others
+        let mut data = b"otpauth://unknown/broken?secret=JBSWY3DPEHPK3PXP\n".to_vec();
+        data.extend(std::fs::read("./src/backup/tests/ente_plain.txt").unwrap());
+        let (items, skipped) = Ente::restore_from_data_with_skipped(&data, None).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "Line 1");
+    }
+
+    #[test]
+    fn parse_encrypted() {
+        let data = std::fs::read("./src/backup/tests/ente_encrypted.txt").unwrap();
+        let items = Ente::restore_from_data(&data, Some("EnteTest123")).unwrap();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[0].issuer(), "GitHub");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[0].tags, ["Work", "Dev"]);
+        assert_eq!(items[1].counter(), Some(12));
+        assert_eq!(items[2].method(), Method::Steam);
+
+        assert!(Ente::restore_from_data(&data, Some("wrong password")).is_err());
+        assert!(Ente::restore_from_data(&data, None).is_err());
+
+        // Ask for more memory than the key derivation is allowed to use
+        let data = String::from_utf8(data)
+            .unwrap()
+            .replace("\"memLimit\": 8388608", "\"memLimit\": 4294967295");
+        assert!(Ente::restore_from_data(data.as_bytes(), Some("EnteTest123")).is_err());
+    }
+}
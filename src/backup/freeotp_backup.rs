@@ -0,0 +1,366 @@
+//! FreeOTP Encrypted Backup Import Module
+//!
+//! Since 2.0, FreeOTP backups are a Java serialized `HashMap<String, String>`
+//! whose values are JSON objects:
+//! - `masterKey`: the master key, encrypted with AES-GCM using a key derived
+//!   from the password with PBKDF2
+//! - `$uuid`: the secret of a token, encrypted with the master key
+//! - `$uuid-token`: the rest of the token, in the same format as the plain
+//!   JSON backups of FreeOTP+
+//!
+//! Keys are encrypted along their algorithm name, used as additional data.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use gettextrs::gettext;
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use super::{crypto, freeotp_json::FreeOTPItem, Restorable};
+
+pub struct FreeOTPBackup;
+
+#[derive(Deserialize)]
+struct MasterKey {
+    #[serde(rename = "mAlgorithm")]
+    algorithm: String,
+    #[serde(rename = "mEncryptedKey")]
+    encrypted_key: EncryptedKey,
+    #[serde(rename = "mIterations")]
+    iterations: u32,
+    #[serde(rename = "mSalt")]
+    salt: Vec<i8>,
+}
+
+#[derive(Deserialize)]
+struct EncryptedKey {
+    #[serde(rename = "mCipherText")]
+    ciphertext: Vec<i8>,
+    // The DER encoded GCM parameters, holding the IV
+    #[serde(rename = "mParameters")]
+    parameters: Vec<i8>,
+    // The algorithm of the key, like `AES` or `HmacSHA1`
+    #[serde(rename = "mToken")]
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct TokenKey {
+    // An `EncryptedKey` serialized as JSON, again
+    key: String,
+}
+
+impl MasterKey {
+    // Read from the unauthenticated backup, bounded to not hang on a crafted file
+    const MAX_ITERATIONS: u32 = 10_000_000;
+
+    /// Derives the key the master key is encrypted with from the password.
+    fn derive_key(&self, password: &str) -> Result<Zeroizing<[u8; 32]>> {
+        anyhow::ensure!(
+            self.algorithm == "PBKDF2withHmacSHA512",
+            "Unsupported key derivation {}",
+            self.algorithm
+        );
+        anyhow::ensure!(
+            self.iterations <= Self::MAX_ITERATIONS,
+            "Too many key derivation iterations: {}",
+            self.iterations
+        );
+        let mut derived_key = Zeroizing::new([0u8; 32]);
+        crypto::pbkdf2(
+            ring::pbkdf2::PBKDF2_HMAC_SHA512,
+            password.as_bytes(),
+            &to_bytes(&self.salt),
+            self.iterations,
+            derived_key.as_mut_slice(),
+        )?;
+        Ok(derived_key)
+    }
+}
+
+impl EncryptedKey {
+    fn decrypt(&self, key: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let parameters = to_bytes(&self.parameters);
+        // A SEQUENCE starting with the IV as an OCTET STRING, then the tag size
+        anyhow::ensure!(
+            parameters.len() > 4 && parameters[0] == 0x30 && parameters[2] == 0x04,
+            "Invalid GCM parameters"
+        );
+        let iv = parameters
+            .get(4..4 + parameters[3] as usize)
+            .context("Invalid GCM parameters")?;
+        crypto::aes_gcm_decrypt_with_aad(
+            key,
+            iv,
+            self.token.as_bytes(),
+            &to_bytes(&self.ciphertext),
+        )
+        .map(Zeroizing::new)
+    }
+}
+
+fn to_bytes(bytes: &[i8]) -> Vec<u8> {
+    bytes.iter().map(|byte| *byte as u8).collect()
+}
+
+/// Reads the subset of the Java serialization protocol needed for a
+/// `HashMap<String, String>`.
+///
+/// See <https://docs.oracle.com/javase/8/docs/platform/serialization/spec/protocol.html>.
+struct ObjectStream<'a> {
+    data: &'a [u8],
+    position: usize,
+    // Objects can refer to the previous ones, only the strings are kept
+    handles: Vec<Option<String>>,
+}
+
+impl<'a> ObjectStream<'a> {
+    const MAGIC: u16 = 0xaced;
+    const VERSION: u16 = 5;
+    const TC_NULL: u8 = 0x70;
+    const TC_REFERENCE: u8 = 0x71;
+    const TC_CLASSDESC: u8 = 0x72;
+    const TC_OBJECT: u8 = 0x73;
+    const TC_STRING: u8 = 0x74;
+    const TC_BLOCKDATA: u8 = 0x77;
+    const TC_ENDBLOCKDATA: u8 = 0x78;
+    const TC_LONGSTRING: u8 = 0x7c;
+    const BASE_HANDLE: u32 = 0x7e0000;
+
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            handles: Vec::new(),
+        }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(size)
+            .context("Unexpected end of the backup")?;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .context("Unexpected end of the backup")?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<()> {
+        let found = self.u8()?;
+        anyhow::ensure!(found == tag, "Expected {tag:#x}, found {found:#x}");
+        Ok(())
+    }
+
+    fn utf(&mut self, size: usize) -> Result<String> {
+        // Java uses a modified UTF-8, which only differs for the NUL character
+        // and the supplementary characters
+        Ok(std::str::from_utf8(self.take(size)?)?.to_owned())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.u8()? {
+            Self::TC_STRING => {
+                let size = self.u16()? as usize;
+                let string = self.utf(size)?;
+                self.handles.push(Some(string.clone()));
+                Ok(string)
+            }
+            Self::TC_LONGSTRING => {
+                let size = u64::from_be_bytes(self.take(8)?.try_into()?).try_into()?;
+                let string = self.utf(size)?;
+                self.handles.push(Some(string.clone()));
+                Ok(string)
+            }
+            Self::TC_REFERENCE => {
+                let handle = self.u32()?.wrapping_sub(Self::BASE_HANDLE) as usize;
+                self.handles
+                    .get(handle)
+                    .cloned()
+                    .flatten()
+                    .context("Invalid reference to a string")
+            }
+            tag => anyhow::bail!("Expected a string, found {tag:#x}"),
+        }
+    }
+
+    fn hash_map(mut self) -> Result<HashMap<String, Zeroizing<String>>> {
+        anyhow::ensure!(
+            self.u16()? == Self::MAGIC && self.u16()? == Self::VERSION,
+            "Not a FreeOTP backup"
+        );
+        self.expect(Self::TC_OBJECT)?;
+
+        self.expect(Self::TC_CLASSDESC)?;
+        let size = self.u16()? as usize;
+        let class_name = self.utf(size)?;
+        anyhow::ensure!(
+            class_name == "java.util.HashMap",
+            "Unexpected class {class_name}"
+        );
+        self.handles.push(None);
+        // The serial version UID and the flags
+        self.take(9)?;
+        let mut fields_size = 0;
+        for _ in 0..self.u16()? {
+            let field_type = self.u8()?;
+            let size = self.u16()? as usize;
+            self.take(size)?;
+            fields_size += match field_type {
+                b'B' | b'Z' => 1,
+                b'C' | b'S' => 2,
+                b'F' | b'I' => 4,
+                b'D' | b'J' => 8,
+                _ => anyhow::bail!("Unexpected field type {field_type:#x}"),
+            };
+        }
+        self.expect(Self::TC_ENDBLOCKDATA)?;
+        // No super class
+        self.expect(Self::TC_NULL)?;
+        self.handles.push(None);
+
+        // The load factor and the threshold
+        self.take(fields_size)?;
+        // The capacity and the number of entries
+        self.expect(Self::TC_BLOCKDATA)?;
+        let size = self.u8()? as usize;
+        let block = self.take(size)?;
+        let entries = block
+            .get(4..8)
+            .map(|entries| u32::from_be_bytes(entries.try_into().unwrap()))
+            .context("Invalid HashMap")?;
+
+        let mut map = HashMap::new();
+        for _ in 0..entries {
+            let key = self.string()?;
+            let value = Zeroizing::new(self.string()?);
+            map.insert(key, value);
+        }
+        self.handles.clear();
+        Ok(map)
+    }
+}
+
+impl Restorable for FreeOTPBackup {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "freeotp_backup";
+    type Item = FreeOTPItem;
+
+    fn title() -> String {
+        // Translators: This is for restoring a backup from the FreeOTP app, version 2 or newer.
+        gettext("FreeOTP _Backup")
+    }
+
+    fn subtitle() -> String {
+        gettext("From an encrypted backup file of FreeOTP 2")
+    }
+
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let password = key
+            .filter(|key| !key.is_empty())
+            .context("FreeOTP backups are always encrypted, a password is needed")?;
+        let map = ObjectStream::new(from).hash_map()?;
+
+        let master_key: MasterKey =
+            serde_json::from_str(map.get("masterKey").context("Missing the master key")?)?;
+        let derived_key = master_key.derive_key(password)?;
+        let master_key = master_key
+            .encrypted_key
+            .decrypt(derived_key.as_slice())
+            .context("Cannot decrypt the backup, is the password correct?")?;
+
+        let mut items = map
+            .iter()
+            .filter_map(|(key, token)| Some((key.strip_suffix("-token")?, token)))
+            .map(|(uuid, token)| {
+                let token_key: TokenKey =
+                    serde_json::from_str(map.get(uuid).context("Missing the token secret")?)?;
+                let encrypted_key: EncryptedKey = serde_json::from_str(&token_key.key)?;
+                let secret = encrypted_key.decrypt(&master_key)?;
+
+                let mut item: FreeOTPItem = serde_json::from_str(token)?;
+                item.set_secret(&secret);
+                Ok((uuid, item))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // The order of a HashMap is random, sort by UUID to make it stable
+        items.sort_by_key(|(uuid, _)| *uuid);
+        Ok(items.into_iter().map(|(_, item)| item).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+    use crate::models::{Algorithm, Method};
+
+    #[test]
+    fn parse() {
+        let data = std::fs::read("./src/backup/tests/freeotp_backup.xml").unwrap();
+        let items = FreeOTPBackup::restore_from_data(&data, Some("FreeOTPTest123")).unwrap();
+        assert_eq!(items.len(), 3);
+
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[0].issuer(), "GitHub");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[0].period(), Some(30));
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[0].algorithm(), Algorithm::SHA1);
+        assert_eq!(items[0].digits(), Some(6));
+        assert_eq!(items[0].counter(), None);
+
+        assert_eq!(items[1].account(), "gordon");
+        assert_eq!(items[1].issuer(), "Black Mesa");
+        assert_eq!(items[1].secret(), "5OM4WOOGPLQEF6UGN3CPEOOLWU");
+        assert_eq!(items[1].method(), Method::HOTP);
+        assert_eq!(items[1].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[1].digits(), Some(8));
+        assert_eq!(items[1].counter(), Some(13));
+
+        assert_eq!(items[2].account(), "barney");
+        assert_eq!(items[2].issuer(), "");
+        assert_eq!(items[2].secret(), "7ELGJSGXNCCTV3O6LKJWYFV2RA");
+        assert_eq!(items[2].period(), Some(60));
+        assert_eq!(items[2].algorithm(), Algorithm::SHA512);
+    }
+
+    #[test]
+    fn wrong_password() {
+        let data = std::fs::read("./src/backup/tests/freeotp_backup.xml").unwrap();
+        assert!(FreeOTPBackup::restore_from_data(&data, Some("wrong password")).is_err());
+        assert!(FreeOTPBackup::restore_from_data(&data, None).is_err());
+    }
+
+    #[test]
+    fn tampered() {
+        let master_key: MasterKey = serde_json::from_value(serde_json::json!({
+            "mAlgorithm": "PBKDF2withHmacSHA512",
+            "mEncryptedKey": { "mCipherText": [], "mParameters": [], "mToken": "AES" },
+            "mIterations": u32::MAX,
+            "mSalt": [0, 1, 2, 3],
+        }))
+        .unwrap();
+        assert!(master_key.derive_key("FreeOTPTest123").is_err());
+
+        // A string longer than what is left once the position is added to it
+        let mut stream = ObjectStream::new(&[0]);
+        stream.u8().unwrap();
+        assert!(stream.take(usize::MAX).is_err());
+    }
+}
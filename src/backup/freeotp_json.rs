@@ -22,17 +22,26 @@ pub struct FreeOTPItem {
     digits: Option<u32>,
     #[zeroize(skip)]
     label: String,
-    #[serde(rename = "issuerExt")]
+    #[serde(rename = "issuerExt", default)]
     #[zeroize(skip)]
     issuer: String,
     #[zeroize(skip)]
     period: Option<u32>,
+    // Missing from the encrypted backups, where it is stored separately
+    #[serde(default)]
     secret: Vec<i16>,
     #[serde(rename = "type")]
     #[zeroize(skip)]
     method: Method,
 }
 
+impl FreeOTPItem {
+    pub(super) fn set_secret(&mut self, secret: &[u8]) {
+        self.secret.zeroize();
+        self.secret = secret.iter().map(|byte| *byte as i8 as i16).collect();
+    }
+}
+
 impl RestorableItem for FreeOTPItem {
     fn account(&self) -> String {
         self.label.clone()
@@ -93,6 +102,11 @@ impl Restorable for FreeOTPJSON {
 
     fn restore_from_data(from: &[u8], _key: Option<&str>) -> Result<Vec<Self::Item>> {
         let root: FreeOTPJSON = serde_json::de::from_slice(from)?;
+        // Only the encrypted backups store the secret separately
+        anyhow::ensure!(
+            root.tokens.iter().all(|token| !token.secret.is_empty()),
+            "A token is missing its secret"
+        );
         Ok(root.tokens)
     }
 }
@@ -160,4 +174,20 @@ mod tests {
         assert_eq!(items[5].digits(), Some(8));
         assert_eq!(items[5].counter(), Some(10300));
     }
+
+    #[test]
+    fn missing_secret() {
+        let data = serde_json::json!({
+            "tokens": [{
+                "algo": "SHA1",
+                "digits": 6,
+                "issuerExt": "Deno",
+                "label": "Mason",
+                "period": 30,
+                "type": "TOTP",
+            }],
+        });
+        let data = serde_json::to_vec(&data).unwrap();
+        assert!(FreeOTPJSON::restore_from_data(&data, None).is_err());
+    }
 }
@@ -1,14 +1,150 @@
-use anyhow::Result;
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
 use gettextrs::gettext;
-use percent_encoding::percent_decode;
+use gtk::prelude::*;
+use percent_encoding::{percent_decode, utf8_percent_encode, NON_ALPHANUMERIC};
 use prost::{Enumeration, Message};
+use rand::Rng;
 use url::Url;
 
-use super::Restorable;
-use crate::models::{Algorithm, Method, OTPUri};
+use super::{Backupable, MissingQRCodes, Restorable, SkippedItem};
+use crate::models::{i18n, Account, Algorithm, Method, OTPUri, Provider, ProvidersModel, OTP};
 
 pub struct Google;
 
+impl Google {
+    // Google Authenticator doesn't put more accounts than that in a QR code
+    const BATCH_SIZE: usize = 10;
+
+    fn otp_parameters(account: &Account) -> Result<protobuf::migration_payload::OtpParameters> {
+        use protobuf::migration_payload::{
+            Algorithm as GoogleAlgorithm, DigitCount, OtpParameters, OtpType,
+        };
+
+        let provider = account.provider();
+        let r#type = match provider.method() {
+            Method::TOTP => {
+                anyhow::ensure!(
+                    provider.period() == OTP::DEFAULT_PERIOD && provider.epoch() == 0,
+                    "Only a period of {} seconds is supported",
+                    OTP::DEFAULT_PERIOD
+                );
+                OtpType::OTP_TOTP
+            }
+            Method::HOTP => OtpType::OTP_HOTP,
+            method => anyhow::bail!("Unsupported method {}", method.to_string()),
+        };
+        let digits = match provider.digits() {
+            6 => DigitCount::DIGIT_COUNT_SIX,
+            8 => DigitCount::DIGIT_COUNT_EIGHT,
+            digits => anyhow::bail!("Unsupported number of digits {digits}"),
+        };
+        let algorithm = match provider.algorithm() {
+            Algorithm::SHA1 => GoogleAlgorithm::ALGO_SHA1,
+            Algorithm::SHA256 => GoogleAlgorithm::ALGO_SHA256,
+            Algorithm::SHA512 => GoogleAlgorithm::ALGO_SHA512,
+        };
+
+        Ok(OtpParameters {
+            secret: data_encoding::BASE32_NOPAD.decode(account.otp().secret().as_bytes())?,
+            name: account.name(),
+            issuer: provider.name(),
+            algorithm: algorithm as i32,
+            digits: digits as i32,
+            r#type: r#type as i32,
+            counter: account.counter() as i64,
+        })
+    }
+
+    /// Splits the accounts into as many `otpauth-migration` URIs as needed.
+    fn migration_uris(
+        otp_parameters: Vec<protobuf::migration_payload::OtpParameters>,
+        batch_id: i32,
+    ) -> Vec<String> {
+        let batches = otp_parameters
+            .chunks(Self::BATCH_SIZE)
+            .map(<[_]>::to_vec)
+            .collect::<Vec<_>>();
+        let batch_size = batches.len() as i32;
+
+        batches
+            .into_iter()
+            .enumerate()
+            .map(|(batch_index, otp_parameters)| {
+                let payload = protobuf::MigrationPayload {
+                    otp_parameters,
+                    version: 1,
+                    batch_size,
+                    batch_index: batch_index as i32,
+                    batch_id,
+                };
+                let data = data_encoding::BASE64.encode(&payload.encode_to_vec());
+                format!(
+                    "otpauth-migration://offline?data={}",
+                    utf8_percent_encode(&data, NON_ALPHANUMERIC)
+                )
+            })
+            .collect()
+    }
+}
+
+impl Backupable for Google {
+    const ENCRYPTABLE: bool = false;
+    const SCANNABLE: bool = true;
+    const IDENTIFIER: &'static str = "google";
+
+    fn title() -> String {
+        gettext("Google Authenticator")
+    }
+
+    fn subtitle() -> String {
+        gettext("Into QR codes that can be scanned by Google Authenticator")
+    }
+
+    fn backup(model: &ProvidersModel, _key: Option<&str>) -> Result<Vec<u8>> {
+        let mut otp_parameters = Vec::new();
+
+        for i in 0..model.n_items() {
+            let provider = model.item(i).and_downcast::<Provider>().unwrap();
+            let accounts = provider.accounts_model();
+
+            for j in 0..accounts.n_items() {
+                let account = accounts.item(j).and_downcast::<Account>().unwrap();
+
+                match Self::otp_parameters(&account) {
+                    Ok(parameters) => otp_parameters.push(parameters),
+                    Err(err) => tracing::warn!(
+                        "Skipping account {}/{} not supported by Google Authenticator: {err}",
+                        provider.name(),
+                        account.name()
+                    ),
+                }
+            }
+        }
+        anyhow::ensure!(
+            !otp_parameters.is_empty(),
+            "No account can be exported to Google Authenticator"
+        );
+
+        let batch_id = rand::thread_rng().gen_range(1..i32::MAX);
+        let content = Self::migration_uris(otp_parameters, batch_id).join("\n");
+        Ok(content.as_bytes().to_vec())
+    }
+
+    fn skipped_accounts(model: &ProvidersModel) -> u32 {
+        (0..model.n_items())
+            .filter_map(|i| model.item(i).and_downcast::<Provider>())
+            .flat_map(|provider| {
+                let accounts = provider.accounts_model();
+                (0..accounts.n_items())
+                    .filter_map(move |j| accounts.item(j).and_downcast::<Account>())
+            })
+            .filter(|account| Self::otp_parameters(account).is_err())
+            .count() as u32
+    }
+}
+
 impl Restorable for Google {
     const ENCRYPTABLE: bool = false;
     const SCANNABLE: bool = true;
@@ -23,9 +159,153 @@ impl Restorable for Google {
         gettext("From a QR code generated by Google Authenticator")
     }
 
-    fn restore_from_data(from: &[u8], _key: Option<&str>) -> Result<Vec<Self::Item>> {
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        Ok(Self::restore_from_data_with_skipped(from, key)?.0)
+    }
+
+    /// Restores the accounts of one or several `otpauth-migration` URIs, one
+    /// per line, belonging to the same export.
+    fn restore_from_data_with_skipped(
+        from: &[u8],
+        _key: Option<&str>,
+    ) -> Result<(Vec<Self::Item>, Vec<SkippedItem>)> {
         let string = String::from_utf8(from.into())?;
-        let uri = Url::parse(&string)?;
+
+        let mut session = MigrationSession::default();
+        for uri in string.lines().map(str::trim).filter(|uri| !uri.is_empty()) {
+            session.add(uri)?;
+        }
+        if !session.is_complete() {
+            return Err(MissingQRCodes {
+                scanned: session.batches.len(),
+                total: session.batch_size,
+            }
+            .into());
+        }
+
+        Ok(session.into_items())
+    }
+}
+
+impl TryFrom<&protobuf::migration_payload::OtpParameters> for OTPUri {
+    type Error = anyhow::Error;
+
+    fn try_from(otp: &protobuf::migration_payload::OtpParameters) -> Result<Self> {
+        use protobuf::migration_payload::{Algorithm as GoogleAlgorithm, DigitCount, OtpType};
+
+        let algorithm = match otp.algorithm() {
+            GoogleAlgorithm::ALGO_SHA1 => Algorithm::SHA1,
+            GoogleAlgorithm::ALGO_SHA256 => Algorithm::SHA256,
+            GoogleAlgorithm::ALGO_SHA512 => Algorithm::SHA512,
+            GoogleAlgorithm::ALGO_MD5 => {
+                anyhow::bail!(gettext("The MD5 algorithm is not supported"))
+            }
+            GoogleAlgorithm::ALGO_INVALID => anyhow::bail!(i18n::i18n_f(
+                "Unsupported algorithm {}",
+                &[&otp.algorithm.to_string()]
+            )),
+        };
+        let method = match otp.r#type() {
+            OtpType::OTP_HOTP => Method::HOTP,
+            OtpType::OTP_TOTP => Method::TOTP,
+            OtpType::OTP_INVALID => anyhow::bail!(i18n::i18n_f(
+                "Unsupported type {}",
+                &[&otp.r#type.to_string()]
+            )),
+        };
+        let digits = match otp.digits() {
+            DigitCount::DIGIT_COUNT_SIX => Some(6),
+            DigitCount::DIGIT_COUNT_EIGHT => Some(8),
+            DigitCount::DIGIT_COUNT_UNSPECIFIED => None,
+        };
+
+        let secret = data_encoding::BASE32_NOPAD.encode(&otp.secret);
+
+        Ok(OTPUri {
+            algorithm,
+            digits,
+            method,
+            secret: secret.trim_end_matches(['\0', '=']).to_owned(),
+            label: otp.name.clone(),
+            issuer: otp.issuer.clone(),
+            period: None,
+            counter: Some(otp.counter as u32),
+            pin: None,
+            epoch: None,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        })
+    }
+}
+
+/// Collects the payloads of an export split across several QR codes until
+/// every batch of it was seen.
+#[derive(Default)]
+struct MigrationSession {
+    batch_id: Option<i32>,
+    batch_size: usize,
+    batches: BTreeMap<usize, protobuf::MigrationPayload>,
+}
+
+impl MigrationSession {
+    fn add(&mut self, uri: &str) -> Result<()> {
+        let payload = Self::payload(uri)?;
+
+        match self.batch_id {
+            Some(batch_id) if batch_id != payload.batch_id => {
+                anyhow::bail!("The QR code belongs to another export")
+            }
+            _ => self.batch_id = Some(payload.batch_id),
+        }
+        // Exports made before batches were introduced leave them unset
+        let batch_size = payload.batch_size.max(1) as usize;
+        let batch_index = usize::try_from(payload.batch_index)?;
+        anyhow::ensure!(
+            batch_index < batch_size,
+            "Invalid batch index {batch_index} of {batch_size}"
+        );
+
+        self.batch_size = batch_size;
+        self.batches.entry(batch_index).or_insert(payload);
+        Ok(())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.batch_size > 0 && self.batches.len() == self.batch_size
+    }
+
+    /// The accounts of the export, along the ones that can't be restored.
+    fn into_items(self) -> (Vec<OTPUri>, Vec<SkippedItem>) {
+        let mut items = Vec::new();
+        let mut skipped = Vec::new();
+        for otp in self
+            .batches
+            .values()
+            .flat_map(|payload| &payload.otp_parameters)
+        {
+            match OTPUri::try_from(otp) {
+                Ok(uri) => items.push(uri),
+                Err(err) => {
+                    let name = if otp.name.is_empty() {
+                        otp.issuer.clone()
+                    } else {
+                        format!("{}/{}", otp.issuer, otp.name)
+                    };
+                    tracing::warn!("Skipping the account {name}: {err}");
+                    skipped.push(SkippedItem {
+                        name,
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+        (items, skipped)
+    }
+
+    fn payload(uri: &str) -> Result<protobuf::MigrationPayload> {
+        let uri = Url::parse(uri)?;
 
         if uri.scheme() != "otpauth-migration" {
             anyhow::bail!("Invalid OTP migration uri format, expected uri protocol to be otpauth-migration, got {}", uri.scheme());
@@ -62,50 +342,7 @@ impl Restorable for Google {
             })
         });
 
-        let data = if let Some(data) = data {
-            data
-        } else {
-            anyhow::bail!("Invalid OTP migration uri format, expected a data query parameter");
-        };
-
-        let data_len = data.otp_parameters.len();
-
-        let mut restored = data.otp_parameters.into_iter().fold(
-            Vec::with_capacity(data_len),
-            |mut folded, otp| {
-                folded.push(OTPUri {
-                    algorithm: match otp.algorithm() {
-                        protobuf::migration_payload::Algorithm::ALGO_INVALID => return folded,
-                        protobuf::migration_payload::Algorithm::ALGO_SHA1 => Algorithm::SHA1,
-                    },
-                    digits: match otp.r#type() {
-                        protobuf::migration_payload::OtpType::OTP_HOTP => Some(otp.digits as u32),
-                        _ => None,
-                    },
-                    method: match otp.r#type() {
-                        protobuf::migration_payload::OtpType::OTP_INVALID => return folded,
-                        protobuf::migration_payload::OtpType::OTP_HOTP => Method::HOTP,
-                        protobuf::migration_payload::OtpType::OTP_TOTP => Method::TOTP,
-                    },
-                    secret: {
-                        let string = data_encoding::BASE32_NOPAD.encode(&otp.secret);
-
-                        string
-                            .trim_end_matches(|c| c == '\0' || c == '=')
-                            .to_owned()
-                    },
-                    label: otp.name.clone(),
-                    issuer: otp.issuer.clone(),
-                    period: None,
-                    counter: Some(otp.counter as u32),
-                });
-                folded
-            },
-        );
-
-        restored.shrink_to_fit();
-
-        Ok(restored)
+        data.context("Invalid OTP migration uri format, expected a data query parameter")
     }
 }
 
@@ -136,6 +373,16 @@ mod protobuf {
         pub enum Algorithm {
             ALGO_INVALID = 0,
             ALGO_SHA1 = 1,
+            ALGO_SHA256 = 2,
+            ALGO_SHA512 = 3,
+            ALGO_MD5 = 4,
+        }
+
+        #[derive(Debug, Enumeration)]
+        pub enum DigitCount {
+            DIGIT_COUNT_UNSPECIFIED = 0,
+            DIGIT_COUNT_SIX = 1,
+            DIGIT_COUNT_EIGHT = 2,
         }
 
         #[derive(Debug, Enumeration)]
@@ -145,7 +392,7 @@ mod protobuf {
             OTP_TOTP = 2,
         }
 
-        #[derive(Message, Zeroize, ZeroizeOnDrop)]
+        #[derive(Clone, Message, Zeroize, ZeroizeOnDrop)]
         pub struct OtpParameters {
             #[prost(bytes)]
             pub secret: Vec<u8>,
@@ -158,7 +405,7 @@ mod protobuf {
             #[prost(enumeration = "Algorithm")]
             #[zeroize(skip)]
             pub algorithm: i32,
-            #[prost(int32)]
+            #[prost(enumeration = "DigitCount")]
             #[zeroize(skip)]
             pub digits: i32,
             #[prost(enumeration = "OtpType")]
@@ -185,7 +432,99 @@ mod tests {
         assert_eq!(items[0].secret(), "JRZCL47CMXVOQMNPZR2F7J4RGI");
         assert_eq!(items[0].period(), None);
         assert_eq!(items[0].algorithm(), Algorithm::SHA1);
-        assert_eq!(items[0].digits(), None);
+        assert_eq!(items[0].digits(), Some(6));
         assert_eq!(items[0].counter(), Some(0));
     }
+
+    #[test]
+    fn batches() {
+        use protobuf::migration_payload::{DigitCount, OtpParameters, OtpType};
+
+        let otp_parameters = (0..15)
+            .map(|i| OtpParameters {
+                secret: b"12345678901234567890".to_vec(),
+                name: format!("account{i}"),
+                issuer: "Example".to_owned(),
+                algorithm: (if i == 14 {
+                    protobuf::migration_payload::Algorithm::ALGO_MD5
+                } else {
+                    protobuf::migration_payload::Algorithm::ALGO_SHA512
+                }) as i32,
+                digits: DigitCount::DIGIT_COUNT_SIX as i32,
+                r#type: OtpType::OTP_TOTP as i32,
+                counter: 0,
+            })
+            .collect::<Vec<_>>();
+        let other_export = Google::migration_uris(otp_parameters[..1].to_vec(), 8);
+        let uris = Google::migration_uris(otp_parameters, 7);
+        assert_eq!(uris.len(), 2);
+
+        let err = Google::restore_from_data(uris[1].as_bytes(), None)
+            .err()
+            .unwrap();
+        let missing = err.downcast_ref::<MissingQRCodes>().unwrap();
+        assert_eq!((missing.scanned, missing.total), (1, 2));
+
+        // Scanning the same QR code twice doesn't count
+        let data = [&uris[1], &uris[1]].map(String::as_str).join("\n");
+        assert!(Google::restore_from_data(data.as_bytes(), None).is_err());
+
+        let data = [&uris[1], &other_export[0]].map(String::as_str).join("\n");
+        assert!(Google::restore_from_data(data.as_bytes(), None).is_err());
+
+        let data = [&uris[1], &uris[0]].map(String::as_str).join("\n");
+        let (items, skipped) =
+            Google::restore_from_data_with_skipped(data.as_bytes(), None).unwrap();
+        assert_eq!(items.len(), 14);
+        assert_eq!(items[0].account(), "account0");
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[0].algorithm(), Algorithm::SHA512);
+        assert_eq!(items[0].digits(), Some(6));
+        assert_eq!(
+            skipped,
+            [SkippedItem {
+                name: "Example/account14".to_owned(),
+                reason: "The MD5 algorithm is not supported".to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn migration_uris() {
+        use protobuf::migration_payload::{DigitCount, OtpParameters, OtpType};
+
+        let otp_parameters = (0..25)
+            .map(|i| OtpParameters {
+                secret: b"12345678901234567890".to_vec(),
+                name: format!("account{i}"),
+                issuer: "Example".to_owned(),
+                algorithm: protobuf::migration_payload::Algorithm::ALGO_SHA256 as i32,
+                digits: DigitCount::DIGIT_COUNT_EIGHT as i32,
+                r#type: OtpType::OTP_HOTP as i32,
+                counter: i,
+            })
+            .collect::<Vec<_>>();
+        let uris = Google::migration_uris(otp_parameters, 42);
+        assert_eq!(uris.len(), 3);
+
+        for (batch_index, uri) in uris.iter().enumerate() {
+            let url = Url::parse(uri).unwrap();
+            let data = url.query_pairs().next().unwrap().1;
+            let data = data_encoding::BASE64.decode(data.as_bytes()).unwrap();
+            let payload = protobuf::MigrationPayload::decode(&*data).unwrap();
+            assert_eq!(payload.batch_size, 3);
+            assert_eq!(payload.batch_index, batch_index as i32);
+            assert_eq!(payload.batch_id, 42);
+        }
+
+        let items = Google::restore_from_data(uris.join("\n").as_bytes(), None).unwrap();
+        assert_eq!(items.len(), 25);
+        assert_eq!(items[20].account(), "account20");
+        assert_eq!(items[20].issuer(), "Example");
+        assert_eq!(items[20].secret(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(items[20].method(), Method::HOTP);
+        assert_eq!(items[20].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[20].digits(), Some(8));
+        assert_eq!(items[20].counter(), Some(20));
+    }
 }
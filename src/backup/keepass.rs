@@ -0,0 +1,748 @@
+//! KeePass Import Module
+//!
+//! Reads KDBX 3.1 and 4 databases protected by a password, key files are not
+//! supported. See <https://keepass.info/help/kb/kdbx_4.html> for a description
+//! of the format.
+//!
+//! The OTP of an entry is read from either
+//! - the `otp` field of KeePassXC, holding an otpauth URI
+//! - the legacy `TOTP Seed` and `TOTP Settings` fields of KeePassXC
+//! - the `TimeOtp-*` fields of KeePass 2.47+
+//!
+//! Entries without any of them, the history and the recycle bin are skipped.
+
+use std::{collections::HashMap, io::Read, str::FromStr};
+
+use aes_gcm::aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes256,
+};
+use anyhow::{Context, Result};
+use chacha20::{
+    cipher::{KeyIvInit, StreamCipher},
+    ChaCha20,
+};
+use data_encoding::{BASE32_NOPAD, BASE64, HEXLOWER_PERMISSIVE};
+use gettextrs::gettext;
+use ring::{digest, hmac};
+use salsa20::Salsa20;
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{crypto, Restorable};
+use crate::models::{Algorithm, Method, OTPUri, OTP};
+
+pub struct KeePass;
+
+// Identifiers of the outer ciphers
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+
+// Identifiers of the key derivation functions
+const KDF_AES_KDBX3: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+const KDF_AES_KDBX4: [u8; 16] = [
+    0x7c, 0x02, 0xbb, 0x82, 0x79, 0xa7, 0x4a, 0xc0, 0x92, 0x7d, 0x11, 0x4a, 0x00, 0x64, 0x82, 0x38,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+
+const SIGNATURE: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5];
+// The fixed nonce of the Salsa20 inner stream
+const SALSA20_NONCE: [u8; 8] = [0xe8, 0x30, 0x09, 0x4b, 0x97, 0x20, 0x5d, 0x2a];
+const INNER_STREAM_SALSA20: u32 = 2;
+const INNER_STREAM_CHACHA20: u32 = 3;
+// The KDF parameters come from the unauthenticated header, these bounds are
+// well above what KeePass and KeePassXC pick but keep a crafted database from
+// hanging the app or exhausting the memory
+const MAX_AES_ROUNDS: u64 = 100_000_000;
+const MAX_ARGON2_MEMORY: u64 = 4 * 1024 * 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u64 = 1_000;
+const MAX_ARGON2_PARALLELISM: u32 = 64;
+
+/// A cursor over the binary parts of a database.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + size)
+            .context("Unexpected end of the database")?;
+        self.position += size;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.position..];
+        self.position = self.data.len();
+        rest
+    }
+}
+
+enum Kdf {
+    Aes {
+        seed: Vec<u8>,
+        rounds: u64,
+    },
+    Argon2 {
+        variant: argon2::Variant,
+        salt: Vec<u8>,
+        parallelism: u32,
+        // In bytes
+        memory: u64,
+        iterations: u64,
+        version: u32,
+    },
+}
+
+impl Kdf {
+    /// Parses the KDF parameters of KDBX 4, stored in a `VariantDictionary`.
+    fn from_variant_dictionary(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        anyhow::ensure!(
+            reader.u16()? >> 8 == 1,
+            "Unsupported version of the KDF parameters"
+        );
+
+        let mut values = HashMap::new();
+        loop {
+            let value_type = reader.u8()?;
+            if value_type == 0 {
+                break;
+            }
+            let key_size = reader.u32()? as usize;
+            let key = std::str::from_utf8(reader.take(key_size)?)?;
+            let value_size = reader.u32()? as usize;
+            values.insert(key, reader.take(value_size)?);
+        }
+
+        let bytes = |key: &str| {
+            values
+                .get(key)
+                .map(|value| value.to_vec())
+                .with_context(|| format!("Missing the KDF parameter {key}"))
+        };
+        let u32_value = |key: &str| -> Result<u32> {
+            Ok(u32::from_le_bytes(
+                bytes(key)?
+                    .try_into()
+                    .ok()
+                    .context("Invalid KDF parameter")?,
+            ))
+        };
+        let u64_value = |key: &str| -> Result<u64> {
+            Ok(u64::from_le_bytes(
+                bytes(key)?
+                    .try_into()
+                    .ok()
+                    .context("Invalid KDF parameter")?,
+            ))
+        };
+
+        let uuid: [u8; 16] = bytes("$UUID")?
+            .try_into()
+            .ok()
+            .context("Invalid KDF identifier")?;
+        match uuid {
+            KDF_AES_KDBX3 | KDF_AES_KDBX4 => Ok(Self::Aes {
+                seed: bytes("S")?,
+                rounds: u64_value("R")?,
+            }),
+            KDF_ARGON2D | KDF_ARGON2ID => Ok(Self::Argon2 {
+                variant: if uuid == KDF_ARGON2D {
+                    argon2::Variant::Argon2d
+                } else {
+                    argon2::Variant::Argon2id
+                },
+                salt: bytes("S")?,
+                parallelism: u32_value("P")?,
+                memory: u64_value("M")?,
+                iterations: u64_value("I")?,
+                version: u32_value("V")?,
+            }),
+            _ => anyhow::bail!("Unsupported key derivation function"),
+        }
+    }
+
+    fn transform(&self, composite_key: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        match self {
+            Self::Aes { seed, rounds } => {
+                anyhow::ensure!(
+                    *rounds <= MAX_AES_ROUNDS,
+                    "Too many key transformation rounds: {rounds}"
+                );
+                let cipher =
+                    Aes256::new_from_slice(seed).map_err(|_| anyhow::anyhow!("Invalid seed"))?;
+                let mut key = Zeroizing::new(composite_key.to_vec());
+                let mut blocks = key
+                    .chunks_exact(16)
+                    .map(GenericArray::clone_from_slice)
+                    .collect::<Vec<_>>();
+                for _ in 0..*rounds {
+                    cipher.encrypt_blocks(&mut blocks);
+                }
+                for (chunk, block) in key.chunks_exact_mut(16).zip(&mut blocks) {
+                    chunk.copy_from_slice(block);
+                    block.zeroize();
+                }
+                Ok(Zeroizing::new(
+                    digest::digest(&digest::SHA256, &key).as_ref().to_vec(),
+                ))
+            }
+            Self::Argon2 {
+                variant,
+                salt,
+                parallelism,
+                memory,
+                iterations,
+                version,
+            } => {
+                anyhow::ensure!(
+                    *memory <= MAX_ARGON2_MEMORY
+                        && *iterations <= MAX_ARGON2_ITERATIONS
+                        && *parallelism <= MAX_ARGON2_PARALLELISM,
+                    "Unreasonable Argon2 parameters"
+                );
+                let config = argon2::Config {
+                    variant: *variant,
+                    version: argon2::Version::from_u32(*version)?,
+                    mem_cost: (*memory / 1024).try_into()?,
+                    time_cost: (*iterations).try_into()?,
+                    lanes: *parallelism,
+                    hash_length: 32,
+                    ..argon2::Config::default()
+                };
+                Ok(Zeroizing::new(argon2::hash_raw(
+                    composite_key,
+                    salt,
+                    &config,
+                )?))
+            }
+        }
+    }
+}
+
+/// The stream used to protect some of the values, like the passwords, in the
+/// XML document.
+enum InnerStream {
+    Salsa20(Salsa20),
+    ChaCha20(ChaCha20),
+}
+
+impl InnerStream {
+    fn new(id: u32, key: &[u8]) -> Result<Self> {
+        match id {
+            INNER_STREAM_SALSA20 => {
+                let key = digest::digest(&digest::SHA256, key);
+                Ok(Self::Salsa20(Salsa20::new(
+                    GenericArray::from_slice(key.as_ref()),
+                    &SALSA20_NONCE.into(),
+                )))
+            }
+            INNER_STREAM_CHACHA20 => {
+                let key = digest::digest(&digest::SHA512, key);
+                let (key, nonce) = key.as_ref().split_at(32);
+                Ok(Self::ChaCha20(ChaCha20::new(
+                    GenericArray::from_slice(key),
+                    GenericArray::from_slice(&nonce[..12]),
+                )))
+            }
+            _ => anyhow::bail!("Unsupported inner stream {id}"),
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        match self {
+            Self::Salsa20(cipher) => cipher.apply_keystream(data),
+            Self::ChaCha20(cipher) => cipher.apply_keystream(data),
+        }
+    }
+}
+
+/// The decrypted content of a database.
+struct Database {
+    xml: Zeroizing<String>,
+    inner_stream: InnerStream,
+}
+
+impl Database {
+    fn open(data: &[u8], password: &str) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        anyhow::ensure!(
+            reader.take(SIGNATURE.len())? == SIGNATURE,
+            "Not a KeePass database"
+        );
+        let _minor_version = reader.u16()?;
+        let major_version = reader.u16()?;
+        anyhow::ensure!(
+            matches!(major_version, 3 | 4),
+            "Unsupported KDBX version {major_version}"
+        );
+        let is_kdbx4 = major_version == 4;
+
+        let mut fields = HashMap::new();
+        loop {
+            let id = reader.u8()?;
+            let size = if is_kdbx4 {
+                reader.u32()? as usize
+            } else {
+                reader.u16()? as usize
+            };
+            let value = reader.take(size)?;
+            if id == 0 {
+                break;
+            }
+            fields.insert(id, value);
+        }
+        let header = &data[..reader.position];
+        let field = |id: u8| {
+            fields
+                .get(&id)
+                .copied()
+                .with_context(|| format!("Missing the header field {id}"))
+        };
+
+        let cipher = field(2)?;
+        let compressed = u32::from_le_bytes(field(3)?.try_into()?) == 1;
+        let master_seed = field(4)?;
+        let iv = field(7)?;
+        let kdf = if is_kdbx4 {
+            Kdf::from_variant_dictionary(field(11)?)?
+        } else {
+            Kdf::Aes {
+                seed: field(5)?.to_vec(),
+                rounds: u64::from_le_bytes(field(6)?.try_into()?),
+            }
+        };
+
+        // Only passwords are supported, making it the sole component
+        let password_hash = digest::digest(&digest::SHA256, password.as_bytes());
+        let composite_key = digest::digest(&digest::SHA256, password_hash.as_ref());
+        let transformed_key = kdf.transform(composite_key.as_ref())?;
+        let mut seeded_key = Zeroizing::new([master_seed, &transformed_key].concat());
+        let key = digest::digest(&digest::SHA256, &seeded_key);
+
+        let mut payload = if is_kdbx4 {
+            anyhow::ensure!(
+                reader.take(32)? == digest::digest(&digest::SHA256, header).as_ref(),
+                "The header of the database is corrupted"
+            );
+            seeded_key.push(1);
+            let hmac_key = digest::digest(&digest::SHA512, &seeded_key);
+            let header_hmac = reader.take(32)?;
+            Self::verify_block(hmac_key.as_ref(), u64::MAX, header, header_hmac)
+                .context("Cannot open the database, is the password correct?")?;
+
+            let encrypted = Self::read_hmac_blocks(&mut reader, hmac_key.as_ref())?;
+            Self::decrypt(cipher, key.as_ref(), iv, &encrypted)?
+        } else {
+            let decrypted = Self::decrypt(cipher, key.as_ref(), iv, reader.rest())
+                .context("Cannot open the database, is the password correct?")?;
+            let mut decrypted_reader = Reader::new(&decrypted);
+            anyhow::ensure!(
+                decrypted_reader.take(32)? == field(9)?,
+                "Cannot open the database, is the password correct?"
+            );
+            Self::read_hashed_blocks(&mut decrypted_reader)?
+        };
+
+        if compressed {
+            let mut decompressed = Vec::new();
+            flate2::read::GzDecoder::new(payload.as_slice()).read_to_end(&mut decompressed)?;
+            payload.zeroize();
+            payload = decompressed;
+        }
+
+        let mut payload_reader = Reader::new(&payload);
+        let inner_stream = if is_kdbx4 {
+            let mut inner_fields = HashMap::new();
+            loop {
+                let id = payload_reader.u8()?;
+                let size = payload_reader.u32()? as usize;
+                let value = payload_reader.take(size)?;
+                if id == 0 {
+                    break;
+                }
+                // Binaries, the attachments, are not needed
+                if id != 3 {
+                    inner_fields.insert(id, value);
+                }
+            }
+            let id = inner_fields.get(&1).context("Missing the inner stream")?;
+            let key = inner_fields
+                .get(&2)
+                .context("Missing the inner stream key")?;
+            InnerStream::new(u32::from_le_bytes((*id).try_into()?), key)?
+        } else {
+            InnerStream::new(u32::from_le_bytes(field(10)?.try_into()?), field(8)?)?
+        };
+
+        let xml = std::str::from_utf8(payload_reader.rest()).map(ToOwned::to_owned);
+        payload.zeroize();
+        Ok(Self {
+            xml: Zeroizing::new(xml?),
+            inner_stream,
+        })
+    }
+
+    fn decrypt(cipher: &[u8], key: &[u8], iv: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        if cipher == CIPHER_AES256 {
+            crypto::aes_cbc_decrypt(key, iv, data)
+        } else if cipher == CIPHER_CHACHA20 {
+            let mut decrypted = data.to_vec();
+            ChaCha20::new(GenericArray::from_slice(key), GenericArray::from_slice(iv))
+                .apply_keystream(&mut decrypted);
+            Ok(decrypted)
+        } else {
+            anyhow::bail!("Unsupported cipher, only AES-256 and ChaCha20 are supported")
+        }
+    }
+
+    fn verify_block(hmac_key: &[u8], index: u64, data: &[u8], expected: &[u8]) -> Result<()> {
+        let block_key = digest::digest(&digest::SHA512, &[&index.to_le_bytes(), hmac_key].concat());
+        let key = hmac::Key::new(hmac::HMAC_SHA256, block_key.as_ref());
+        hmac::verify(&key, data, expected).map_err(|_| anyhow::anyhow!("Invalid block HMAC"))
+    }
+
+    /// Reads the blocks of KDBX 4, each authenticated with an HMAC.
+    fn read_hmac_blocks(reader: &mut Reader, hmac_key: &[u8]) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        for index in 0u64.. {
+            let expected = reader.take(32)?;
+            let size = reader.u32()?;
+            let block = reader.take(size as usize)?;
+            let authenticated = [&index.to_le_bytes()[..], &size.to_le_bytes(), block].concat();
+            Self::verify_block(hmac_key, index, &authenticated, expected)?;
+            if block.is_empty() {
+                break;
+            }
+            data.extend_from_slice(block);
+        }
+        Ok(data)
+    }
+
+    /// Reads the blocks of KDBX 3, each followed by its SHA-256 hash.
+    fn read_hashed_blocks(reader: &mut Reader) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        loop {
+            let _index = reader.u32()?;
+            let hash = reader.take(32)?;
+            let size = reader.u32()? as usize;
+            if size == 0 {
+                break;
+            }
+            let block = reader.take(size)?;
+            anyhow::ensure!(
+                digest::digest(&digest::SHA256, block).as_ref() == hash,
+                "The database is corrupted"
+            );
+            data.extend_from_slice(block);
+        }
+        Ok(data)
+    }
+
+    fn into_items(mut self) -> Result<Vec<OTPUri>> {
+        let document = roxmltree::Document::parse(&self.xml)?;
+
+        // Protected values must be decrypted in the order of the document,
+        // including the ones we skip, as they share the same stream
+        let mut protected = HashMap::new();
+        for node in document.descendants() {
+            if node.has_tag_name("Value") && node.attribute("Protected") == Some("True") {
+                let mut value = BASE64.decode(node.text().unwrap_or_default().as_bytes())?;
+                self.inner_stream.apply(&mut value);
+                protected.insert(node.id(), Zeroizing::new(String::from_utf8(value)?));
+            }
+        }
+
+        let root = document.root_element();
+        let meta = child(root, "Meta");
+        let recycle_bin = meta
+            .filter(|meta| {
+                child(*meta, "RecycleBinEnabled").and_then(|node| node.text()) == Some("True")
+            })
+            .and_then(|meta| child(meta, "RecycleBinUUID"))
+            .and_then(|node| node.text());
+        let group = child(root, "Root")
+            .and_then(|node| child(node, "Group"))
+            .context("The database has no root group")?;
+
+        let mut items = Vec::new();
+        let mut groups = vec![group];
+        while let Some(group) = groups.pop() {
+            if recycle_bin.is_some()
+                && child(group, "UUID").and_then(|node| node.text()) == recycle_bin
+            {
+                continue;
+            }
+            for node in group.children().filter(|node| node.is_element()) {
+                if node.has_tag_name("Group") {
+                    groups.push(node);
+                } else if node.has_tag_name("Entry") {
+                    let entry = node
+                        .children()
+                        .filter(|field| field.has_tag_name("String"))
+                        .filter_map(|field| {
+                            let key = child(field, "Key")?.text()?;
+                            let value = child(field, "Value")?;
+                            let value = match protected.get(&value.id()) {
+                                Some(value) => value.to_string(),
+                                None => value.text().unwrap_or_default().to_owned(),
+                            };
+                            Some((key, Zeroizing::new(value)))
+                        })
+                        .collect::<HashMap<_, _>>();
+                    match KeePass::entry_otp(&entry) {
+                        Ok(Some(uri)) => items.push(uri),
+                        Ok(None) => (),
+                        Err(err) => tracing::warn!(
+                            "Skipping the entry {:?}, invalid OTP: {err}",
+                            entry.get("Title").map(|title| title.as_str())
+                        ),
+                    }
+                }
+            }
+        }
+        Ok(items)
+    }
+}
+
+fn child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+impl KeePass {
+    fn otp_uri(method: Method, secret: String) -> OTPUri {
+        OTPUri {
+            algorithm: Algorithm::default(),
+            label: String::new(),
+            secret,
+            issuer: String::new(),
+            method,
+            digits: None,
+            period: None,
+            counter: None,
+            pin: None,
+            epoch: None,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        }
+    }
+
+    fn entry_otp(entry: &HashMap<&str, Zeroizing<String>>) -> Result<Option<OTPUri>> {
+        let field = |key: &str| {
+            entry
+                .get(key)
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        let mut uri = if let Some(otp) = field("otp") {
+            let mut uri = OTPUri::from_str(otp)?;
+            // KeePassXC marks Steam tokens with a custom parameter
+            if let Some(index) = uri
+                .extra
+                .iter()
+                .position(|(key, value)| key == "encoder" && value == "steam")
+            {
+                uri.extra.remove(index);
+                uri.method = Method::Steam;
+            }
+            uri
+        } else if let Some(seed) = field("TOTP Seed") {
+            // Formatted as `period;digits`, where the digits are `S` for Steam
+            let settings = field("TOTP Settings").unwrap_or("30;6");
+            let (period, digits) = settings.split_once(';').context("Invalid TOTP settings")?;
+            let is_steam = digits.starts_with('S');
+            let method = if is_steam {
+                Method::Steam
+            } else {
+                Method::TOTP
+            };
+            let mut uri = Self::otp_uri(method, seed.replace(' ', "").to_uppercase());
+            uri.period = Some(period.parse()?);
+            uri.digits = Some(if is_steam {
+                OTP::STEAM_DEFAULT_DIGITS
+            } else {
+                digits.split(';').next().unwrap_or_default().parse()?
+            });
+            uri
+        } else if let Some(secret) = Self::time_otp_secret(&field)? {
+            let mut uri = Self::otp_uri(Method::TOTP, secret);
+            uri.period = field("TimeOtp-Period").map(str::parse).transpose()?;
+            uri.digits = field("TimeOtp-Length").map(str::parse).transpose()?;
+            if let Some(algorithm) = field("TimeOtp-Algorithm") {
+                uri.algorithm =
+                    Algorithm::from_str(&algorithm.replace("HMAC-", "").replace('-', ""))?;
+            }
+            uri
+        } else {
+            return Ok(None);
+        };
+
+        if let Some(title) = field("Title") {
+            uri.issuer = title.to_owned();
+        }
+        if let Some(username) = field("UserName") {
+            uri.label = username.to_owned();
+        }
+        Ok(Some(uri))
+    }
+
+    /// The secret of KeePass' native TOTP, as Base32.
+    fn time_otp_secret<'a>(field: &impl Fn(&str) -> Option<&'a str>) -> Result<Option<String>> {
+        let secret = if let Some(secret) = field("TimeOtp-Secret-Base32") {
+            return Ok(Some(secret.replace(' ', "").to_uppercase()));
+        } else if let Some(secret) = field("TimeOtp-Secret-Hex") {
+            HEXLOWER_PERMISSIVE.decode(secret.replace(' ', "").as_bytes())?
+        } else if let Some(secret) = field("TimeOtp-Secret-Base64") {
+            BASE64.decode(secret.as_bytes())?
+        } else if let Some(secret) = field("TimeOtp-Secret") {
+            secret.as_bytes().to_vec()
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(BASE32_NOPAD.encode(&Zeroizing::new(secret))))
+    }
+}
+
+impl Restorable for KeePass {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "keepass";
+    type Item = OTPUri;
+
+    fn title() -> String {
+        // Translators: This is for restoring the OTPs stored in a KeePass database.
+        gettext("_KeePass")
+    }
+
+    fn subtitle() -> String {
+        gettext("From a KDBX database protected by a password")
+    }
+
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let password = key
+            .filter(|key| !key.is_empty())
+            .context("A KeePass database needs a password")?;
+        Database::open(from, password)?.into_items()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+
+    #[test]
+    fn parse_kdbx3() {
+        let data = std::fs::read("./src/backup/tests/keepass_kdbx3.kdbx").unwrap();
+        let mut items = KeePass::restore_from_data(&data, Some("KeePassTest123")).unwrap();
+        items.sort_by_key(|item| item.issuer());
+
+        // Entries in the history or the recycle bin are skipped
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].issuer(), "Black Mesa");
+        assert_eq!(items[0].account(), "gordon");
+        assert_eq!(items[1].secret(), "5OM4WOOGPLQEF6UGN3CPEOOLWU");
+        assert_eq!(items[2].algorithm(), Algorithm::SHA512);
+        assert_eq!(items[3].method(), Method::Steam);
+
+        assert!(KeePass::restore_from_data(&data, Some("wrong password")).is_err());
+    }
+
+    #[test]
+    fn parse_kdbx4() {
+        let data = std::fs::read("./src/backup/tests/keepass_kdbx4.kdbx").unwrap();
+        let mut items = KeePass::restore_from_data(&data, Some("KeePassTest123")).unwrap();
+        items.sort_by_key(|item| item.issuer());
+
+        // Entries in the history or the recycle bin are skipped
+        assert_eq!(items.len(), 4);
+
+        assert_eq!(items[0].issuer(), "Black Mesa");
+        assert_eq!(items[0].account(), "gordon");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[0].period(), Some(30));
+        assert_eq!(items[0].digits(), Some(6));
+
+        assert_eq!(items[1].issuer(), "GitHub");
+        assert_eq!(items[1].account(), "alyx");
+        assert_eq!(items[1].secret(), "5OM4WOOGPLQEF6UGN3CPEOOLWU");
+        assert_eq!(items[1].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[1].digits(), Some(8));
+        assert_eq!(items[1].period(), Some(60));
+
+        assert_eq!(items[2].issuer(), "Native");
+        assert_eq!(items[2].account(), "eli");
+        assert_eq!(items[2].secret(), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(items[2].algorithm(), Algorithm::SHA512);
+        assert_eq!(items[2].digits(), Some(7));
+        assert_eq!(items[2].period(), Some(45));
+
+        assert_eq!(items[3].issuer(), "Steam");
+        assert_eq!(items[3].account(), "barney");
+        assert_eq!(items[3].secret(), "JRZCL47CMXVOQMNPZR2F7J4RGI");
+        assert_eq!(items[3].method(), Method::Steam);
+        assert_eq!(items[3].digits(), Some(5));
+
+        assert!(KeePass::restore_from_data(&data, Some("wrong password")).is_err());
+        assert!(KeePass::restore_from_data(&data, None).is_err());
+    }
+
+    #[test]
+    fn kdf_bounds() {
+        let kdf = Kdf::Aes {
+            seed: vec![0; 32],
+            rounds: u64::MAX,
+        };
+        assert!(kdf.transform(&[0; 32]).is_err());
+
+        let kdf = Kdf::Argon2 {
+            variant: argon2::Variant::Argon2id,
+            salt: vec![0; 32],
+            parallelism: 2,
+            memory: u64::MAX,
+            iterations: 2,
+            version: 0x13,
+        };
+        assert!(kdf.transform(&[0; 32]).is_err());
+    }
+}
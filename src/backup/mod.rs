@@ -1,10 +1,35 @@
 use anyhow::Result;
+use gtk::glib::clone;
 
 use crate::{
-    models::{keyring, Account, Algorithm, Method, ProvidersModel},
-    utils::spawn_tokio_blocking,
+    models::{keyring, Account, Algorithm, Method, ProvidersModel, OTP},
+    utils::spawn,
 };
 
+/// Returned when restoring from data split across several QR codes, while
+/// some of them were not scanned yet.
+#[derive(Debug)]
+pub struct MissingQRCodes {
+    pub scanned: usize,
+    pub total: usize,
+}
+
+impl std::fmt::Display for MissingQRCodes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Scanned {} of {} QR codes", self.scanned, self.total)
+    }
+}
+
+impl std::error::Error for MissingQRCodes {}
+
+/// An entry of a backup that cannot be restored, shown to the user along the
+/// reason why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedItem {
+    pub name: String,
+    pub reason: String,
+}
+
 pub enum Operation {
     Backup,
     Restore,
@@ -20,7 +45,7 @@ pub trait Restorable: Sized {
     // Used to define the `restore.$identifier` action
     const IDENTIFIER: &'static str;
 
-    type Item: RestorableItem;
+    type Item: RestorableItem + 'static;
 
     fn title() -> String;
     fn subtitle() -> String;
@@ -31,6 +56,15 @@ pub trait Restorable: Sized {
     /// If `key` is `None`, then the implementation should assume that the slice
     /// is unencrypted, and error if it only supports encrypted slices.
     fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>>;
+
+    /// Like [`Restorable::restore_from_data`], along the entries that were
+    /// left out.
+    fn restore_from_data_with_skipped(
+        from: &[u8],
+        key: Option<&str>,
+    ) -> Result<(Vec<Self::Item>, Vec<SkippedItem>)> {
+        Ok((Self::restore_from_data(from, key)?, Vec::new()))
+    }
 }
 
 pub trait RestorableItem {
@@ -43,33 +77,91 @@ pub trait RestorableItem {
     fn digits(&self) -> Option<u32>;
     fn counter(&self) -> Option<u32>;
 
-    fn restore(&self, provider: &ProvidersModel) -> Result<()> {
-        let owned_token = self.secret();
-        let token_exists =
-            spawn_tokio_blocking(async move { keyring::token_exists(&owned_token).await })?;
-        if !token_exists {
-            let provider = provider.find_or_create(
-                &self.issuer(),
-                self.period(),
-                self.method(),
-                None,
-                self.algorithm(),
-                self.digits(),
-                self.counter(),
-                None,
-                None,
-            )?;
-
-            let account =
-                Account::create(&self.account(), &self.secret(), self.counter(), &provider)?;
-            provider.add_account(&account);
-        } else {
-            tracing::info!(
-                "Account {}/{} already exists",
+    /// The PIN of PIN-based methods like mOTP.
+    fn pin(&self) -> Option<String> {
+        None
+    }
+
+    /// The UNIX time from which the time steps are counted, T0 in RFC 6238.
+    fn epoch(&self) -> Option<u64> {
+        None
+    }
+
+    /// The URL of an image representing the provider.
+    fn image(&self) -> Option<String> {
+        None
+    }
+
+    /// The otpauth parameters, like the color or the tags, we keep without
+    /// using them, as a query string.
+    fn otpauth_params(&self) -> Option<String> {
+        None
+    }
+
+    /// The token stored in the keyring for the item, used to tell whether it
+    /// was restored before.
+    fn token(&self) -> String {
+        keyring::token_with_pin(&self.secret(), self.pin().as_deref())
+    }
+
+    /// Whether restoring the item would change the period or the algorithm of
+    /// an existing provider with the same name.
+    fn changes_provider(&self, model: &ProvidersModel) -> bool {
+        model
+            .find_by_name(&self.issuer())
+            // Items with another epoch get a provider of their own
+            .filter(|provider| self.epoch().map_or(true, |epoch| epoch == provider.epoch()))
+            .is_some_and(|provider| {
+                self.period()
+                    .is_some_and(|period| period != provider.period())
+                    || self.algorithm() != provider.algorithm()
+            })
+    }
+
+    /// Creates the account and its provider if needed, without looking for an
+    /// existing account with the same token. The caller leaves out the
+    /// duplicates, see [`RestorableItem::token`].
+    fn restore_unchecked(&self, provider: &ProvidersModel) -> Result<()> {
+        if let Some(digits) = self.digits() {
+            anyhow::ensure!(
+                OTP::is_valid_digits(digits),
+                "Account {}/{} has an invalid number of digits {digits}",
                 self.issuer(),
                 self.account()
             );
         }
+        let pin = self.pin();
+        let provider = provider.find_or_create(
+            &self.issuer(),
+            self.period(),
+            self.method(),
+            None,
+            self.algorithm(),
+            self.digits(),
+            self.counter(),
+            None,
+            None,
+            self.epoch(),
+        )?;
+        if let Some(image) = self.image().filter(|_| provider.image_uri().is_none()) {
+            spawn(clone!(@weak provider => async move {
+                if let Err(err) = provider.set_image_from_url(&image).await {
+                    tracing::warn!("Failed to set the provider image from {image}: {err}");
+                }
+            }));
+        }
+
+        let account = Account::create(
+            &self.account(),
+            &self.secret(),
+            pin.as_deref(),
+            self.counter(),
+            &provider,
+        )?;
+        if let Some(params) = self.otpauth_params() {
+            account.update_otpauth_params(Some(&params))?;
+        }
+        provider.add_account(&account);
         Ok(())
     }
 }
@@ -77,6 +169,10 @@ pub trait RestorableItem {
 pub trait Backupable: Sized {
     /// Indicates that the GUI might need to prompt for a password.
     const ENCRYPTABLE: bool = false;
+
+    /// Indicates that the GUI needs to show the backup as QR codes, one per
+    /// line, instead of saving it into a file.
+    const SCANNABLE: bool = false;
     // Used to define the `backup.$identifier` action
     const IDENTIFIER: &'static str;
 
@@ -84,17 +180,47 @@ pub trait Backupable: Sized {
     fn subtitle() -> String;
     // if no key is provided the backup code should save it as plain text
     fn backup(provider: &ProvidersModel, key: Option<&str>) -> Result<Vec<u8>>;
+    /// The number of accounts left out of the backup as the format cannot
+    /// hold them.
+    fn skipped_accounts(_provider: &ProvidersModel) -> u32 {
+        0
+    }
 }
 
 mod aegis;
 mod andotp;
+mod authenticatorpro;
 mod bitwarden;
+mod crypto;
+mod csv;
+mod ente;
 mod freeotp;
+mod freeotp_backup;
 mod freeotp_json;
 mod google;
+mod keepass;
 mod legacy;
+mod native;
+mod pass;
 mod raivootp;
+mod steam;
+mod twofas;
 pub use self::{
-    aegis::Aegis, andotp::AndOTP, bitwarden::Bitwarden, freeotp::FreeOTP,
-    freeotp_json::FreeOTPJSON, google::Google, legacy::LegacyAuthenticator, raivootp::RaivoOTP,
+    aegis::Aegis,
+    andotp::AndOTP,
+    authenticatorpro::AuthenticatorPro,
+    bitwarden::Bitwarden,
+    csv::{Csv, CsvColumn},
+    ente::Ente,
+    freeotp::FreeOTP,
+    freeotp_backup::FreeOTPBackup,
+    freeotp_json::FreeOTPJSON,
+    google::Google,
+    keepass::KeePass,
+    legacy::LegacyAuthenticator,
+    native::Native,
+    pass::PasswordStore,
+    raivootp::RaivoOTP,
+    steam::Steam,
+    twofas::TwoFAS,
 };
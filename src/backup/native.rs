@@ -0,0 +1,521 @@
+//! Authenticator Backup Module
+//!
+//! Unlike the formats of the other apps, this one keeps everything
+//! Authenticator knows about the providers and the accounts, including the
+//! cached favicons and the HOTP counters.
+//!
+//! A backup is made of a header followed by a JSON payload encrypted with
+//! XChaCha20-Poly1305, using a key derived from the password with Argon2id:
+//! - the magic bytes `AUTHENTICATOR`
+//! - the version of the payload, as a big endian u16
+//! - the Argon2id memory cost in KiB, iterations and lanes, as big endian u32
+//! - the salt and the nonce
+//!
+//! The whole header is authenticated along the payload. Backups of older
+//! versions are upgraded before being restored, see [`Native::upgrade`].
+
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use gettextrs::gettext;
+use gtk::{glib, prelude::*};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use super::{crypto, Backupable, Restorable, RestorableItem};
+use crate::models::{Account, Algorithm, Method, Provider, ProvidersModel, FAVICONS_PATH, OTP};
+
+pub struct Native;
+
+#[derive(Serialize, Deserialize)]
+struct Payload {
+    providers: Vec<NativeProvider>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NativeProvider {
+    name: String,
+    website: Option<String>,
+    help_url: Option<String>,
+    period: u32,
+    digits: u32,
+    default_counter: u32,
+    algorithm: Algorithm,
+    method: Method,
+    ocra_suite: Option<String>,
+    time_offset: i64,
+    epoch: u64,
+    group_size: u32,
+    alphabet: Option<String>,
+    image: Option<NativeImage>,
+    accounts: Vec<NativeAccount>,
+}
+
+/// The cached favicon of a provider, as base64 encoded PNG or SVG files.
+#[derive(Serialize, Deserialize)]
+struct NativeImage {
+    small: String,
+    large: String,
+}
+
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+struct NativeAccount {
+    #[zeroize(skip)]
+    name: String,
+    secret: String,
+    pin: Option<String>,
+    #[zeroize(skip)]
+    counter: u32,
+    #[zeroize(skip)]
+    otpauth_params: Option<String>,
+}
+
+/// An account along the provider it belongs to.
+pub struct NativeItem {
+    provider: Rc<NativeProvider>,
+    account: NativeAccount,
+}
+
+impl NativeImage {
+    fn read(icon_name: &str) -> Option<Self> {
+        let read = |size| {
+            std::fs::read(FAVICONS_PATH.join(format!("{icon_name}_{size}x{size}")))
+                .ok()
+                .map(|data| data_encoding::BASE64.encode(&data))
+        };
+        Some(Self {
+            small: read(32)?,
+            large: read(96)?,
+        })
+    }
+
+    /// Saves the images in the favicons cache, like the downloaded ones.
+    fn write(&self, provider: &Provider) -> Result<()> {
+        let icon_name = format!("{}_{}", provider.id(), provider.name().replace(' ', "_"));
+        let icon_name = glib::base64_encode(icon_name.as_bytes());
+        for (size, data) in [(32, &self.small), (96, &self.large)] {
+            std::fs::write(
+                FAVICONS_PATH.join(format!("{icon_name}_{size}x{size}")),
+                data_encoding::BASE64.decode(data.as_bytes())?,
+            )?;
+        }
+        provider.set_image_uri(icon_name.to_string());
+        Ok(())
+    }
+}
+
+impl NativeProvider {
+    fn new(provider: &Provider) -> Self {
+        let accounts = provider.accounts_model();
+        Self {
+            name: provider.name(),
+            website: provider.website(),
+            help_url: provider.help_url(),
+            period: provider.period(),
+            digits: provider.digits(),
+            default_counter: provider.default_counter(),
+            algorithm: provider.algorithm(),
+            method: provider.method(),
+            ocra_suite: provider.ocra_suite(),
+            time_offset: provider.time_offset(),
+            epoch: provider.epoch(),
+            group_size: provider.group_size(),
+            alphabet: provider.alphabet(),
+            image: provider
+                .image_uri()
+                .filter(|uri| uri != "invalid")
+                .and_then(|uri| NativeImage::read(&uri)),
+            accounts: (0..accounts.n_items())
+                .map(|i| {
+                    let account = accounts.item(i).and_downcast::<Account>().unwrap();
+                    NativeAccount {
+                        name: account.name(),
+                        secret: account.otp().secret(),
+                        pin: account.otp().pin().map(ToOwned::to_owned),
+                        counter: account.counter(),
+                        otpauth_params: account.otpauth_params(),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Native {
+    const MAGIC: &'static [u8] = b"AUTHENTICATOR";
+    const VERSION: u16 = 1;
+    const SALT_SIZE: usize = 16;
+    const NONCE_SIZE: usize = 24;
+    const KEY_SIZE: usize = 32;
+    const HEADER_SIZE: usize = Self::MAGIC.len() + 2 + 3 * 4 + Self::SALT_SIZE + Self::NONCE_SIZE;
+    // Argon2id parameters of new backups, older ones keep theirs in the header
+    const MEMORY_COST: u32 = 65_536;
+    const TIME_COST: u32 = 3;
+    const LANES: u32 = 4;
+    // The header isn't authenticated until the key is derived, bound what a
+    // crafted one can ask for
+    const MAX_MEMORY_COST: u32 = 1_048_576;
+    const MAX_TIME_COST: u32 = 64;
+    const MAX_LANES: u32 = 16;
+
+    fn derive_key(
+        password: &str,
+        salt: &[u8],
+        memory_cost: u32,
+        time_cost: u32,
+        lanes: u32,
+    ) -> Result<Zeroizing<Vec<u8>>> {
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            mem_cost: memory_cost,
+            time_cost,
+            lanes,
+            hash_length: Self::KEY_SIZE as u32,
+            ..argon2::Config::default()
+        };
+        Ok(Zeroizing::new(argon2::hash_raw(
+            password.as_bytes(),
+            salt,
+            &config,
+        )?))
+    }
+
+    fn encrypt(payload: &Payload, password: &str) -> Result<Vec<u8>> {
+        let mut salt = [0u8; Self::SALT_SIZE];
+        let mut nonce = [0u8; Self::NONCE_SIZE];
+        let mut rng = rand::thread_rng();
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+
+        let mut data = Vec::with_capacity(Self::HEADER_SIZE);
+        data.extend_from_slice(Self::MAGIC);
+        data.extend_from_slice(&Self::VERSION.to_be_bytes());
+        data.extend_from_slice(&Self::MEMORY_COST.to_be_bytes());
+        data.extend_from_slice(&Self::TIME_COST.to_be_bytes());
+        data.extend_from_slice(&Self::LANES.to_be_bytes());
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+
+        let key = Self::derive_key(
+            password,
+            &salt,
+            Self::MEMORY_COST,
+            Self::TIME_COST,
+            Self::LANES,
+        )?;
+        let plaintext = Zeroizing::new(serde_json::to_vec(payload)?);
+        let ciphertext = crypto::xchacha20_poly1305_encrypt(&key, &nonce, &data, &plaintext)?;
+        data.extend_from_slice(&ciphertext);
+        Ok(data)
+    }
+
+    fn decrypt(data: &[u8], password: &str) -> Result<Payload> {
+        anyhow::ensure!(data.starts_with(Self::MAGIC), "Not an Authenticator backup");
+        anyhow::ensure!(data.len() > Self::HEADER_SIZE, "The backup is too short");
+        let (header, ciphertext) = data.split_at(Self::HEADER_SIZE);
+
+        let (version, params) = header[Self::MAGIC.len()..].split_at(2);
+        let version = u16::from_be_bytes(version.try_into()?);
+        let (params, salt) = params.split_at(3 * 4);
+        let (salt, nonce) = salt.split_at(Self::SALT_SIZE);
+        let param = |i: usize| u32::from_be_bytes(params[4 * i..4 * (i + 1)].try_into().unwrap());
+
+        let (memory_cost, time_cost, lanes) = (param(0), param(1), param(2));
+        anyhow::ensure!(
+            memory_cost <= Self::MAX_MEMORY_COST
+                && time_cost <= Self::MAX_TIME_COST
+                && lanes <= Self::MAX_LANES,
+            "Unreasonable key derivation parameters"
+        );
+
+        let key = Self::derive_key(password, salt, memory_cost, time_cost, lanes)?;
+        let plaintext = Zeroizing::new(
+            crypto::xchacha20_poly1305_decrypt(&key, nonce, header, ciphertext)
+                .context("Cannot decrypt the backup, is the password correct?")?,
+        );
+        let payload = serde_json::from_slice(&plaintext)?;
+        Ok(serde_json::from_value(Self::upgrade(version, payload)?)?)
+    }
+
+    /// Turns the payload of a backup into the one of the current version.
+    ///
+    /// When the payload changes, bump `VERSION` and add a step converting the
+    /// payload of the previous version here, so the old backups can still be
+    /// restored.
+    fn upgrade(version: u16, payload: serde_json::Value) -> Result<serde_json::Value> {
+        match version {
+            Self::VERSION => Ok(payload),
+            version if version > Self::VERSION => anyhow::bail!(
+                "The backup was made by a newer version of Authenticator, version {version}"
+            ),
+            version => anyhow::bail!("Unsupported backup version {version}"),
+        }
+    }
+}
+
+impl Backupable for Native {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "native";
+
+    fn title() -> String {
+        // Translators: This is for making a complete backup of Authenticator, icons included.
+        gettext("Authenticator (_Full)")
+    }
+
+    fn subtitle() -> String {
+        gettext("Into an encrypted file keeping every detail, including the icons")
+    }
+
+    fn backup(model: &ProvidersModel, key: Option<&str>) -> Result<Vec<u8>> {
+        let password = key
+            .filter(|key| !key.is_empty())
+            .context("Authenticator backups are always encrypted, a password is needed")?;
+        let providers = (0..model.n_items())
+            .map(|i| model.item(i).and_downcast::<Provider>().unwrap())
+            .filter(|provider| provider.has_accounts())
+            .map(|provider| NativeProvider::new(&provider))
+            .collect();
+        Self::encrypt(&Payload { providers }, password)
+    }
+}
+
+impl Restorable for Native {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "native";
+    type Item = NativeItem;
+
+    fn title() -> String {
+        // Translators: This is for restoring a complete backup of Authenticator.
+        gettext("Authenticator (_Full)")
+    }
+
+    fn subtitle() -> String {
+        gettext("From an encrypted file made by Authenticator")
+    }
+
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let password = key
+            .filter(|key| !key.is_empty())
+            .context("Authenticator backups are always encrypted, a password is needed")?;
+        let payload = Self::decrypt(from, password)?;
+
+        let mut items = Vec::new();
+        for mut provider in payload.providers {
+            let accounts = std::mem::take(&mut provider.accounts);
+            let provider = Rc::new(provider);
+            items.extend(accounts.into_iter().map(|account| NativeItem {
+                provider: provider.clone(),
+                account,
+            }));
+        }
+        Ok(items)
+    }
+}
+
+impl RestorableItem for NativeItem {
+    fn account(&self) -> String {
+        self.account.name.clone()
+    }
+
+    fn issuer(&self) -> String {
+        self.provider.name.clone()
+    }
+
+    fn secret(&self) -> String {
+        self.account.secret.clone()
+    }
+
+    fn period(&self) -> Option<u32> {
+        Some(self.provider.period)
+    }
+
+    fn method(&self) -> Method {
+        self.provider.method
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        self.provider.algorithm
+    }
+
+    fn digits(&self) -> Option<u32> {
+        Some(self.provider.digits)
+    }
+
+    fn counter(&self) -> Option<u32> {
+        Some(self.account.counter)
+    }
+
+    fn pin(&self) -> Option<String> {
+        self.account.pin.clone()
+    }
+
+    fn epoch(&self) -> Option<u64> {
+        Some(self.provider.epoch)
+    }
+
+    fn otpauth_params(&self) -> Option<String> {
+        self.account.otpauth_params.clone()
+    }
+
+    /// Restores the provider as it was, instead of only the fields the other
+    /// formats know about.
+    fn restore_unchecked(&self, model: &ProvidersModel) -> Result<()> {
+        let data = &self.provider;
+        anyhow::ensure!(
+            OTP::is_valid_digits(data.digits),
+            "Invalid number of digits {}",
+            data.digits
+        );
+        // The other accounts of an existing provider rely on its settings
+        let is_new = model
+            .find_by_name_and_epoch(&data.name, data.epoch)
+            .is_none();
+        let provider = model.find_or_create(
+            &data.name,
+            Some(data.period),
+            data.method,
+            data.website.clone(),
+            data.algorithm,
+            Some(data.digits),
+            Some(data.default_counter),
+            data.help_url.clone(),
+            None,
+            Some(data.epoch),
+        )?;
+        if is_new {
+            provider.update_ocra_suite(data.ocra_suite.as_deref())?;
+            provider.update_time_offset(data.time_offset)?;
+            provider.update_rendering(data.group_size, data.alphabet.as_deref())?;
+        }
+        if let Some(image) = data
+            .image
+            .as_ref()
+            .filter(|_| provider.image_uri().is_none())
+        {
+            if let Err(err) = image.write(&provider) {
+                tracing::warn!("Failed to restore the image of {}: {err}", data.name);
+            }
+        }
+
+        let account = Account::create(
+            &self.account.name,
+            &self.account.secret,
+            self.account.pin.as_deref(),
+            Some(self.account.counter),
+            &provider,
+        )?;
+        if let Some(ref params) = self.account.otpauth_params {
+            account.update_otpauth_params(Some(params))?;
+        }
+        provider.add_account(&account);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Payload {
+        Payload {
+            providers: vec![NativeProvider {
+                name: "Black Mesa".to_owned(),
+                website: Some("https://blackmesa.example".to_owned()),
+                help_url: Some("https://blackmesa.example/help".to_owned()),
+                period: 30,
+                digits: 8,
+                default_counter: 1,
+                algorithm: Algorithm::SHA256,
+                method: Method::HOTP,
+                ocra_suite: None,
+                time_offset: -5,
+                epoch: 0,
+                group_size: 4,
+                alphabet: None,
+                image: Some(NativeImage {
+                    small: "iVBORw0KGgo=".to_owned(),
+                    large: "iVBORw0KGgo=".to_owned(),
+                }),
+                accounts: vec![
+                    NativeAccount {
+                        name: "gordon".to_owned(),
+                        secret: "5OM4WOOGPLQEF6UGN3CPEOOLWU".to_owned(),
+                        pin: None,
+                        counter: 13,
+                        otpauth_params: Some("color=FF8800".to_owned()),
+                    },
+                    NativeAccount {
+                        name: "barney".to_owned(),
+                        secret: "7ELGJSGXNCCTV3O6LKJWYFV2RA".to_owned(),
+                        pin: Some("1234".to_owned()),
+                        counter: 0,
+                        otpauth_params: None,
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let data = std::fs::read("./src/backup/tests/authenticator_native.bin").unwrap();
+        let items = Native::restore_from_data(&data, Some("AuthenticatorTest123")).unwrap();
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].account(), "gordon");
+        assert_eq!(items[0].issuer(), "Black Mesa");
+        assert_eq!(items[0].secret(), "5OM4WOOGPLQEF6UGN3CPEOOLWU");
+        assert_eq!(items[0].method(), Method::HOTP);
+        assert_eq!(items[0].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[0].digits(), Some(8));
+        assert_eq!(items[0].counter(), Some(13));
+        assert_eq!(items[0].otpauth_params().as_deref(), Some("color=FF8800"));
+        assert_eq!(items[0].provider.time_offset, -5);
+        assert_eq!(items[0].provider.group_size, 4);
+        assert!(items[0].provider.image.is_some());
+
+        assert_eq!(items[1].account(), "barney");
+        assert_eq!(items[1].issuer(), "Black Mesa");
+        assert_eq!(items[1].pin().as_deref(), Some("1234"));
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = Native::encrypt(&payload(), "AuthenticatorTest123").unwrap();
+        let payload = Native::decrypt(&data, "AuthenticatorTest123").unwrap();
+        assert_eq!(payload.providers.len(), 1);
+        assert_eq!(payload.providers[0].accounts[0].name, "gordon");
+        assert_eq!(
+            payload.providers[0].help_url.as_deref(),
+            Some("https://blackmesa.example/help")
+        );
+
+        assert!(Native::decrypt(&data, "wrong password").is_err());
+        assert!(Native::restore_from_data(&data, None).is_err());
+    }
+
+    #[test]
+    fn tampered_header() {
+        let mut data = Native::encrypt(&payload(), "AuthenticatorTest123").unwrap();
+        // Claim the backup comes from a newer version
+        data[Native::MAGIC.len() + 1] += 1;
+        assert!(Native::decrypt(&data, "AuthenticatorTest123").is_err());
+
+        // Ask for more memory than the key derivation is allowed to use
+        let mut data = Native::encrypt(&payload(), "AuthenticatorTest123").unwrap();
+        let memory_cost = Native::MAGIC.len() + 2;
+        data[memory_cost..memory_cost + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(Native::decrypt(&data, "AuthenticatorTest123").is_err());
+    }
+
+    #[test]
+    fn upgrade() {
+        let payload = serde_json::json!({ "providers": [] });
+        assert!(Native::upgrade(Native::VERSION, payload.clone()).is_ok());
+        assert!(Native::upgrade(Native::VERSION + 1, payload.clone()).is_err());
+        assert!(Native::upgrade(0, payload).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+//! Password Store Import/Export Module
+//!
+//! With the pass-otp extension, an entry of [pass](https://www.passwordstore.org/)
+//! holds an otpauth URI on one of its lines. Every entry is a file encrypted
+//! with GnuPG, so decrypting them goes through the gpg executable and its
+//! agent, which takes care of asking for the passphrase of the key.
+//!
+//! The path of an entry, like `github.com/alice`, gives the provider and the
+//! account names.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
+use gettextrs::gettext;
+use gtk::prelude::*;
+use zeroize::Zeroizing;
+
+use super::{Restorable, SkippedItem};
+use crate::models::{Account, OTPUri, Provider, ProvidersModel};
+
+pub struct PasswordStore {
+    path: PathBuf,
+    // Replaces the default GnuPG home directory
+    gnupg_home: Option<PathBuf>,
+}
+
+impl PasswordStore {
+    const EXTENSION: &'static str = "gpg";
+    // Lists the recipients of the store, one per line
+    const GPG_ID: &'static str = ".gpg-id";
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            gnupg_home: None,
+        }
+    }
+
+    pub fn backup_title() -> String {
+        // Translators: This is for making a backup into the pass password manager.
+        gettext("_Password Store")
+    }
+
+    pub fn backup_subtitle() -> String {
+        gettext("Into a pass directory, encrypted for a GnuPG key")
+    }
+
+    fn gpg(&self) -> Command {
+        let mut command = Command::new("gpg");
+        // `--yes` is left out on purpose, so gpg refuses to overwrite an existing
+        // entry, which may hold a password
+        command.args(["--quiet", "--batch"]);
+        if let Some(ref home) = self.gnupg_home {
+            command.env("GNUPGHOME", home);
+        }
+        command
+    }
+
+    fn decrypt(&self, path: &Path) -> Result<Zeroizing<Vec<u8>>> {
+        let output = self
+            .gpg()
+            .arg("--decrypt")
+            .arg(path)
+            .stdin(Stdio::null())
+            .output()
+            .context("Failed to run gpg")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "gpg failed to decrypt {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        Ok(Zeroizing::new(output.stdout))
+    }
+
+    fn encrypt(&self, path: &Path, content: &[u8], recipients: &[String]) -> Result<()> {
+        let mut command = self.gpg();
+        command.arg("--encrypt");
+        for recipient in recipients {
+            command.args(["--recipient", recipient]);
+        }
+        let mut child = command
+            .arg("--output")
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run gpg")?;
+        child
+            .stdin
+            .take()
+            .context("Failed to write to gpg")?
+            .write_all(content)?;
+        let output = child.wait_with_output()?;
+        anyhow::ensure!(
+            output.status.success(),
+            "gpg failed to encrypt {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        Ok(())
+    }
+
+    /// Every entry of the store, skipping the hidden files and directories
+    /// like `.git`.
+    fn entries(directory: &Path, entries: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+            if path.is_dir() {
+                Self::entries(&path, entries)?;
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == Self::EXTENSION)
+            {
+                entries.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypts the entries of the store holding an otpauth URI. Entries
+    /// without one are ignored, the ones failing to decrypt or to parse are
+    /// returned as skipped.
+    pub fn restore(&self) -> Result<(Vec<OTPUri>, Vec<SkippedItem>)> {
+        let mut entries = Vec::new();
+        Self::entries(&self.path, &mut entries)?;
+        entries.sort();
+
+        let mut items = Vec::new();
+        let mut skipped = Vec::new();
+        for entry in entries {
+            let name = entry.strip_prefix(&self.path)?.with_extension("");
+            let uris = self
+                .decrypt(&entry)
+                .and_then(|content| Self::restore_from_data(&content, None));
+            let mut uris = match uris {
+                Ok(uris) => uris,
+                Err(err) => {
+                    tracing::warn!("Skipping the entry {}: {err}", entry.display());
+                    skipped.push(SkippedItem {
+                        name: name.to_string_lossy().into_owned(),
+                        reason: err.to_string(),
+                    });
+                    continue;
+                }
+            };
+            if uris.is_empty() {
+                continue;
+            }
+            // pass-otp only uses the first URI of an entry
+            let mut uri = uris.swap_remove(0);
+
+            let account = name
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            match name
+                .parent()
+                .and_then(Path::file_name)
+                .map(|parent| parent.to_string_lossy().into_owned())
+            {
+                Some(issuer) => {
+                    uri.issuer = issuer;
+                    uri.label = account;
+                }
+                // At the root of the store, the name of the entry is the provider's
+                None => uri.issuer = account,
+            }
+            items.push(uri);
+        }
+        Ok((items, skipped))
+    }
+
+    /// The entry of every account, its path in the store along its otpauth
+    /// URI. Collected beforehand as encrypting them takes a while.
+    pub fn backup_entries(&self, model: &ProvidersModel) -> Vec<(PathBuf, Zeroizing<String>)> {
+        let mut entries = Vec::new();
+        for i in 0..model.n_items() {
+            let provider = model.item(i).and_downcast::<Provider>().unwrap();
+            let accounts = provider.accounts_model();
+
+            for j in 0..accounts.n_items() {
+                let account = accounts.item(j).and_downcast::<Account>().unwrap();
+                let uri: String = account.otp_uri().into();
+                let path = self
+                    .path
+                    .join(Self::file_name(&provider.name()))
+                    // Names like `alice@example.com` already look like they have an extension
+                    .join(format!(
+                        "{}.{}",
+                        Self::file_name(&account.name()),
+                        Self::EXTENSION
+                    ));
+                entries.push((path, Zeroizing::new(uri + "\n")));
+            }
+        }
+        entries
+    }
+
+    /// Writes the `entries`, encrypted for `recipient` or, if there is none,
+    /// for the recipients listed in the `.gpg-id` file of the store.
+    ///
+    /// The entries already in the store are left untouched as they may hold
+    /// a password. Returns the number of entries written and skipped.
+    pub fn backup(
+        &self,
+        entries: &[(PathBuf, Zeroizing<String>)],
+        recipient: Option<&str>,
+    ) -> Result<(usize, usize)> {
+        let recipients = match recipient.filter(|recipient| !recipient.is_empty()) {
+            Some(recipient) => vec![recipient.to_owned()],
+            None => std::fs::read_to_string(self.path.join(Self::GPG_ID))
+                .context("No recipient was given and the directory has no .gpg-id file")?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(ToOwned::to_owned)
+                .collect(),
+        };
+        anyhow::ensure!(!recipients.is_empty(), "No recipient to encrypt for");
+
+        let mut skipped = 0;
+        for (path, content) in entries {
+            if path.exists() {
+                tracing::warn!("Skipping the existing entry {}", path.display());
+                skipped += 1;
+                continue;
+            }
+            self.write_entry(path, content, &recipients)?;
+        }
+        Ok((entries.len() - skipped, skipped))
+    }
+
+    fn write_entry(&self, path: &Path, content: &str, recipients: &[String]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.encrypt(path, content.as_bytes(), recipients)
+    }
+
+    /// Makes a name usable as a file name, as it can contain slashes.
+    fn file_name(name: &str) -> String {
+        let name = name.replace('/', "-");
+        let name = name.trim().trim_start_matches('.');
+        if name.is_empty() {
+            gettext("Unknown")
+        } else {
+            name.to_owned()
+        }
+    }
+}
+
+impl Restorable for PasswordStore {
+    const ENCRYPTABLE: bool = false;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "pass";
+    type Item = OTPUri;
+
+    fn title() -> String {
+        // Translators: This is for restoring the accounts stored in the pass password manager.
+        gettext("Pa_ssword Store")
+    }
+
+    fn subtitle() -> String {
+        gettext("From a pass directory, using the pass-otp extension")
+    }
+
+    /// Restores the otpauth URIs of a decrypted entry.
+    fn restore_from_data(from: &[u8], _key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let content = std::str::from_utf8(from)?;
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("otpauth://"))
+            .map(OTPUri::from_str)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+    use crate::models::Method;
+
+    #[test]
+    fn parse() {
+        let data = b"hunter2\nlogin: alice\notpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub\n";
+        let items = PasswordStore::restore_from_data(data, None).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+
+        let items = PasswordStore::restore_from_data(b"hunter2\n", None).unwrap();
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    #[ignore = "requires gpg"]
+    fn round_trip() {
+        let root = tempfile::tempdir().unwrap();
+        let gnupg_home = root.path().join("gnupg");
+        let path = root.path().join("store");
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::create_dir_all(&gnupg_home).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&gnupg_home, std::fs::Permissions::from_mode(0o700)).unwrap();
+        }
+
+        let store = PasswordStore {
+            path: path.clone(),
+            gnupg_home: Some(gnupg_home.clone()),
+        };
+        let status = store
+            .gpg()
+            .args(["--passphrase", "", "--quick-generate-key"])
+            .args([
+                "Authenticator <test@example.com>",
+                "default",
+                "default",
+                "never",
+            ])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let recipients = ["test@example.com".to_owned()];
+
+        for (name, content) in [
+            (
+                "github.com/alice",
+                "hunter2\notpauth://totp/GitHub:bob?secret=JBSWY3DPEHPK3PXP&issuer=GitHub\n",
+            ),
+            (
+                "steam",
+                "otpauth://totp/Steam:barney?secret=JRZCL47CMXVOQMNPZR2F7J4RGI&issuer=Steam&encoder=steam\n",
+            ),
+            ("email/work", "correct horse battery staple\n"),
+            ("broken", "otpauth://totp/Broken?issuer=Broken\n"),
+            (
+                ".git/ignored",
+                "otpauth://totp/Ignored?secret=JBSWY3DPEHPK3PXP\n",
+            ),
+        ] {
+            let entry = path.join(name).with_extension(PasswordStore::EXTENSION);
+            store.write_entry(&entry, content, &recipients).unwrap();
+        }
+
+        let (items, skipped) = store.restore().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].issuer(), "github.com");
+        assert_eq!(items[0].account(), "alice");
+        assert_eq!(items[0].secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[1].issuer(), "steam");
+        assert_eq!(items[1].account(), "barney");
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].name, "broken");
+
+        // Existing entries are never overwritten
+        let entries = [
+            (
+                path.join("email/work.gpg"),
+                Zeroizing::new("otpauth://totp/Work?secret=JBSWY3DPEHPK3PXP\n".to_owned()),
+            ),
+            (
+                path.join("email/home.gpg"),
+                Zeroizing::new("otpauth://totp/Home?secret=JBSWY3DPEHPK3PXP\n".to_owned()),
+            ),
+        ];
+        assert_eq!(
+            store.backup(&entries, Some("test@example.com")).unwrap(),
+            (1, 1)
+        );
+        let content = store.decrypt(&path.join("email/work.gpg")).unwrap();
+        assert_eq!(&*content, b"correct horse battery staple\n");
+        assert!(store.write_entry(&entries[0].0, "", &recipients).is_err());
+
+        let _ = Command::new("gpgconf")
+            .args(["--kill", "gpg-agent"])
+            .env("GNUPGHOME", &gnupg_home)
+            .status();
+    }
+
+    #[test]
+    fn file_name() {
+        assert_eq!(PasswordStore::file_name("GitHub"), "GitHub");
+        assert_eq!(PasswordStore::file_name("a/b"), "a-b");
+        assert_eq!(PasswordStore::file_name("../etc"), "-etc");
+    }
+}
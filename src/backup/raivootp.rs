@@ -68,7 +68,7 @@ impl RestorableItem for Item {
     fn period(&self) -> Option<u32> {
         match self.method() {
             Method::TOTP => self.period,
-            Method::HOTP | Method::Steam => None,
+            Method::HOTP | Method::Steam | Method::MOTP | Method::Yandex | Method::OCRA => None,
         }
     }
 
@@ -87,7 +87,7 @@ impl RestorableItem for Item {
     fn counter(&self) -> Option<u32> {
         match self.method() {
             Method::HOTP => self.counter,
-            Method::TOTP | Method::Steam => None,
+            Method::TOTP | Method::Steam | Method::MOTP | Method::Yandex | Method::OCRA => None,
         }
     }
 }
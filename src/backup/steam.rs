@@ -0,0 +1,228 @@
+//! Steam Desktop Authenticator Import Module
+//!
+//! SDA keeps a `.maFile` per account in its `maFiles` folder, along with a
+//! `manifest.json` describing how they were encrypted when a passkey is set.
+//! Encrypted maFiles can only be restored from a ZIP of the whole folder.
+
+use std::io::{Cursor, Read};
+
+use anyhow::{Context, Result};
+use data_encoding::{BASE32_NOPAD, BASE64};
+use gettextrs::gettext;
+use serde::Deserialize;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+use zip::ZipArchive;
+
+use super::{crypto, Restorable, RestorableItem};
+use crate::models::{Algorithm, Method, OTP};
+
+pub struct Steam;
+
+/// The parts of a maFile we need.
+#[derive(Deserialize, Zeroize, ZeroizeOnDrop)]
+struct MaFile {
+    // Base64 encoded
+    shared_secret: String,
+    #[zeroize(skip)]
+    account_name: String,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    encrypted: bool,
+    entries: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    encryption_iv: Option<String>,
+    encryption_salt: Option<String>,
+    filename: String,
+}
+
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct Item {
+    #[zeroize(skip)]
+    account: String,
+    secret: String,
+}
+
+impl TryFrom<MaFile> for Item {
+    type Error = anyhow::Error;
+
+    fn try_from(ma_file: MaFile) -> Result<Self> {
+        let secret = BASE64
+            .decode(ma_file.shared_secret.as_bytes())
+            .context("The shared secret is not valid Base64")?;
+        Ok(Self {
+            account: ma_file.account_name.clone(),
+            secret: BASE32_NOPAD.encode(&secret),
+        })
+    }
+}
+
+impl RestorableItem for Item {
+    fn account(&self) -> String {
+        self.account.clone()
+    }
+
+    fn issuer(&self) -> String {
+        "Steam".to_owned()
+    }
+
+    fn secret(&self) -> String {
+        self.secret.clone()
+    }
+
+    fn period(&self) -> Option<u32> {
+        Some(OTP::STEAM_DEFAULT_PERIOD)
+    }
+
+    fn method(&self) -> Method {
+        Method::Steam
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::SHA1
+    }
+
+    fn digits(&self) -> Option<u32> {
+        Some(OTP::STEAM_DEFAULT_DIGITS)
+    }
+
+    fn counter(&self) -> Option<u32> {
+        None
+    }
+}
+
+impl Steam {
+    // Key derivation parameters used by SDA
+    const PBKDF2_ITERATIONS: u32 = 50_000;
+    const KEY_SIZE: usize = 32;
+
+    fn decrypt(data: &[u8], entry: &ManifestEntry, passkey: &str) -> Result<Vec<u8>> {
+        let salt = entry
+            .encryption_salt
+            .as_deref()
+            .context("Missing the encryption salt")?;
+        let iv = entry
+            .encryption_iv
+            .as_deref()
+            .context("Missing the encryption IV")?;
+
+        let mut key = [0u8; Self::KEY_SIZE];
+        crypto::pbkdf2(
+            ring::pbkdf2::PBKDF2_HMAC_SHA1,
+            passkey.as_bytes(),
+            &BASE64.decode(salt.as_bytes())?,
+            Self::PBKDF2_ITERATIONS,
+            &mut key,
+        )?;
+        let ciphertext = BASE64.decode(data.trim_ascii())?;
+        let plaintext = crypto::aes_cbc_decrypt(&key, &BASE64.decode(iv.as_bytes())?, &ciphertext)
+            .context("Failed to decrypt the maFile, is the passkey correct?");
+        key.zeroize();
+        plaintext
+    }
+
+    fn read_file(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Vec<u8>> {
+        // The folder may or may not be part of the archive
+        let path = archive
+            .file_names()
+            .find(|path| path.rsplit('/').next() == Some(name))
+            .with_context(|| format!("Could not find {name} in the archive"))?
+            .to_owned();
+        let mut data = Vec::new();
+        archive.by_name(&path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn restore_from_zip(from: &[u8], passkey: Option<&str>) -> Result<Vec<Item>> {
+        let mut archive = ZipArchive::new(Cursor::new(from))?;
+        let manifest: Manifest =
+            serde_json::from_slice(&Self::read_file(&mut archive, "manifest.json")?)?;
+
+        manifest
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut data = Self::read_file(&mut archive, &entry.filename)?;
+                if manifest.encrypted {
+                    let passkey =
+                        passkey.context("The maFiles are encrypted, a passkey is needed")?;
+                    let plaintext = Self::decrypt(&data, entry, passkey)?;
+                    data.zeroize();
+                    data = plaintext;
+                }
+                let ma_file = serde_json::from_slice::<MaFile>(&data);
+                data.zeroize();
+                Item::try_from(ma_file?)
+            })
+            .collect()
+    }
+}
+
+impl Restorable for Steam {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "steam";
+    type Item = Item;
+
+    fn title() -> String {
+        gettext("Steam Desktop Authenticator")
+    }
+
+    fn subtitle() -> String {
+        gettext("From a maFile or a ZIP of the maFiles folder, the passkey is only needed if it is encrypted")
+    }
+
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let passkey = key.filter(|key| !key.is_empty());
+        if from.starts_with(b"PK") {
+            return Self::restore_from_zip(from, passkey);
+        }
+
+        let ma_file = serde_json::from_slice::<MaFile>(from).context(
+            "Not a valid maFile, encrypted maFiles can only be restored from a ZIP of the maFiles folder",
+        )?;
+        Ok(vec![Item::try_from(ma_file)?])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+
+    #[test]
+    fn parse() {
+        let data = std::fs::read("./src/backup/tests/steam.maFile").unwrap();
+        let items = Steam::restore_from_data(&data, None).unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].account(), "gordon");
+        assert_eq!(items[0].issuer(), "Steam");
+        assert_eq!(items[0].secret(), "AEBAGBAFAYDQQCIKBMGA2DQPCAIREEYU");
+        assert_eq!(items[0].period(), Some(30));
+        assert_eq!(items[0].method(), Method::Steam);
+        assert_eq!(items[0].algorithm(), Algorithm::SHA1);
+        assert_eq!(items[0].digits(), Some(5));
+        assert_eq!(items[0].counter(), None);
+    }
+
+    #[test]
+    fn parse_encrypted() {
+        let data = std::fs::read("./src/backup/tests/steam_encrypted.zip").unwrap();
+        let items = Steam::restore_from_data(&data, Some("SteamTest123")).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].account(), "alyx");
+        assert_eq!(items[0].secret(), "CAIBAEAQCAIBAEAQCAIBAEAQCAIBAEAQ");
+        assert_eq!(items[0].method(), Method::Steam);
+        assert_eq!(items[1].account(), "barney");
+        assert_eq!(items[1].secret(), "MRSWMZ3INFVGW3DNNZXXA4LSON2HK5TX");
+
+        assert!(Steam::restore_from_data(&data, Some("bad passkey")).is_err());
+        assert!(Steam::restore_from_data(&data, None).is_err());
+    }
+}
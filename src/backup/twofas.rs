@@ -0,0 +1,394 @@
+//! 2FAS Authenticator Import/Export Module
+//!
+//! 2FAS backups are JSON files with a `.2fas` extension. When a password is
+//! set, the services are replaced by `servicesEncrypted`, a
+//! `ciphertext:salt:iv` triplet of Base64 strings, encrypted with AES-GCM
+//! using a key derived with PBKDF2.
+//!
+//! Groups and icons are lost when importing, 2FAS only supports TOTP, HOTP
+//! and Steam tokens so the other methods are skipped when exporting.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use data_encoding::BASE64;
+use gettextrs::gettext;
+use gtk::prelude::*;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use super::{crypto, Backupable, Restorable, RestorableItem};
+use crate::models::{Account, Algorithm, Method, Provider, ProvidersModel, OTP};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFAS {
+    #[serde(default)]
+    services: Vec<Service>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    services_encrypted: Option<String>,
+    // Encrypted along the services, used by 2FAS to check the password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    #[serde(default)]
+    updated_at: u64,
+    schema_version: u32,
+}
+
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+#[serde(rename_all = "camelCase")]
+pub struct Service {
+    #[zeroize(skip)]
+    name: String,
+    secret: String,
+    #[serde(default)]
+    #[zeroize(skip)]
+    updated_at: u64,
+    #[zeroize(skip)]
+    otp: ServiceOTP,
+    #[serde(default)]
+    #[zeroize(skip)]
+    order: Order,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServiceOTP {
+    label: Option<String>,
+    account: Option<String>,
+    issuer: Option<String>,
+    digits: Option<u32>,
+    period: Option<u32>,
+    algorithm: Option<String>,
+    counter: Option<u32>,
+    token_type: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Order {
+    position: u32,
+}
+
+impl Service {
+    fn new(account: &Account, provider: &Provider, position: u32, updated_at: u64) -> Self {
+        let method = provider.method();
+        Self {
+            name: provider.name(),
+            secret: account.otp().secret(),
+            updated_at,
+            otp: ServiceOTP {
+                label: Some(account.name()),
+                account: Some(account.name()),
+                issuer: Some(provider.name()),
+                digits: Some(provider.digits()),
+                period: method.is_time_based().then(|| provider.period()),
+                algorithm: Some(provider.algorithm().to_string().to_uppercase()),
+                counter: method.is_event_based().then(|| account.counter()),
+                token_type: Some(method.to_string().to_uppercase()),
+            },
+            order: Order { position },
+        }
+    }
+}
+
+impl RestorableItem for Service {
+    fn account(&self) -> String {
+        self.otp
+            .account
+            .clone()
+            .or_else(|| self.otp.label.clone())
+            .unwrap_or_default()
+    }
+
+    fn issuer(&self) -> String {
+        self.otp
+            .issuer
+            .clone()
+            .filter(|issuer| !issuer.is_empty())
+            .unwrap_or_else(|| self.name.clone())
+    }
+
+    fn secret(&self) -> String {
+        self.secret.trim_end_matches('=').to_owned()
+    }
+
+    fn period(&self) -> Option<u32> {
+        self.otp.period
+    }
+
+    fn method(&self) -> Method {
+        self.otp
+            .token_type
+            .as_deref()
+            .and_then(|token_type| token_type.parse().ok())
+            .unwrap_or_default()
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        self.otp
+            .algorithm
+            .as_deref()
+            .and_then(|algorithm| algorithm.parse().ok())
+            .unwrap_or_default()
+    }
+
+    fn digits(&self) -> Option<u32> {
+        self.otp.digits
+    }
+
+    fn counter(&self) -> Option<u32> {
+        self.otp.counter
+    }
+}
+
+impl TwoFAS {
+    const SCHEMA_VERSION: u32 = 4;
+    const PBKDF2_ITERATIONS: u32 = 10_000;
+    const KEY_SIZE: usize = 32;
+    const SALT_SIZE: usize = 256;
+    const IV_SIZE: usize = 12;
+    // The plain-text of `reference`, as defined by 2FAS
+    const REFERENCE: &'static str = "tRViSsLKzd86Hprh4ceC2OP7xazn4rrt4xhfEUbOjxLX8Rc3mkISXE0lWbmnWfggogbBJhtYgpK6fMl1D6mtsy92R3HkdGfwuXbzLebqVFJsR7IZ2w58t938iymwG4824igYy1wi6n2WDpO1Q1P69zwJGs2F5a1qP4MyIiDSD7NCV2OvidXQCBnDlGfmz0f1BQySRkkt4ryiJeCjD2o4QsveJ9uDBUn8ELyOrESv5R5DMDkD4iAF8TXU7KyoJujd";
+
+    // mOTP, Yandex and OCRA accounts have no equivalent in 2FAS
+    fn is_supported(method: Method) -> bool {
+        matches!(method, Method::TOTP | Method::HOTP | Method::Steam)
+    }
+
+    fn derive_key(password: &str, salt: &[u8]) -> Result<Zeroizing<[u8; Self::KEY_SIZE]>> {
+        let mut key = Zeroizing::new([0u8; Self::KEY_SIZE]);
+        crypto::pbkdf2(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            password.as_bytes(),
+            salt,
+            Self::PBKDF2_ITERATIONS,
+            &mut *key,
+        )?;
+        Ok(key)
+    }
+
+    fn encrypt_field(key: &[u8], salt: &[u8], plaintext: &[u8]) -> Result<String> {
+        let mut iv = [0u8; Self::IV_SIZE];
+        rand::thread_rng().fill_bytes(&mut iv);
+        let ciphertext = crypto::aes_gcm_encrypt(key, &iv, plaintext)?;
+        Ok(format!(
+            "{}:{}:{}",
+            BASE64.encode(&ciphertext),
+            BASE64.encode(salt),
+            BASE64.encode(&iv)
+        ))
+    }
+
+    fn decrypt_field(field: &str, password: &str) -> Result<Vec<u8>> {
+        let parts = field
+            .split(':')
+            .map(|part| BASE64.decode(part.as_bytes()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let [ciphertext, salt, iv] = parts.as_slice() else {
+            anyhow::bail!("Invalid encrypted services");
+        };
+        let key = Self::derive_key(password, salt)?;
+        crypto::aes_gcm_decrypt(&*key, iv, ciphertext)
+            .context("Cannot decrypt the backup, is the password correct?")
+    }
+
+    /// Creates a backup of `services`, encrypting them if a password is given.
+    fn new(services: Vec<Service>, password: Option<&str>) -> Result<Self> {
+        let updated_at = services
+            .iter()
+            .map(|service| service.updated_at)
+            .max()
+            .unwrap_or_default();
+        let mut backup = Self {
+            services,
+            services_encrypted: None,
+            reference: None,
+            updated_at,
+            schema_version: Self::SCHEMA_VERSION,
+        };
+
+        if let Some(password) = password {
+            let mut salt = [0u8; Self::SALT_SIZE];
+            rand::thread_rng().fill_bytes(&mut salt);
+            let key = Self::derive_key(password, &salt)?;
+
+            let mut services = serde_json::to_vec(&backup.services)?;
+            let services_encrypted = Self::encrypt_field(&*key, &salt, &services);
+            services.zeroize();
+            backup.services_encrypted = Some(services_encrypted?);
+            backup.reference = Some(Self::encrypt_field(
+                &*key,
+                &salt,
+                Self::REFERENCE.as_bytes(),
+            )?);
+            backup.services = Vec::new();
+        }
+        Ok(backup)
+    }
+
+    fn into_services(self, key: Option<&str>) -> Result<Vec<Service>> {
+        let Some(ref services_encrypted) = self.services_encrypted else {
+            return Ok(self.services);
+        };
+        let password = key
+            .filter(|key| !key.is_empty())
+            .context("The backup is encrypted, a password is needed")?;
+
+        let mut services = Self::decrypt_field(services_encrypted, password)?;
+        let items = serde_json::from_slice::<Vec<Service>>(&services);
+        services.zeroize();
+        Ok(items?)
+    }
+}
+
+impl Backupable for TwoFAS {
+    const ENCRYPTABLE: bool = true;
+    const IDENTIFIER: &'static str = "twofas";
+
+    fn title() -> String {
+        // Translators: This is for making a backup for the 2FAS Authenticator app.
+        gettext("2_FAS")
+    }
+
+    fn subtitle() -> String {
+        gettext("Into a plain-text or encrypted 2FAS file")
+    }
+
+    fn backup(model: &ProvidersModel, key: Option<&str>) -> Result<Vec<u8>> {
+        let updated_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+        let mut services = Vec::new();
+
+        for i in 0..model.n_items() {
+            let provider = model.item(i).and_downcast::<Provider>().unwrap();
+            if !Self::is_supported(provider.method()) {
+                tracing::warn!(
+                    "Skipping the accounts of {}, 2FAS doesn't support {}",
+                    provider.name(),
+                    provider.method().to_string()
+                );
+                continue;
+            }
+            let accounts = provider.accounts_model();
+
+            for j in 0..accounts.n_items() {
+                let account = accounts.item(j).and_downcast::<Account>().unwrap();
+                let position = services.len() as u32;
+                services.push(Service::new(&account, &provider, position, updated_at));
+            }
+        }
+
+        let backup = Self::new(services, key.filter(|key| !key.is_empty()))?;
+        let content = serde_json::ser::to_vec_pretty(&backup)?;
+        Ok(content)
+    }
+
+    fn skipped_accounts(model: &ProvidersModel) -> u32 {
+        (0..model.n_items())
+            .filter_map(|i| model.item(i).and_downcast::<Provider>())
+            .filter(|provider| !Self::is_supported(provider.method()))
+            .map(|provider| provider.accounts_model().n_items())
+            .sum()
+    }
+}
+
+impl Restorable for TwoFAS {
+    const ENCRYPTABLE: bool = true;
+    const SCANNABLE: bool = false;
+    const IDENTIFIER: &'static str = "twofas";
+    type Item = Service;
+
+    fn title() -> String {
+        // Translators: This is for restoring a backup from the 2FAS Authenticator app.
+        gettext("2_FAS")
+    }
+
+    fn subtitle() -> String {
+        gettext("From a plain-text or encrypted 2FAS file")
+    }
+
+    fn restore_from_data(from: &[u8], key: Option<&str>) -> Result<Vec<Self::Item>> {
+        let backup: TwoFAS = serde_json::de::from_slice(from)?;
+        let services = backup.into_services(key)?;
+
+        // Steam tokens don't come with their parameters
+        Ok(services
+            .into_iter()
+            .map(|mut service| {
+                if service.method() == Method::Steam {
+                    service.otp.digits = Some(OTP::STEAM_DEFAULT_DIGITS);
+                    service.otp.period = Some(OTP::STEAM_DEFAULT_PERIOD);
+                }
+                service
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::RestorableItem, *};
+
+    #[test]
+    fn parse() {
+        let data = std::fs::read("./src/backup/tests/twofas_plain.2fas").unwrap();
+        let items = TwoFAS::restore_from_data(&data, None).unwrap();
+
+        assert_eq!(items.len(), 3);
+
+        assert_eq!(items[0].account(), "Mason");
+        assert_eq!(items[0].issuer(), "Deno");
+        assert_eq!(items[0].secret(), "4SJHB4GSD43FZBAI7C2HLRJGPQ");
+        assert_eq!(items[0].period(), Some(30));
+        assert_eq!(items[0].method(), Method::TOTP);
+        assert_eq!(items[0].algorithm(), Algorithm::SHA1);
+        assert_eq!(items[0].digits(), Some(6));
+
+        assert_eq!(items[1].account(), "James");
+        assert_eq!(items[1].issuer(), "Issuu");
+        assert_eq!(items[1].secret(), "YOOMIXWS5GN6RTBPUFFWKTW5M4");
+        assert_eq!(items[1].method(), Method::HOTP);
+        assert_eq!(items[1].algorithm(), Algorithm::SHA256);
+        assert_eq!(items[1].digits(), Some(7));
+        assert_eq!(items[1].counter(), Some(50));
+
+        assert_eq!(items[2].account(), "Sophia");
+        assert_eq!(items[2].issuer(), "Steam");
+        assert_eq!(items[2].secret(), "JRZCL47CMXVOQMNPZR2F7J4RGI");
+        assert_eq!(items[2].method(), Method::Steam);
+        assert_eq!(items[2].digits(), Some(5));
+        assert_eq!(items[2].period(), Some(30));
+    }
+
+    #[test]
+    fn parse_encrypted() {
+        let data = std::fs::read("./src/backup/tests/twofas_encrypted.2fas").unwrap();
+        let items = TwoFAS::restore_from_data(&data, Some("TwoFASTest123")).unwrap();
+
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].account(), "Mason");
+        assert_eq!(items[0].secret(), "4SJHB4GSD43FZBAI7C2HLRJGPQ");
+        assert_eq!(items[1].counter(), Some(50));
+        assert_eq!(items[2].method(), Method::Steam);
+
+        assert!(TwoFAS::restore_from_data(&data, Some("wrong password")).is_err());
+        assert!(TwoFAS::restore_from_data(&data, None).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = std::fs::read("./src/backup/tests/twofas_plain.2fas").unwrap();
+        let services = TwoFAS::restore_from_data(&data, None).unwrap();
+
+        let backup = TwoFAS::new(services, Some("password")).unwrap();
+        assert!(backup.services.is_empty());
+        let data = serde_json::to_vec(&backup).unwrap();
+        let items = TwoFAS::restore_from_data(&data, Some("password")).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].secret(), "YOOMIXWS5GN6RTBPUFFWKTW5M4");
+        assert_eq!(items[1].algorithm(), Algorithm::SHA256);
+
+        let reference = TwoFAS::decrypt_field(&backup.reference.unwrap(), "password").unwrap();
+        assert_eq!(reference, TwoFAS::REFERENCE.as_bytes());
+    }
+}
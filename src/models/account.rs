@@ -7,7 +7,9 @@ use gtk::{
 };
 
 use crate::{
-    models::{database, keyring, DieselProvider, Method, OTPUri, Provider, OTP, RUNTIME},
+    models::{
+        database, keyring, DieselProvider, Method, OTPUri, Provider, SecretEncoding, OTP, RUNTIME,
+    },
     schema::accounts,
     utils::spawn_tokio_blocking,
 };
@@ -30,6 +32,7 @@ pub struct DieselAccount {
     pub counter: i32,
     pub token_id: String,
     pub provider_id: i32,
+    pub otpauth_params: Option<String>,
 }
 
 #[doc(hidden)]
@@ -48,6 +51,8 @@ mod imp {
         pub id: Cell<u32>,
         #[property(get, set)]
         pub code: RefCell<String>,
+        #[property(get, set)]
+        pub next_code: RefCell<String>,
         #[property(get, set = Self::set_name)]
         pub name: RefCell<String>,
         #[property(get, set = Self::set_counter, default = OTP::DEFAULT_COUNTER)]
@@ -55,6 +60,9 @@ mod imp {
         pub otp: OnceCell<OTP>,
         #[property(get, set, construct_only)]
         pub token_id: RefCell<String>,
+        /// The otpauth parameters we don't use but keep for the backups.
+        #[property(get, set, construct_only)]
+        pub otpauth_params: RefCell<Option<String>>,
         // We don't use property here as we can't mark the getter as not nullable
         pub provider: RefCell<Option<Provider>>,
     }
@@ -70,7 +78,9 @@ mod imp {
                 counter: Cell::new(OTP::DEFAULT_COUNTER),
                 name: RefCell::default(),
                 code: RefCell::default(),
+                next_code: RefCell::default(),
                 token_id: RefCell::default(),
+                otpauth_params: RefCell::default(),
                 provider: RefCell::default(),
                 otp: OnceCell::default(),
             }
@@ -157,9 +167,15 @@ glib::wrapper! {
 }
 
 impl Account {
+    // How far the counter is searched in both directions when resynchronising
+    const RESYNC_WINDOW: u64 = 100;
+    // How far the clock is searched in both directions when calibrating, in seconds
+    const CALIBRATION_RANGE: u64 = 60 * 60;
+
     pub fn create(
         name: &str,
         token: &str,
+        pin: Option<&str>,
         counter: Option<u32>,
         provider: &Provider,
     ) -> Result<Account> {
@@ -167,7 +183,8 @@ impl Account {
         let mut conn = db.get()?;
 
         let label = format!("{} - {name}", provider.name());
-        let token_send = token.to_owned();
+        let token = keyring::token_with_pin(token, pin);
+        let token_send = token.clone();
         let token_id = spawn_tokio_blocking(async move {
             keyring::store(&label, &token_send)
                 .await
@@ -193,8 +210,9 @@ impl Account {
                     &account.name,
                     &account.token_id,
                     account.counter as u32,
+                    account.otpauth_params.as_deref(),
                     provider,
-                    Some(token),
+                    Some(&token),
                 )
                 .unwrap()
             })
@@ -214,6 +232,7 @@ impl Account {
                     &account.name,
                     &account.token_id,
                     account.counter as u32,
+                    account.otpauth_params.as_deref(),
                     &p,
                     None,
                 )
@@ -236,6 +255,7 @@ impl Account {
         name: &str,
         token_id: &str,
         counter: u32,
+        otpauth_params: Option<&str>,
         provider: &Provider,
         secret: Option<&str>,
     ) -> Result<Account> {
@@ -243,6 +263,7 @@ impl Account {
             .property("id", id)
             .property("name", name)
             .property("token-id", token_id)
+            .property("otpauth-params", otpauth_params)
             .property("provider", provider)
             .property("counter", counter)
             .build();
@@ -257,7 +278,15 @@ impl Account {
                 })
             })?
         };
-        let otp = OTP::from_str(&secret, provider.algorithm(), provider.digits())?;
+        let (secret, pin) = keyring::split_pin(&secret);
+        let otp = OTP::from_str(
+            secret,
+            SecretEncoding::Base32,
+            provider.algorithm(),
+            provider.digits(),
+        )?
+        .with_pin(pin)
+        .with_epoch(provider.epoch());
         account.imp().otp.set(otp).unwrap();
         account.generate_otp();
         Ok(account)
@@ -265,22 +294,138 @@ impl Account {
 
     pub fn generate_otp(&self) {
         let provider = self.provider();
+        let timestamp = OTP::timestamp(provider.time_offset());
 
-        let otp_password = match provider.method() {
-            Method::Steam => self.otp().steam(None),
-            Method::TOTP => self.otp().totp_formatted(Some(provider.period())),
-            Method::HOTP => self.otp().hotp_formatted(self.counter() as u64),
-        };
-
-        let label = match otp_password {
+        let label = match self.generate_otp_at(timestamp) {
             Ok(password) => password,
             Err(err) => {
                 tracing::warn!("Failed to generate the OTP {}", err);
                 "Error".to_string()
             }
         };
-
         self.set_code(label);
+
+        // Only time-based codes have an upcoming value worth previewing
+        let next_code = if provider.method().is_time_based() {
+            self.generate_otp_at(timestamp + provider.period() as u64)
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        self.set_next_code(next_code);
+    }
+
+    /// Generates the code at the UNIX `timestamp`, the clock offset of the
+    /// provider is not applied.
+    pub fn generate_otp_at(&self, timestamp: u64) -> Result<String> {
+        let provider = self.provider();
+
+        match provider.method() {
+            Method::Steam => self.otp().steam_at(timestamp),
+            Method::TOTP => self.otp().totp_formatted_at(
+                Some(provider.period()),
+                timestamp,
+                &provider.rendering(),
+            ),
+            Method::HOTP => self
+                .otp()
+                .hotp_formatted(self.counter() as u64, &provider.rendering()),
+            Method::MOTP => self.otp().motp_at(timestamp),
+            Method::Yandex => self.otp().yandex_at(timestamp),
+            // Challenge-response codes are only computed on demand
+            Method::OCRA => Ok(self.code()),
+        }
+    }
+
+    /// Computes the response of a challenge-response account and displays it
+    /// as the current code.
+    pub fn respond_to_challenge(
+        &self,
+        challenge: &str,
+        session: Option<&str>,
+        pin: Option<&str>,
+    ) -> Result<()> {
+        let suite = self.provider().ocra()?;
+        let response =
+            self.otp()
+                .ocra(&suite, challenge, self.counter() as u64, session, pin, None)?;
+        if suite.counter {
+            self.increment_counter()?;
+        }
+        self.set_code(response);
+        Ok(())
+    }
+
+    /// The code of a time-based account at the time step `counter`.
+    fn time_based_code(&self, counter: u64) -> Result<String> {
+        match self.provider().method() {
+            Method::TOTP => self
+                .otp()
+                .hotp_formatted(counter, &self.provider().rendering()),
+            Method::Steam => self.otp().steam(Some(counter), 0),
+            Method::MOTP => self.otp().motp(Some(counter), 0),
+            Method::Yandex => self.otp().yandex(Some(counter), 0),
+            method => anyhow::bail!("{} is not a time-based method", method.to_string()),
+        }
+    }
+
+    /// The period of the time steps given to [`Self::time_based_code`], Steam,
+    /// mOTP and Yandex ignore the one of the provider.
+    fn time_based_period(&self) -> u32 {
+        match self.provider().method() {
+            Method::Steam => OTP::STEAM_DEFAULT_PERIOD,
+            Method::MOTP => OTP::MOTP_DEFAULT_PERIOD,
+            Method::Yandex => OTP::YANDEX_DEFAULT_PERIOD,
+            _ => self.provider().period(),
+        }
+    }
+
+    /// Finds the clock offset of the provider from a `code` shown by a server
+    /// or another device, by searching the time steps around the current time.
+    pub fn calibrate(&self, code: &str) -> Result<()> {
+        let provider = self.provider();
+        let period = self.time_based_period();
+        let code = code.replace(' ', "");
+        let current = self.otp().time_step(OTP::timestamp(0), period);
+        let range = Self::CALIBRATION_RANGE / period as u64;
+
+        let step = std::iter::once(current)
+            .chain((1..=range).flat_map(|i| [current.saturating_sub(i), current + i]))
+            .find(|step| {
+                self.time_based_code(*step)
+                    .is_ok_and(|c| c.replace(' ', "").eq_ignore_ascii_case(&code))
+            })
+            .context("The code doesn't match any time step nearby")?;
+
+        let offset = (step as i64 - current as i64) * period as i64;
+        tracing::info!(
+            "Calibrated the clock of '{}' with an offset of {offset}s",
+            provider.name()
+        );
+        provider.update_time_offset(offset)
+    }
+
+    /// Moves the counter of a HOTP account right after the two consecutive
+    /// codes typed by the user, e.g. from a hardware token that drifted.
+    pub fn resync_counter(&self, first_code: &str, second_code: &str) -> Result<()> {
+        let otp = self.otp();
+        let start = (self.counter() as u64).saturating_sub(Self::RESYNC_WINDOW);
+        let end = start + 2 * Self::RESYNC_WINDOW;
+
+        let mut counter = start;
+        while let Some(step) = otp.verify_hotp(first_code, counter, end - counter) {
+            if otp.verify_hotp(second_code, step + 1, 0).is_some() {
+                let new_counter = u32::try_from(step + 2)?;
+                self.set_counter(new_counter);
+                self.generate_otp();
+                return Ok(());
+            }
+            if step == end {
+                break;
+            }
+            counter = step + 1;
+        }
+        anyhow::bail!("The codes don't match any counter near {}", self.counter())
     }
 
     /// Increment the internal counter in case of a HOTP account
@@ -299,11 +444,7 @@ impl Account {
     }
 
     pub fn copy_otp(&self) {
-        let display = gtk::gdk::Display::default().unwrap();
-        let clipboard = display.clipboard();
-        // The codes come with the white space shown in the label.
-        let code = self.code().replace(' ', "");
-        clipboard.set_text(&code);
+        Self::copy_to_clipboard(&self.code());
 
         // Indirectly increment the counter once the token was copied
         if self.provider().method().is_event_based() {
@@ -311,6 +452,19 @@ impl Account {
         }
     }
 
+    /// Copies the code of the next time step, useful when the current one is
+    /// about to expire.
+    pub fn copy_next_otp(&self) {
+        Self::copy_to_clipboard(&self.next_code());
+    }
+
+    fn copy_to_clipboard(code: &str) {
+        let display = gtk::gdk::Display::default().unwrap();
+        let clipboard = display.clipboard();
+        // The codes come with the white space shown in the label.
+        clipboard.set_text(&code.replace(' ', ""));
+    }
+
     pub fn provider(&self) -> Provider {
         self.imp().provider.borrow().clone().unwrap()
     }
@@ -323,11 +477,29 @@ impl Account {
         diesel::update(target)
             .set(accounts::columns::provider_id.eq(provider.id() as i32))
             .execute(&mut conn)?;
+        self.otp().set_epoch(provider.epoch());
         self.imp().provider.replace(Some(provider.clone()));
         self.notify("provider");
         Ok(())
     }
 
+    /// Keeps the otpauth parameters we don't use, like the color or the
+    /// tags, so they end up in the backups.
+    pub fn update_otpauth_params(&self, params: Option<&str>) -> Result<()> {
+        let db = database::connection();
+        let mut conn = db.get()?;
+
+        let target = accounts::table.filter(accounts::columns::id.eq(self.id() as i32));
+        diesel::update(target)
+            .set(accounts::columns::otpauth_params.eq(params))
+            .execute(&mut conn)?;
+        self.imp()
+            .otpauth_params
+            .replace(params.map(ToOwned::to_owned));
+        self.notify_otpauth_params();
+        Ok(())
+    }
+
     pub fn otp(&self) -> &OTP {
         self.imp().otp.get().unwrap()
     }
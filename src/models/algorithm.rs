@@ -16,6 +16,11 @@ pub enum Method {
     #[enum_value(name = "HOTP")]
     HOTP = 1,
     Steam = 2,
+    #[enum_value(name = "mOTP")]
+    MOTP = 3,
+    Yandex = 4,
+    #[enum_value(name = "OCRA")]
+    OCRA = 5,
 }
 
 impl Serialize for Method {
@@ -41,6 +46,9 @@ impl From<u32> for Method {
         match u {
             1 => Self::HOTP,
             2 => Self::Steam,
+            3 => Self::MOTP,
+            4 => Self::Yandex,
+            5 => Self::OCRA,
             _ => Self::default(),
         }
     }
@@ -48,19 +56,33 @@ impl From<u32> for Method {
 
 impl Method {
     pub fn is_time_based(self) -> bool {
-        matches!(self, Self::TOTP | Self::Steam)
+        matches!(self, Self::TOTP | Self::Steam | Self::MOTP | Self::Yandex)
     }
 
     pub fn is_event_based(self) -> bool {
         matches!(self, Self::HOTP)
     }
 
+    /// Whether the codes are computed from a challenge typed by the user.
+    pub fn is_challenge_based(self) -> bool {
+        matches!(self, Self::OCRA)
+    }
+
+    /// Whether the method needs a PIN in addition to the secret.
+    pub fn requires_pin(self) -> bool {
+        matches!(self, Self::MOTP | Self::Yandex)
+    }
+
     pub fn to_locale_string(self) -> String {
         match self {
             Self::HOTP => gettext("Counter-based"),
             Self::TOTP => gettext("Time-based"),
             // Translators: Steam refers to the gaming application by Valve.
             Self::Steam => gettext("Steam"),
+            Self::MOTP => gettext("Mobile-OTP"),
+            // Translators: Yandex refers to the Yandex.Key authenticator.
+            Self::Yandex => gettext("Yandex"),
+            Self::OCRA => gettext("Challenge-response"),
         }
     }
 }
@@ -72,6 +94,9 @@ impl FromStr for Method {
             "totp" | "otp" => Ok(Self::TOTP),
             "hotp" => Ok(Self::HOTP),
             "steam" => Ok(Self::Steam),
+            "motp" => Ok(Self::MOTP),
+            "yandex" | "yaotp" => Ok(Self::Yandex),
+            "ocra" => Ok(Self::OCRA),
             _ => anyhow::bail!("Unsupported Method"),
         }
     }
@@ -83,6 +108,9 @@ impl ToString for Method {
             Self::TOTP => "totp",
             Self::HOTP => "hotp",
             Self::Steam => "steam",
+            Self::MOTP => "motp",
+            Self::Yandex => "yandex",
+            Self::OCRA => "ocra",
         }
         .to_string()
     }
@@ -172,3 +200,37 @@ impl From<u32> for Algorithm {
         }
     }
 }
+
+/// How a secret typed by the user is encoded.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, glib::Enum)]
+#[repr(u32)]
+#[enum_type(name = "OTPSecretEncoding")]
+pub enum SecretEncoding {
+    #[default]
+    Auto = 0,
+    Base32 = 1,
+    Hex = 2,
+    Base64 = 3,
+}
+
+impl SecretEncoding {
+    pub fn to_locale_string(self) -> String {
+        match self {
+            Self::Auto => gettext("Automatic"),
+            Self::Base32 => gettext("Base32"),
+            Self::Hex => gettext("Hexadecimal"),
+            Self::Base64 => gettext("Base64"),
+        }
+    }
+}
+
+impl From<u32> for SecretEncoding {
+    fn from(u: u32) -> Self {
+        match u {
+            1 => Self::Base32,
+            2 => Self::Hex,
+            3 => Self::Base64,
+            _ => Self::default(),
+        }
+    }
+}
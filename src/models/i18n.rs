@@ -1,4 +1,4 @@
-use gettextrs::gettext;
+use gettextrs::{gettext, ngettext};
 
 fn freplace(input: String, args: &[&str]) -> String {
     let mut parts = input.split("{}");
@@ -13,3 +13,8 @@ pub(crate) fn i18n_f(format: &str, args: &[&str]) -> String {
     let s = gettext(format);
     freplace(s, args)
 }
+
+pub(crate) fn ni18n_f(single: &str, plural: &str, n: u32, args: &[&str]) -> String {
+    let s = ngettext(single, plural, n);
+    freplace(s, args)
+}
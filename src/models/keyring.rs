@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use once_cell::sync::OnceCell;
 use rand::RngCore;
@@ -29,6 +29,23 @@ fn encode_argon2(secret: &str) -> anyhow::Result<String> {
     Ok(hash)
 }
 
+/// PIN-based methods keep the PIN next to the secret in the same keyring
+/// item. The colon separator can't be part of a Base32 secret.
+pub fn token_with_pin(token: &str, pin: Option<&str>) -> String {
+    match pin {
+        Some(pin) if !pin.is_empty() => format!("{token}:{pin}"),
+        _ => token.to_owned(),
+    }
+}
+
+/// Splits a stored token into its secret and optional PIN.
+pub fn split_pin(token: &str) -> (&str, Option<&str>) {
+    match token.split_once(':') {
+        Some((secret, pin)) => (secret, Some(pin)),
+        None => (token, None),
+    }
+}
+
 pub async fn store(label: &str, token: &str) -> anyhow::Result<String> {
     let token_id = encode_argon2(token)?;
     let attributes = token_attributes(&token_id);
@@ -60,20 +77,20 @@ pub async fn remove_token(token_id: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn token_exists(token: &str) -> anyhow::Result<bool> {
+/// Whether each of the `tokens` is already stored. They are looked up at
+/// once as every stored token has to be decrypted to compare them.
+pub async fn tokens_exist(tokens: &[String]) -> anyhow::Result<Vec<bool>> {
     let attributes = HashMap::from([("application", config::APP_ID), ("type", "token")]);
     let items = SECRET_SERVICE
         .get()
         .unwrap()
         .search_items(&attributes)
         .await?;
+    let mut stored = HashSet::with_capacity(items.len());
     for item in items {
-        let item_token = String::from_utf8(hex::decode(&*item.secret().await?)?)?;
-        if item_token == token {
-            return Ok(true);
-        }
+        stored.insert(String::from_utf8(hex::decode(&*item.secret().await?)?)?);
     }
-    Ok(false)
+    Ok(tokens.iter().map(|token| stored.contains(token)).collect())
 }
 
 pub async fn has_set_password() -> anyhow::Result<bool> {
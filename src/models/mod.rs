@@ -6,6 +6,7 @@ mod algorithm;
 pub mod database;
 pub mod i18n;
 pub mod keyring;
+mod ocra;
 mod otp;
 mod otp_uri;
 mod provider;
@@ -25,9 +26,10 @@ pub static FAVICONS_PATH: Lazy<std::path::PathBuf> = Lazy::new(|| {
 pub use self::{
     account::Account,
     accounts::AccountsModel,
-    algorithm::{Algorithm, Method},
+    algorithm::{Algorithm, Method, SecretEncoding},
     keyring::SECRET_SERVICE,
-    otp::OTP,
+    ocra::{OcraChallenge, OcraSuite},
+    otp::{Rendering, OTP},
     otp_uri::OTPUri,
     provider::{DieselProvider, Provider, ProviderPatch},
     providers::ProvidersModel,
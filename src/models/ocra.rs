@@ -0,0 +1,188 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{Context, Result};
+
+use super::Algorithm;
+
+/// The format of the challenge (question) of an OCRA suite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcraChallenge {
+    Alphanumeric,
+    Numeric,
+    Hex,
+}
+
+/// An OCRA suite as described in [RFC 6287](https://www.rfc-editor.org/rfc/rfc6287#section-6),
+/// e.g. `OCRA-1:HOTP-SHA256-8:QN08-PSHA1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OcraSuite {
+    suite: String,
+    pub algorithm: Algorithm,
+    pub digits: u32,
+    pub counter: bool,
+    pub challenge: OcraChallenge,
+    pub challenge_length: usize,
+    pub pin: Option<Algorithm>,
+    pub session_length: Option<usize>,
+    pub time_step: Option<u64>,
+}
+
+impl OcraSuite {
+    pub const CHALLENGE_MIN_LENGTH: usize = 4;
+    pub const CHALLENGE_MAX_LENGTH: usize = 64;
+
+    /// The suite as typed by the user, it is part of the signed message.
+    pub fn as_str(&self) -> &str {
+        &self.suite
+    }
+
+    pub fn requires_pin(&self) -> bool {
+        self.pin.is_some()
+    }
+
+    pub fn requires_session(&self) -> bool {
+        self.session_length.is_some()
+    }
+
+    fn parse_time_step(step: &str) -> Result<u64> {
+        let (value, unit) = step.split_at(step.len().saturating_sub(1));
+        let value = value.parse::<u64>().context("Invalid OCRA time step")?;
+        match unit {
+            "S" if (1..=59).contains(&value) => Ok(value),
+            "M" if (1..=59).contains(&value) => Ok(value * 60),
+            "H" if value <= 48 => Ok(value * 3600),
+            _ => anyhow::bail!("Invalid OCRA time step {step}"),
+        }
+    }
+}
+
+impl FromStr for OcraSuite {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let suite = s.trim();
+        if !suite.is_ascii() {
+            anyhow::bail!("Invalid OCRA suite {suite}");
+        }
+        let parts = suite.split(':').collect::<Vec<_>>();
+        let [version, crypto_function, data_input] = parts[..] else {
+            anyhow::bail!("An OCRA suite must have three parts");
+        };
+        if version != "OCRA-1" {
+            anyhow::bail!("Unsupported OCRA version {version}");
+        }
+
+        let function = crypto_function.split('-').collect::<Vec<_>>();
+        let ["HOTP", algorithm, digits] = function[..] else {
+            anyhow::bail!("Unsupported OCRA function {crypto_function}");
+        };
+        let algorithm = Algorithm::from_str(algorithm)?;
+        let digits = digits.parse::<u32>().context("Invalid OCRA digits")?;
+        // A truncation of 0 means the whole HMAC, which is not something a user can type
+        if !(4..=10).contains(&digits) {
+            anyhow::bail!("OCRA digits must be between 4 and 10, got {digits}");
+        }
+
+        let mut counter = false;
+        let mut challenge = None;
+        let mut pin = None;
+        let mut session_length = None;
+        let mut time_step = None;
+        for (idx, input) in data_input.split('-').enumerate() {
+            if input.is_empty() {
+                anyhow::bail!("Invalid OCRA data input {data_input}");
+            }
+            match input.split_at(1) {
+                ("C", "") if idx == 0 => counter = true,
+                ("Q", format) if format.len() == 3 => {
+                    let kind = match &format[..1] {
+                        "A" => OcraChallenge::Alphanumeric,
+                        "N" => OcraChallenge::Numeric,
+                        "H" => OcraChallenge::Hex,
+                        _ => anyhow::bail!("Unsupported OCRA challenge format {input}"),
+                    };
+                    let length = format[1..]
+                        .parse::<usize>()
+                        .context("Invalid OCRA challenge length")?;
+                    if !(Self::CHALLENGE_MIN_LENGTH..=Self::CHALLENGE_MAX_LENGTH).contains(&length)
+                    {
+                        anyhow::bail!("Invalid OCRA challenge length {length}");
+                    }
+                    challenge = Some((kind, length));
+                }
+                ("P", hash) => pin = Some(Algorithm::from_str(hash)?),
+                ("S", length) => {
+                    session_length = Some(if length.is_empty() {
+                        // The RFC default session length
+                        64
+                    } else {
+                        length
+                            .parse::<usize>()
+                            .context("Invalid OCRA session length")?
+                    })
+                }
+                ("T", step) => time_step = Some(Self::parse_time_step(step)?),
+                _ => anyhow::bail!("Unsupported OCRA data input {input}"),
+            }
+        }
+
+        let Some((challenge, challenge_length)) = challenge else {
+            anyhow::bail!("An OCRA suite must define a challenge");
+        };
+
+        Ok(Self {
+            suite: suite.to_owned(),
+            algorithm,
+            digits,
+            counter,
+            challenge,
+            challenge_length,
+            pin,
+            session_length,
+            time_step,
+        })
+    }
+}
+
+impl fmt::Display for OcraSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.suite)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, OcraChallenge, OcraSuite};
+
+    #[test]
+    fn parse() {
+        let suite = "OCRA-1:HOTP-SHA256-8:QN08-PSHA1"
+            .parse::<OcraSuite>()
+            .unwrap();
+        assert_eq!(suite.algorithm, Algorithm::SHA256);
+        assert_eq!(suite.digits, 8);
+        assert!(!suite.counter);
+        assert_eq!(suite.challenge, OcraChallenge::Numeric);
+        assert_eq!(suite.challenge_length, 8);
+        assert_eq!(suite.pin, Some(Algorithm::SHA1));
+        assert_eq!(suite.session_length, None);
+        assert_eq!(suite.time_step, None);
+
+        let suite = "OCRA-1:HOTP-SHA512-8:C-QH40-S128-T2H"
+            .parse::<OcraSuite>()
+            .unwrap();
+        assert!(suite.counter);
+        assert_eq!(suite.challenge, OcraChallenge::Hex);
+        assert_eq!(suite.challenge_length, 40);
+        assert_eq!(suite.session_length, Some(128));
+        assert_eq!(suite.time_step, Some(7200));
+        assert_eq!(suite.to_string(), "OCRA-1:HOTP-SHA512-8:C-QH40-S128-T2H");
+
+        assert!("OCRA-1:HOTP-SHA1-6".parse::<OcraSuite>().is_err());
+        assert!("OCRA-2:HOTP-SHA1-6:QN08".parse::<OcraSuite>().is_err());
+        assert!("OCRA-1:HOTP-MD5-6:QN08".parse::<OcraSuite>().is_err());
+        assert!("OCRA-1:HOTP-SHA1-6:C-PSHA1".parse::<OcraSuite>().is_err());
+        assert!("OCRA-1:HOTP-SHA1-6:QX08".parse::<OcraSuite>().is_err());
+        assert!("OCRA-1:HOTP-SHA1-6:QN08-T60M".parse::<OcraSuite>().is_err());
+    }
+}
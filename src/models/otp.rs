@@ -1,14 +1,15 @@
 use std::{
+    cell::Cell,
     convert::TryInto,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::{anyhow, Result};
-use data_encoding::BASE32_NOPAD;
-use ring::hmac;
+use anyhow::{anyhow, Context, Result};
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD, BASE64_NOPAD, HEXLOWER_PERMISSIVE};
+use ring::{digest, hmac};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
-use super::Algorithm;
+use super::{Algorithm, OcraChallenge, OcraSuite, SecretEncoding};
 
 #[derive(Debug, Zeroize, ZeroizeOnDrop)]
 #[allow(clippy::upper_case_acronyms)]
@@ -18,27 +19,83 @@ pub struct OTP {
     algorithm: Algorithm,
     #[zeroize(skip)]
     digits: u32,
+    pin: Option<String>,
+    // T0 of RFC 6238, the UNIX time from which the time steps are counted
+    #[zeroize(skip)]
+    epoch: Cell<u64>,
 }
 
 impl OTP {
     const STEAM_CHARS: &'static str = "23456789BCDFGHJKMNPQRTVWXY";
     pub const STEAM_DEFAULT_PERIOD: u32 = 30;
     pub const STEAM_DEFAULT_DIGITS: u32 = 5;
+    pub const MOTP_DEFAULT_PERIOD: u32 = 10;
+    pub const MOTP_DEFAULT_DIGITS: u32 = 6;
+    pub const YANDEX_DEFAULT_PERIOD: u32 = 30;
+    pub const YANDEX_DEFAULT_DIGITS: u32 = 8;
+    // Yandex secrets are 16 bytes long, the remaining bytes are a checksum
+    const YANDEX_SECRET_LENGTH: usize = 16;
     pub const DEFAULT_COUNTER: u32 = 1;
     pub const DEFAULT_DIGITS: u32 = 6;
     pub const DEFAULT_PERIOD: u32 = 30;
+    pub const MIN_DIGITS: u32 = 1;
+    // Numeric codes are computed from a 31 bits value, 10 digits at most
+    pub const MAX_DIGITS: u32 = 10;
+
+    /// The encodings a secret can be decoded with.
+    ///
+    /// Base32 secrets are sometimes written in lowercase but never in mixed
+    /// case, while Base64 secrets almost always are, which is how the two are
+    /// told apart.
+    pub fn candidate_encodings(secret: &str) -> Vec<SecretEncoding> {
+        let secret = secret.trim().replace(' ', "");
+        let is_mixed_case = secret.chars().any(|c| c.is_ascii_lowercase())
+            && secret.chars().any(|c| c.is_ascii_uppercase());
+        [
+            SecretEncoding::Base32,
+            SecretEncoding::Hex,
+            SecretEncoding::Base64,
+        ]
+        .into_iter()
+        .filter(|encoding| match encoding {
+            SecretEncoding::Base32 => !is_mixed_case,
+            SecretEncoding::Base64 => is_mixed_case || secret.contains(['+', '/', '-', '_']),
+            _ => true,
+        })
+        .filter(|encoding| decode_secret(&secret, *encoding).is_ok())
+        .collect()
+    }
+
+    /// Guesses the encoding of a secret, `None` if the secret is not valid for
+    /// any encoding or is valid for more than one of them, like `deadbeef`.
+    pub fn detect_encoding(secret: &str) -> Option<SecretEncoding> {
+        match Self::candidate_encodings(secret)[..] {
+            [encoding] => Some(encoding),
+            _ => None,
+        }
+    }
 
-    // Validates if `secret` is a valid Base32 String.
-    pub fn is_valid(secret: &str) -> bool {
-        decode_secret(secret).is_ok()
+    pub fn is_valid_digits(digits: u32) -> bool {
+        (Self::MIN_DIGITS..=Self::MAX_DIGITS).contains(&digits)
     }
 
-    fn time_based_counter(period: u32) -> u64 {
-        let timestamp = SystemTime::now()
+    /// The current UNIX timestamp, shifted by a clock `offset` in seconds.
+    pub fn timestamp(offset: i64) -> u64 {
+        SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
-            .as_secs();
-        timestamp / period as u64
+            .as_secs()
+            .saturating_add_signed(offset)
+    }
+
+    fn time_based_counter(&self, period: u32, offset: i64) -> u64 {
+        self.time_step(Self::timestamp(offset), period)
+    }
+
+    /// The time step of the UNIX `timestamp`, counted from the epoch (T0) of
+    /// the token. Timestamps prior to the epoch are all in the first step.
+    pub fn time_step(&self, timestamp: u64, period: u32) -> u64 {
+        timestamp.saturating_sub(self.epoch.get()) / period as u64
     }
 
     pub fn from_bytes_steam(secret: impl AsRef<[u8]>) -> Self {
@@ -46,16 +103,26 @@ impl OTP {
     }
 
     pub fn from_str_steam(secret: &str) -> Result<Self> {
-        Self::from_str(secret, Algorithm::SHA1, Self::STEAM_DEFAULT_DIGITS)
+        Self::from_str(
+            secret,
+            SecretEncoding::Base32,
+            Algorithm::SHA1,
+            Self::STEAM_DEFAULT_DIGITS,
+        )
     }
 
     pub fn from_str_with_defaults(secret: &str) -> Result<Self> {
-        let decoded = decode_secret(secret)?;
+        let decoded = decode_secret(secret, SecretEncoding::Base32)?;
         Ok(Self::from_bytes_with_defaults(decoded))
     }
 
-    pub fn from_str(secret: &str, algorithm: Algorithm, digits: u32) -> Result<Self> {
-        let decoded = decode_secret(secret)?;
+    pub fn from_str(
+        secret: &str,
+        encoding: SecretEncoding,
+        algorithm: Algorithm,
+        digits: u32,
+    ) -> Result<Self> {
+        let decoded = decode_secret(secret, encoding)?;
         Ok(Self::from_bytes(decoded, algorithm, digits))
     }
 
@@ -64,6 +131,8 @@ impl OTP {
             secret: secret.as_ref().to_owned(),
             algorithm: Algorithm::default(),
             digits: Self::DEFAULT_DIGITS,
+            pin: None,
+            epoch: Cell::default(),
         }
     }
 
@@ -72,48 +141,271 @@ impl OTP {
             secret: secret.as_ref().to_owned(),
             algorithm,
             digits,
+            pin: None,
+            epoch: Cell::default(),
         }
     }
 
+    /// Sets the PIN used by PIN-based methods like mOTP.
+    pub fn with_pin(mut self, pin: Option<&str>) -> Self {
+        self.pin = pin.map(ToOwned::to_owned);
+        self
+    }
+
+    /// Sets the UNIX time from which the time steps are counted, T0 in RFC 6238.
+    pub fn with_epoch(self, epoch: u64) -> Self {
+        self.set_epoch(epoch);
+        self
+    }
+
+    pub fn set_epoch(&self, epoch: u64) {
+        self.epoch.set(epoch);
+    }
+
     /// Performs the [HMAC-based One-time Password Algorithm](http://en.wikipedia.org/wiki/HMAC-based_One-time_Password_Algorithm)
     /// (HOTP) given an RFC4648 base32 encoded secret, and an integer counter.
-    pub fn hotp(&self, counter: u64) -> Result<u32> {
+    pub fn hotp(&self, counter: u64) -> Result<u64> {
+        let digest = encode_digest(calc_digest(&self.secret, counter, self.algorithm))?;
+        let modulus = 10_u64
+            .checked_pow(self.digits)
+            .context("Invalid number of digits")?;
+        Ok(digest as u64 % modulus)
+    }
+
+    pub fn hotp_formatted(&self, counter: u64, rendering: &Rendering) -> Result<String> {
         let digest = encode_digest(calc_digest(&self.secret, counter, self.algorithm))?;
-        Ok(digest % 10_u32.pow(self.digits))
+        Ok(rendering.render(digest, self.digits))
+    }
+
+    /// Performs the TOTP algorithm, `offset` is the clock offset in seconds
+    /// of the provider.
+    pub fn totp(&self, period: Option<u32>, offset: i64) -> Result<u64> {
+        self.totp_at(period, Self::timestamp(offset))
+    }
+
+    /// Performs the TOTP algorithm for the UNIX `timestamp`.
+    pub fn totp_at(&self, period: Option<u32>, timestamp: u64) -> Result<u64> {
+        self.hotp(self.time_step(timestamp, period.unwrap_or(Self::DEFAULT_PERIOD)))
+    }
+
+    pub fn totp_formatted(
+        &self,
+        period: Option<u32>,
+        offset: i64,
+        rendering: &Rendering,
+    ) -> Result<String> {
+        self.totp_formatted_at(period, Self::timestamp(offset), rendering)
     }
 
-    pub fn hotp_formatted(&self, counter: u64) -> Result<String> {
-        self.hotp(counter).map(|d| format(d, self.digits as usize))
+    pub fn totp_formatted_at(
+        &self,
+        period: Option<u32>,
+        timestamp: u64,
+        rendering: &Rendering,
+    ) -> Result<String> {
+        self.hotp_formatted(
+            self.time_step(timestamp, period.unwrap_or(Self::DEFAULT_PERIOD)),
+            rendering,
+        )
     }
 
-    pub fn totp(&self, period: Option<u32>) -> Result<u32> {
-        let counter = Self::time_based_counter(period.unwrap_or(Self::DEFAULT_PERIOD));
+    /// Checks a TOTP `code` against the steps around the timestamp `at`,
+    /// tolerating a clock drift of `skew` steps in both directions.
+    ///
+    /// Returns the matched step, the closest one to `at` wins.
+    pub fn verify_totp(&self, code: &str, period: u32, at: u64, skew: u32) -> Option<u64> {
+        let counter = self.time_step(at, period);
+        std::iter::once(counter)
+            .chain((1..=skew as u64).flat_map(|i| {
+                [counter.checked_sub(i), counter.checked_add(i)]
+                    .into_iter()
+                    .flatten()
+            }))
+            .find(|step| self.matches(code, *step))
+    }
+
+    /// Checks a HOTP `code` against the counters from `counter` up to
+    /// `counter + look_ahead`.
+    ///
+    /// Returns the matched counter.
+    pub fn verify_hotp(&self, code: &str, counter: u64, look_ahead: u64) -> Option<u64> {
+        (counter..=counter.saturating_add(look_ahead)).find(|step| self.matches(code, *step))
+    }
+
+    fn matches(&self, code: &str, counter: u64) -> bool {
+        let code = code.replace(' ', "");
         self.hotp(counter)
+            .map(|expected| format!("{expected:0digits$}", digits = self.digits as usize) == code)
+            .unwrap_or_default()
+    }
+
+    pub fn steam(&self, counter: Option<u64>, offset: i64) -> Result<String> {
+        let counter =
+            counter.unwrap_or_else(|| self.time_based_counter(Self::STEAM_DEFAULT_PERIOD, offset));
+        let full_token = encode_digest(calc_digest(&self.secret, counter, Algorithm::SHA1))?;
+        Ok(Rendering::steam().render(full_token, Self::STEAM_DEFAULT_DIGITS))
+    }
+
+    pub fn steam_at(&self, timestamp: u64) -> Result<String> {
+        self.steam(
+            Some(self.time_step(timestamp, Self::STEAM_DEFAULT_PERIOD)),
+            0,
+        )
     }
 
-    pub fn totp_formatted(&self, period: Option<u32>) -> Result<String> {
-        let counter = Self::time_based_counter(period.unwrap_or(Self::DEFAULT_PERIOD));
-        self.hotp_formatted(counter)
+    /// Performs the [Mobile-OTP](https://motp.sourceforge.net/) algorithm.
+    ///
+    /// The code is made of the first hex characters of the MD5 digest of the
+    /// time step (UNIX time / 10), the hex encoded secret and the PIN.
+    pub fn motp(&self, counter: Option<u64>, offset: i64) -> Result<String> {
+        let counter =
+            counter.unwrap_or_else(|| self.time_based_counter(Self::MOTP_DEFAULT_PERIOD, offset));
+        let Some(ref pin) = self.pin else {
+            anyhow::bail!("mOTP requires a PIN");
+        };
+        let digest = md5::compute(format!("{counter}{}{pin}", hex::encode(&self.secret)));
+        let code = format!("{digest:x}");
+        let digits = (self.digits as usize).min(code.len());
+        Ok(code[..digits].to_owned())
     }
 
-    pub fn steam(&self, counter: Option<u64>) -> Result<String> {
-        let counter = counter.unwrap_or(Self::time_based_counter(Self::STEAM_DEFAULT_PERIOD));
-        let mut full_token = encode_digest(calc_digest(&self.secret, counter, Algorithm::SHA1))?;
+    pub fn motp_at(&self, timestamp: u64) -> Result<String> {
+        self.motp(
+            Some(self.time_step(timestamp, Self::MOTP_DEFAULT_PERIOD)),
+            0,
+        )
+    }
+
+    /// Performs the Yandex.Key algorithm.
+    ///
+    /// The HMAC key is the SHA-256 digest of the PIN followed by the secret,
+    /// and the resulting code is rendered with lowercase latin letters.
+    pub fn yandex(&self, counter: Option<u64>, offset: i64) -> Result<String> {
+        let counter =
+            counter.unwrap_or_else(|| self.time_based_counter(Self::YANDEX_DEFAULT_PERIOD, offset));
+        let Some(ref pin) = self.pin else {
+            anyhow::bail!("Yandex requires a PIN");
+        };
+        let secret_len = self.secret.len().min(Self::YANDEX_SECRET_LENGTH);
+        let mut ctx = digest::Context::new(&digest::SHA256);
+        ctx.update(pin.as_bytes());
+        ctx.update(&self.secret[..secret_len]);
+        let key_hash = ctx.finish();
+        let mut key = key_hash.as_ref();
+        if key[0] == 0 {
+            key = &key[1..];
+        }
+
+        let digest = calc_digest(key, counter, Algorithm::SHA256);
+        let digest = digest.as_ref();
+        let offset = (digest[digest.len() - 1] & 0xf) as usize;
+        let code_bytes: [u8; 8] = digest[offset..offset + 8].try_into()?;
+        let mut full_token = u64::from_be_bytes(code_bytes) & 0x7fff_ffff_ffff_ffff;
+        full_token %= 26_u64
+            .checked_pow(self.digits)
+            .context("Invalid number of digits")?;
+
+        let mut code = vec!['a'; self.digits as usize];
+        for c in code.iter_mut().rev() {
+            *c = (b'a' + (full_token % 26) as u8) as char;
+            full_token /= 26;
+        }
+        Ok(code.into_iter().collect())
+    }
+
+    /// Computes the response to an [OCRA](https://www.rfc-editor.org/rfc/rfc6287) challenge.
+    ///
+    /// The counter, session information and timestamp are only used if the
+    /// suite requires them. The PIN falls back to the one stored with the
+    /// secret when none is given.
+    pub fn ocra(
+        &self,
+        suite: &OcraSuite,
+        challenge: &str,
+        counter: u64,
+        session: Option<&str>,
+        pin: Option<&str>,
+        timestamp: Option<u64>,
+    ) -> Result<String> {
+        let mut message = suite.as_str().as_bytes().to_vec();
+        message.push(0);
+
+        if suite.counter {
+            message.extend_from_slice(&counter.to_be_bytes());
+        }
 
-        let mut code = String::new();
-        let total_chars = Self::STEAM_CHARS.len() as u32;
-        for _ in 0..Self::STEAM_DEFAULT_DIGITS {
-            let pos = full_token % total_chars;
-            let charachter = Self::STEAM_CHARS.chars().nth(pos as usize).unwrap();
-            code.push(charachter);
-            full_token /= total_chars;
+        let challenge = challenge.trim();
+        if challenge.len() < OcraSuite::CHALLENGE_MIN_LENGTH
+            || challenge.len() > suite.challenge_length
+        {
+            anyhow::bail!(
+                "The challenge must be between {} and {} characters long",
+                OcraSuite::CHALLENGE_MIN_LENGTH,
+                suite.challenge_length
+            );
         }
-        Ok(code)
+        let question = match suite.challenge {
+            OcraChallenge::Numeric => decimal_to_hex(challenge)?,
+            OcraChallenge::Hex => {
+                if !challenge.chars().all(|c| c.is_ascii_hexdigit()) {
+                    anyhow::bail!("The challenge must be an hexadecimal string");
+                }
+                challenge.to_owned()
+            }
+            OcraChallenge::Alphanumeric => hex::encode(challenge),
+        };
+        // The question is always 128 bytes long, right padded with zeros
+        message.extend(hex::decode(format!("{question:0<256}"))?);
+
+        if let Some(hash) = suite.pin {
+            let Some(pin) = pin.or(self.pin.as_deref()) else {
+                anyhow::bail!("The OCRA suite requires a PIN");
+            };
+            let algorithm = match hash {
+                Algorithm::SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+                Algorithm::SHA256 => &digest::SHA256,
+                Algorithm::SHA512 => &digest::SHA512,
+            };
+            message.extend_from_slice(digest::digest(algorithm, pin.as_bytes()).as_ref());
+        }
+
+        if let Some(length) = suite.session_length {
+            let session = hex::decode(session.unwrap_or_default().trim())
+                .context("The session information must be an hexadecimal string")?;
+            if session.len() > length {
+                anyhow::bail!("The session information must be at most {length} bytes long");
+            }
+            message.resize(message.len() + length - session.len(), 0);
+            message.extend(session);
+        }
+
+        if let Some(step) = suite.time_step {
+            let timestamp = timestamp.unwrap_or_else(|| Self::timestamp(0));
+            message.extend_from_slice(&(timestamp / step).to_be_bytes());
+        }
+
+        let key = hmac::Key::new(suite.algorithm.into(), &self.secret);
+        let modulus = 10_u64
+            .checked_pow(suite.digits)
+            .context("Invalid number of digits")?;
+        let code = encode_digest(hmac::sign(&key, &message))? as u64 % modulus;
+        Ok(format!("{code:0digits$}", digits = suite.digits as usize))
+    }
+
+    pub fn yandex_at(&self, timestamp: u64) -> Result<String> {
+        self.yandex(
+            Some(self.time_step(timestamp, Self::YANDEX_DEFAULT_PERIOD)),
+            0,
+        )
     }
 
     pub fn secret(&self) -> String {
         data_encoding::BASE32_NOPAD.encode(&self.secret)
     }
+
+    pub fn pin(&self) -> Option<&str> {
+        self.pin.as_deref()
+    }
 }
 
 /// Code graciously taken from the rust-otp crate.
@@ -121,24 +413,138 @@ impl OTP {
 
 /// Decodes a secret (given as an RFC4648 base32-encoded ASCII string)
 /// into a byte string. It fails if secret is not a valid Base32 string.
-fn decode_secret(secret: &str) -> Result<Vec<u8>> {
-    let secret = secret.trim().replace(' ', "").to_ascii_uppercase();
-    // The buffer should have a length of secret.len() * 5 / 8.
-    BASE32_NOPAD
-        .decode(secret.as_bytes())
-        .map_err(|_| anyhow!("Invalid Input"))
+///
+/// Hex and Base64 secrets are supported as well, `SecretEncoding::Auto`
+/// guesses the encoding of the secret.
+fn decode_secret(secret: &str, encoding: SecretEncoding) -> Result<Vec<u8>> {
+    let secret = secret.trim().replace(' ', "");
+    let decoded = match encoding {
+        SecretEncoding::Auto => {
+            let encoding =
+                OTP::detect_encoding(&secret).context("Unknown or ambiguous secret encoding")?;
+            return decode_secret(&secret, encoding);
+        }
+        // The buffer should have a length of secret.len() * 5 / 8.
+        SecretEncoding::Base32 => {
+            BASE32_NOPAD.decode(secret.trim_end_matches('=').to_ascii_uppercase().as_bytes())
+        }
+        SecretEncoding::Hex => HEXLOWER_PERMISSIVE.decode(secret.as_bytes()),
+        SecretEncoding::Base64 => {
+            let secret = secret.trim_end_matches('=');
+            BASE64_NOPAD
+                .decode(secret.as_bytes())
+                .or_else(|_| BASE64URL_NOPAD.decode(secret.as_bytes()))
+        }
+    };
+    decoded.map_err(|_| anyhow!("Invalid Input"))
+}
+
+/// How the codes of a provider are displayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendering {
+    /// The number of characters per group, 0 disables the grouping.
+    pub group_size: u32,
+    /// The characters used instead of the decimal digits, like Steam does.
+    pub alphabet: Option<String>,
+}
+
+impl Default for Rendering {
+    fn default() -> Self {
+        Self {
+            group_size: Self::DEFAULT_GROUP_SIZE,
+            alphabet: None,
+        }
+    }
+}
+
+impl Rendering {
+    pub const DEFAULT_GROUP_SIZE: u32 = 3;
+
+    pub fn steam() -> Self {
+        Self {
+            group_size: 0,
+            alphabet: Some(OTP::STEAM_CHARS.to_owned()),
+        }
+    }
+
+    // An alphabet needs at least two distinct, visible, characters
+    pub fn is_valid_alphabet(alphabet: &str) -> bool {
+        let chars = alphabet.chars().collect::<Vec<_>>();
+        chars.len() > 1
+            && chars.iter().all(|c| !c.is_whitespace() && !c.is_control())
+            && chars
+                .iter()
+                .enumerate()
+                .all(|(idx, c)| !chars[..idx].contains(c))
+    }
+
+    /// Renders the truncated HMAC value `full_token` as a code of `digits`
+    /// characters.
+    ///
+    /// With a custom alphabet, the value is written in the base of the
+    /// alphabet starting from its least significant character.
+    pub fn render(&self, full_token: u32, digits: u32) -> String {
+        let code = match self.alphabet {
+            Some(ref alphabet) => {
+                let chars = alphabet.chars().collect::<Vec<_>>();
+                let base = chars.len() as u32;
+                let mut full_token = full_token;
+                let mut code = String::new();
+                for _ in 0..digits {
+                    code.push(chars[(full_token % base) as usize]);
+                    full_token /= base;
+                }
+                code
+            }
+            None => {
+                // The value has 10 digits at most, more are only padding
+                let code = 10_u64
+                    .checked_pow(digits)
+                    .map_or(full_token as u64, |modulus| full_token as u64 % modulus);
+                format!("{code:0digits$}", digits = digits as usize)
+            }
+        };
+        group(&code, self.group_size as usize)
+    }
+}
+
+/// Splits the `code` in groups of `size` characters, starting from the end.
+fn group(code: &str, size: usize) -> String {
+    if size == 0 {
+        return code.to_owned();
+    }
+    let length = code.chars().count();
+    let mut grouped_code = String::new();
+    for (idx, ch) in code.chars().enumerate() {
+        if idx != 0 && (length - idx) % size == 0 {
+            grouped_code.push(' ');
+        }
+        grouped_code.push(ch);
+    }
+    grouped_code
 }
 
-fn format(code: u32, digits: usize) -> String {
-    let padded_code = format!("{code:0digits$}");
-    let mut formated_code = String::new();
-    for (idx, ch) in padded_code.chars().enumerate() {
-        if (digits - idx) % 3 == 0 && (digits - idx) != 0 && idx != 0 {
-            formated_code.push(' ');
+/// Converts a decimal string of any length into its hexadecimal representation,
+/// without leading zeros, as expected by the OCRA numeric challenges.
+fn decimal_to_hex(decimal: &str) -> Result<String> {
+    // Big-endian bytes of the number
+    let mut bytes = vec![0u8];
+    for c in decimal.chars() {
+        let Some(mut carry) = c.to_digit(10) else {
+            anyhow::bail!("The challenge must be a numeric string");
+        };
+        for byte in bytes.iter_mut().rev() {
+            let value = *byte as u32 * 10 + carry;
+            *byte = (value & 0xff) as u8;
+            carry = value >> 8;
+        }
+        if carry > 0 {
+            bytes.insert(0, carry as u8);
         }
-        formated_code.push(ch);
     }
-    formated_code
+    let hex = hex::encode(bytes);
+    let hex = hex.trim_start_matches('0');
+    Ok(if hex.is_empty() { "0" } else { hex }.to_owned())
 }
 
 /// Calculates the HMAC digest for the given secret and counter.
@@ -165,7 +571,7 @@ fn encode_digest(digest: impl AsRef<[u8]>) -> Result<u32> {
 // Some of the tests are heavily inspired(copy-paste) of the andOTP application
 #[cfg(test)]
 mod tests {
-    use super::{format, Algorithm, OTP};
+    use super::{Algorithm, OcraSuite, Rendering, SecretEncoding, OTP};
 
     #[test]
     fn totp() {
@@ -228,20 +634,360 @@ mod tests {
         assert_eq!(Some(520489), otp.hotp(9).ok());
     }
 
+    #[test]
+    fn totp_at() {
+        let otp = OTP::from_bytes(b"12345678901234567890", Algorithm::SHA1, 8);
+        assert_eq!(otp.totp_at(None, 59).ok(), Some(94287082));
+        assert_eq!(otp.totp_at(Some(30), 1111111109).ok(), Some(7081804));
+        assert_eq!(
+            otp.totp_formatted_at(Some(30), 1111111109, &Rendering::default())
+                .ok(),
+            Some("07 081 804".into())
+        );
+        // The next code is the one of the following step
+        assert_eq!(otp.totp_at(Some(30), 1111111109 + 30).ok(), Some(14050471));
+
+        let token = OTP::from_str_steam("BASE32SECRET3232").unwrap();
+        assert_eq!(token.steam_at(29).ok(), Some("2TC8B".into()));
+        assert_eq!(token.steam_at(30).ok(), Some("YKKK4".into()));
+
+        let secret = hex::decode("e3152afee62599c8").unwrap();
+        let token = OTP::from_bytes(secret, Algorithm::SHA1, OTP::MOTP_DEFAULT_DIGITS)
+            .with_pin(Some("1234"));
+        assert_eq!(token.motp_at(1165762715).ok(), Some("83a7e8".into()));
+
+        let token = OTP::from_str(
+            "6SB2IKNM6OBZPAVBVTOHDKS4FAAAAAAADFUTQMBTRY",
+            SecretEncoding::Base32,
+            Algorithm::SHA256,
+            OTP::YANDEX_DEFAULT_DIGITS,
+        )
+        .unwrap()
+        .with_pin(Some("5239"));
+        assert_eq!(token.yandex_at(1641559648).ok(), Some("umozdicq".into()));
+    }
+
+    #[test]
+    fn verify() {
+        let otp = OTP::from_bytes(b"12345678901234567890", Algorithm::SHA1, 8);
+        assert_eq!(otp.verify_totp("94287082", 30, 59, 0), Some(1));
+        assert_eq!(otp.verify_totp("94 287 082", 30, 59, 0), Some(1));
+        assert_eq!(
+            otp.verify_totp("07081804", 30, 1111111109, 1),
+            Some(37037036)
+        );
+        // A code from the previous and the next step
+        assert_eq!(otp.verify_totp("07081804", 30, 1111111109 + 30, 0), None);
+        assert_eq!(
+            otp.verify_totp("07081804", 30, 1111111109 + 30, 1),
+            Some(37037036)
+        );
+        assert_eq!(
+            otp.verify_totp("14050471", 30, 1111111109, 1),
+            Some(37037037)
+        );
+        assert_eq!(otp.verify_totp("12345678", 30, 59, 2), None);
+
+        let otp = OTP::from_bytes_with_defaults(b"12345678901234567890");
+        assert_eq!(otp.verify_hotp("755224", 0, 0), Some(0));
+        assert_eq!(otp.verify_hotp("969429", 0, 0), None);
+        assert_eq!(otp.verify_hotp("969429", 0, 5), Some(3));
+        assert_eq!(otp.verify_hotp("969429", 4, 5), None);
+        // Codes are zero padded
+        assert_eq!(otp.verify_hotp("055283", 0, 0), None);
+        let otp = OTP::from_str_with_defaults("BASE32SECRET3232").unwrap();
+        assert_eq!(otp.verify_hotp("055283", 0, 10), Some(1));
+    }
+
+    #[test]
+    fn epoch() {
+        let otp = OTP::from_bytes(b"12345678901234567890", Algorithm::SHA1, 8).with_epoch(1000);
+        // The steps are counted from T0
+        assert_eq!(otp.time_step(1000, 30), 0);
+        assert_eq!(otp.time_step(1029, 30), 0);
+        assert_eq!(otp.time_step(1030, 30), 1);
+        assert_eq!(otp.time_step(999, 30), 0);
+        assert_eq!(otp.totp_at(None, 1000 + 59).ok(), Some(94287082));
+        assert_eq!(otp.totp_at(Some(30), 1000 + 1111111109).ok(), Some(7081804));
+        assert_eq!(otp.verify_totp("94287082", 30, 1000 + 59, 0), Some(1));
+        assert_eq!(otp.verify_totp("94287082", 30, 59, 0), None);
+
+        let token = OTP::from_str_steam("BASE32SECRET3232")
+            .unwrap()
+            .with_epoch(15);
+        assert_eq!(token.steam_at(44).ok(), Some("2TC8B".into()));
+        assert_eq!(token.steam_at(45).ok(), Some("YKKK4".into()));
+    }
+
     #[test]
     fn steam() {
         let token = OTP::from_str_steam("BASE32SECRET3232").unwrap();
-        assert_eq!(token.steam(Some(0)).ok(), Some("2TC8B".into()));
-        assert_eq!(token.steam(Some(1)).ok(), Some("YKKK4".into()));
+        assert_eq!(token.steam(Some(0), 0).ok(), Some("2TC8B".into()));
+        assert_eq!(token.steam(Some(1), 0).ok(), Some("YKKK4".into()));
+    }
+
+    #[test]
+    fn motp() {
+        let secret = hex::decode("e3152afee62599c8").unwrap();
+        let token = OTP::from_bytes(secret, Algorithm::SHA1, OTP::MOTP_DEFAULT_DIGITS)
+            .with_pin(Some("1234"));
+        assert_eq!(token.motp(Some(0), 0).ok(), Some("2c244b".into()));
+        assert_eq!(token.motp(Some(116576271), 0).ok(), Some("83a7e8".into()));
+        assert_eq!(token.motp(Some(116576272), 0).ok(), Some("836b1f".into()));
+
+        let token = token.with_pin(Some("9876"));
+        assert_eq!(token.motp(Some(123456789), 0).ok(), Some("2922c1".into()));
+
+        let token = token.with_pin(None);
+        assert!(token.motp(Some(0), 0).is_err());
+    }
+
+    #[test]
+    fn yandex() {
+        let vectors = [
+            (
+                "5239",
+                "6SB2IKNM6OBZPAVBVTOHDKS4FAAAAAAADFUTQMBTRY",
+                1641559648,
+                "umozdicq",
+            ),
+            (
+                "7586",
+                "LA2V6KMCGYMWWVEW64RNP3JA3IAAAAAAHTSG4HRZPI",
+                1581064020,
+                "oactmacq",
+            ),
+            (
+                "7586",
+                "LA2V6KMCGYMWWVEW64RNP3JA3IAAAAAAHTSG4HRZPI",
+                1581090810,
+                "wemdwrix",
+            ),
+            (
+                "5210481216086702",
+                "JBGSAU4G7IEZG6OY4UAXX62JU4AAAAAAHTSG4HXU3M",
+                1581091469,
+                "dfrpywob",
+            ),
+            (
+                "5210481216086702",
+                "JBGSAU4G7IEZG6OY4UAXX62JU4AAAAAAHTSG4HXU3M",
+                1581093059,
+                "vunyprpd",
+            ),
+        ];
+        for (pin, secret, timestamp, code) in vectors {
+            let token = OTP::from_str(
+                secret,
+                SecretEncoding::Base32,
+                Algorithm::SHA256,
+                OTP::YANDEX_DEFAULT_DIGITS,
+            )
+            .unwrap()
+            .with_pin(Some(pin));
+            let counter = timestamp / OTP::YANDEX_DEFAULT_PERIOD as u64;
+            assert_eq!(token.yandex(Some(counter), 0).ok(), Some(code.into()));
+        }
+    }
+
+    // The test vectors of RFC 6287, Appendix C
+    #[test]
+    fn ocra() {
+        let secret_sha1 = b"12345678901234567890";
+        let secret_sha256 = b"12345678901234567890123456789012";
+        let secret_sha512 = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+        let otp = OTP::from_bytes(secret_sha1, Algorithm::SHA1, 6);
+        let suite = "OCRA-1:HOTP-SHA1-6:QN08".parse::<OcraSuite>().unwrap();
+        let codes = [
+            "237653", "243178", "653583", "740991", "608993", "388898", "816933", "224598",
+            "750600", "294470",
+        ];
+        for (i, code) in codes.into_iter().enumerate() {
+            let challenge = i.to_string().repeat(8);
+            let response = otp.ocra(&suite, &challenge, 0, None, None, None);
+            assert_eq!(response.ok(), Some(code.into()));
+        }
+
+        let otp = OTP::from_bytes(secret_sha256, Algorithm::SHA256, 8);
+        let suite = "OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1"
+            .parse::<OcraSuite>()
+            .unwrap();
+        let codes = [
+            "65347737", "86775851", "78192410", "71565254", "10104329", "65983500", "70069104",
+            "91771096", "75011558", "08522129",
+        ];
+        for (counter, code) in codes.into_iter().enumerate() {
+            let response = otp.ocra(&suite, "12345678", counter as u64, None, Some("1234"), None);
+            assert_eq!(response.ok(), Some(code.into()));
+        }
+
+        // The PIN stored alongside the secret is used as a fallback
+        let otp = otp.with_pin(Some("1234"));
+        let suite = "OCRA-1:HOTP-SHA256-8:QN08-PSHA1"
+            .parse::<OcraSuite>()
+            .unwrap();
+        let codes = ["83238735", "01501458", "17957585", "86776967", "86807031"];
+        for (i, code) in codes.into_iter().enumerate() {
+            let challenge = i.to_string().repeat(8);
+            let response = otp.ocra(&suite, &challenge, 0, None, None, None);
+            assert_eq!(response.ok(), Some(code.into()));
+        }
+
+        let otp = OTP::from_bytes(secret_sha512, Algorithm::SHA512, 8);
+        let suite = "OCRA-1:HOTP-SHA512-8:C-QN08".parse::<OcraSuite>().unwrap();
+        let codes = [
+            "07016083", "63947962", "70123924", "25341727", "33203315", "34205738", "44343969",
+            "51946085", "20403879", "31409299",
+        ];
+        for (i, code) in codes.into_iter().enumerate() {
+            let challenge = i.to_string().repeat(8);
+            let response = otp.ocra(&suite, &challenge, i as u64, None, None, None);
+            assert_eq!(response.ok(), Some(code.into()));
+        }
+
+        let suite = "OCRA-1:HOTP-SHA512-8:QN08-T1M"
+            .parse::<OcraSuite>()
+            .unwrap();
+        let timestamp = 0x132d0b6 * 60;
+        let codes = ["95209754", "55907591", "22048402", "24218844", "36209546"];
+        for (i, code) in codes.into_iter().enumerate() {
+            let challenge = i.to_string().repeat(8);
+            let response = otp.ocra(&suite, &challenge, 0, None, None, Some(timestamp));
+            assert_eq!(response.ok(), Some(code.into()));
+        }
+
+        // Plain signature
+        let otp = OTP::from_bytes(secret_sha256, Algorithm::SHA256, 8);
+        let suite = "OCRA-1:HOTP-SHA256-8:QA08".parse::<OcraSuite>().unwrap();
+        let codes = ["53095496", "04110475", "31331128", "76028668", "46554205"];
+        for (i, code) in codes.into_iter().enumerate() {
+            let challenge = format!("SIG1{i}000");
+            let response = otp.ocra(&suite, &challenge, 0, None, None, None);
+            assert_eq!(response.ok(), Some(code.into()));
+        }
+
+        let otp = OTP::from_bytes(secret_sha512, Algorithm::SHA512, 8);
+        let suite = "OCRA-1:HOTP-SHA512-8:QA10-T1M"
+            .parse::<OcraSuite>()
+            .unwrap();
+        let codes = ["77537423", "31970405", "10235557", "95213541", "65360607"];
+        for (i, code) in codes.into_iter().enumerate() {
+            let challenge = format!("SIG1{i}00000");
+            let response = otp.ocra(&suite, &challenge, 0, None, None, Some(timestamp));
+            assert_eq!(response.ok(), Some(code.into()));
+        }
+
+        // Invalid challenges
+        let suite = "OCRA-1:HOTP-SHA256-8:QN08-PSHA1"
+            .parse::<OcraSuite>()
+            .unwrap();
+        assert!(otp.ocra(&suite, "1234abcd", 0, None, None, None).is_err());
+        assert!(otp
+            .ocra(&suite, "123", 0, None, Some("1234"), None)
+            .is_err());
+        assert!(otp.ocra(&suite, "12345678", 0, None, None, None).is_err());
     }
 
     #[test]
     fn otp_format() {
-        assert_eq!(format(1234, 5), "01 234");
-        assert_eq!(format(1234, 6), "001 234");
-        assert_eq!(format(123456, 6), "123 456");
-        assert_eq!(format(1234, 7), "0 001 234");
-        assert_eq!(format(1234567, 8), "01 234 567");
-        assert_eq!(format(12345678, 8), "12 345 678");
+        let rendering = Rendering::default();
+        assert_eq!(rendering.render(1234, 5), "01 234");
+        assert_eq!(rendering.render(1234, 6), "001 234");
+        assert_eq!(rendering.render(123456, 6), "123 456");
+        assert_eq!(rendering.render(1234, 7), "0 001 234");
+        assert_eq!(rendering.render(1234567, 8), "01 234 567");
+        assert_eq!(rendering.render(12345678, 8), "12 345 678");
+        assert_eq!(rendering.render(1284755224, 10), "1 284 755 224");
+
+        let rendering = Rendering {
+            group_size: 0,
+            alphabet: None,
+        };
+        assert_eq!(rendering.render(1234, 6), "001234");
+
+        let rendering = Rendering {
+            group_size: 4,
+            alphabet: None,
+        };
+        assert_eq!(rendering.render(12345678, 8), "1234 5678");
+        assert_eq!(rendering.render(123456, 6), "12 3456");
+
+        let rendering = Rendering {
+            group_size: 0,
+            alphabet: Some("01".to_owned()),
+        };
+        assert_eq!(rendering.render(6, 4), "0110");
+
+        assert!(Rendering::is_valid_alphabet("23456789BCDFGHJKMNPQRTVWXY"));
+        assert!(!Rendering::is_valid_alphabet("A"));
+        assert!(!Rendering::is_valid_alphabet("ABA"));
+        assert!(!Rendering::is_valid_alphabet("A B"));
+    }
+
+    #[test]
+    fn secret_encodings() {
+        let base32 = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let hex = "3132333435363738393031323334353637383930";
+        let base64 = "MTIzNDU2Nzg5MDEyMzQ1Njc4OTA=";
+
+        assert_eq!(OTP::detect_encoding(base32), Some(SecretEncoding::Base32));
+        assert_eq!(
+            OTP::detect_encoding(&base32.to_lowercase()),
+            Some(SecretEncoding::Base32)
+        );
+        assert_eq!(OTP::detect_encoding(hex), Some(SecretEncoding::Hex));
+        assert_eq!(OTP::detect_encoding(base64), Some(SecretEncoding::Base64));
+        assert_eq!(OTP::detect_encoding("not a secret!"), None);
+        // Valid Base32 and hex
+        assert_eq!(
+            OTP::candidate_encodings("deadbeef"),
+            vec![SecretEncoding::Base32, SecretEncoding::Hex]
+        );
+        assert_eq!(OTP::detect_encoding("deadbeef"), None);
+        assert!(OTP::from_str("deadbeef", SecretEncoding::Auto, Algorithm::SHA1, 6).is_err());
+        assert!(OTP::from_str("deadbeef", SecretEncoding::Hex, Algorithm::SHA1, 6).is_ok());
+
+        for (input, encoding) in [
+            (base32, SecretEncoding::Base32),
+            (hex, SecretEncoding::Hex),
+            (&hex.to_uppercase(), SecretEncoding::Hex),
+            (base64, SecretEncoding::Base64),
+            ("MTIzNDU2Nzg5MDEyMzQ1Njc4OTA", SecretEncoding::Base64),
+            (base32, SecretEncoding::Auto),
+            (hex, SecretEncoding::Auto),
+            (base64, SecretEncoding::Auto),
+        ] {
+            let otp = OTP::from_str(input, encoding, Algorithm::SHA1, 6).unwrap();
+            // Every secret is normalised to Base32
+            assert_eq!(otp.secret(), base32);
+            assert_eq!(otp.hotp(0).ok(), Some(755224));
+        }
+        assert!(OTP::from_str(hex, SecretEncoding::Base32, Algorithm::SHA1, 6).is_err());
+        assert!(OTP::from_str(base64, SecretEncoding::Hex, Algorithm::SHA1, 6).is_err());
+    }
+
+    #[test]
+    fn ten_digits() {
+        let otp = OTP::from_bytes(b"12345678901234567890", Algorithm::SHA1, 10);
+        assert_eq!(otp.hotp(0).ok(), Some(1284755224));
+        assert_eq!(otp.hotp(1).ok(), Some(1094287082));
+        assert_eq!(
+            otp.hotp_formatted(0, &Rendering::default()).ok(),
+            Some("1 284 755 224".into())
+        );
+
+        assert!(OTP::is_valid_digits(1));
+        assert!(OTP::is_valid_digits(10));
+        assert!(!OTP::is_valid_digits(0));
+        assert!(!OTP::is_valid_digits(11));
+
+        // Stored before the number of digits was checked, it must not panic
+        let otp = OTP::from_bytes(b"12345678901234567890", Algorithm::SHA1, 20);
+        assert!(otp.hotp(0).is_err());
+        assert_eq!(
+            Rendering::default().render(1284755224, 20),
+            "00 000 000 001 284 755 224"
+        );
     }
 }
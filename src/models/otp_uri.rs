@@ -1,14 +1,97 @@
-use std::{fmt::Write, str::FromStr};
+use std::{
+    fmt::{self, Write},
+    str::FromStr,
+};
 
+use gettextrs::gettext;
 use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
 use url::Url;
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::{
     backup::RestorableItem,
-    models::{Account, Algorithm, Method, OTP},
+    models::{i18n, Account, Algorithm, Method, SecretEncoding, OTP},
 };
 
+// Same bound as the period of a provider
+const MAX_PERIOD: u32 = 1000;
+
+/// A single problem found by [`OTPUri::from_str_strict`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OTPUriProblem {
+    InvalidUri,
+    InvalidScheme(String),
+    MissingHost,
+    UnsupportedMethod(String),
+    InvalidLabel,
+    MissingSecret,
+    MissingPin,
+    InvalidSecret,
+    UnsupportedAlgorithm(String),
+    InvalidDigits(String),
+    InvalidPeriod(String),
+    InvalidCounter(String),
+    IssuerMismatch { label: String, parameter: String },
+}
+
+impl fmt::Display for OTPUriProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Self::InvalidUri => gettext("Not a valid URI"),
+            Self::InvalidScheme(scheme) => {
+                i18n::i18n_f("Invalid scheme “{}”, expected otpauth", &[scheme])
+            }
+            Self::MissingHost => gettext("Missing the type of the token"),
+            Self::UnsupportedMethod(method) => {
+                i18n::i18n_f("Unsupported token type “{}”", &[method])
+            }
+            Self::InvalidLabel => gettext("The label or the issuer is not valid UTF-8"),
+            Self::MissingSecret => gettext("Missing secret"),
+            Self::MissingPin => gettext("Missing the PIN"),
+            Self::InvalidSecret => gettext("The secret is not valid Base32"),
+            Self::UnsupportedAlgorithm(algorithm) => {
+                i18n::i18n_f("Unsupported algorithm “{}”", &[algorithm])
+            }
+            Self::InvalidDigits(digits) => i18n::i18n_f(
+                "Invalid number of digits “{}”, expected {} to {}",
+                &[
+                    digits,
+                    &OTP::MIN_DIGITS.to_string(),
+                    &OTP::MAX_DIGITS.to_string(),
+                ],
+            ),
+            Self::InvalidPeriod(period) => i18n::i18n_f(
+                "Invalid period “{}”, expected 1 to {} seconds",
+                &[period, &MAX_PERIOD.to_string()],
+            ),
+            Self::InvalidCounter(counter) => i18n::i18n_f("Invalid counter “{}”", &[counter]),
+            Self::IssuerMismatch { label, parameter } => i18n::i18n_f(
+                "The issuer of the label “{}” doesn't match the issuer “{}”",
+                &[label, parameter],
+            ),
+        };
+        f.write_str(&message)
+    }
+}
+
+/// Every problem found in an otpauth URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OTPUriError(pub Vec<OTPUriProblem>);
+
+impl fmt::Display for OTPUriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let problems = self
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        f.write_str(&problems)
+    }
+}
+
+impl std::error::Error for OTPUriError {}
+
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Zeroize, ZeroizeOnDrop)]
 pub struct OTPUri {
@@ -27,6 +110,19 @@ pub struct OTPUri {
     pub(crate) period: Option<u32>,
     #[zeroize(skip)]
     pub(crate) counter: Option<u32>,
+    pub(crate) pin: Option<String>,
+    #[zeroize(skip)]
+    pub(crate) epoch: Option<u64>,
+    // Extensions used by FreeOTP+ and Aegis
+    #[zeroize(skip)]
+    pub(crate) image: Option<String>,
+    #[zeroize(skip)]
+    pub(crate) color: Option<String>,
+    #[zeroize(skip)]
+    pub(crate) tags: Vec<String>,
+    // Parameters we don't know about, kept to write them back as is
+    #[zeroize(skip)]
+    pub(crate) extra: Vec<(String, String)>,
 }
 
 impl RestorableItem for OTPUri {
@@ -61,11 +157,30 @@ impl RestorableItem for OTPUri {
     fn counter(&self) -> Option<u32> {
         self.counter
     }
+
+    fn pin(&self) -> Option<String> {
+        self.pin.clone()
+    }
+
+    fn epoch(&self) -> Option<u64> {
+        self.epoch
+    }
+
+    fn image(&self) -> Option<String> {
+        self.image.clone()
+    }
+
+    fn otpauth_params(&self) -> Option<String> {
+        self.extensions()
+    }
 }
 
 impl TryFrom<Url> for OTPUri {
     type Error = anyhow::Error;
     fn try_from(url: Url) -> Result<Self, Self::Error> {
+        if url.scheme() == "steam" {
+            return Self::from_steam_url(&url);
+        }
         if url.scheme() != "otpauth" {
             anyhow::bail!(
                 "Invalid OTP uri format, expected otpauth, got {}",
@@ -79,10 +194,13 @@ impl TryFrom<Url> for OTPUri {
         let mut provider_name = None;
         let mut algorithm = None;
         let mut secret = None;
+        let mut pin = None;
+        let mut epoch = None;
+        let mut extensions = Vec::new();
 
         let pairs = url.query_pairs();
 
-        let method = Method::from_str(url.host_str().unwrap())?;
+        let method = Method::from_str(url.host_str().unwrap_or_default())?;
 
         let account_info = url
             .path()
@@ -98,7 +216,7 @@ impl TryFrom<Url> for OTPUri {
             account_info.get(1).unwrap()
         };
 
-        pairs.for_each(|(key, value)| match key.into_owned().as_str() {
+        pairs.for_each(|(key, value)| match key.as_ref() {
             "period" => {
                 period = value.parse::<u32>().ok();
             }
@@ -117,7 +235,14 @@ impl TryFrom<Url> for OTPUri {
             "secret" => {
                 secret = Some(value.to_string());
             }
-            _ => (),
+            "pin" => {
+                pin = Some(value.to_string());
+            }
+            // Not part of the Key URI format, some generators use it to carry T0
+            "epoch" | "t0" => {
+                epoch = value.parse::<u64>().ok();
+            }
+            _ => extensions.push((key.into_owned(), value.into_owned())),
         });
 
         if secret.is_none() {
@@ -131,7 +256,17 @@ impl TryFrom<Url> for OTPUri {
             "Default".to_string()
         };
 
-        Ok(Self {
+        // mOTP tokens use a 10 seconds step unless told otherwise
+        if method == Method::MOTP && period.is_none() {
+            period = Some(OTP::MOTP_DEFAULT_PERIOD);
+        }
+        // Yandex.Key always uses SHA-256 and 8 letters codes
+        if method == Method::Yandex {
+            algorithm = algorithm.or(Some(Algorithm::SHA256));
+            digits = digits.or(Some(OTP::YANDEX_DEFAULT_DIGITS));
+        }
+
+        let mut otp_uri = Self {
             method,
             label,
             secret: secret.unwrap(),
@@ -140,6 +275,205 @@ impl TryFrom<Url> for OTPUri {
             digits,
             period,
             counter,
+            pin,
+            epoch,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        };
+        for (key, value) in extensions {
+            otp_uri.set_extension(&key, value);
+        }
+        Ok(otp_uri)
+    }
+}
+
+impl OTPUri {
+    /// Steam Guard secrets are shared as `steam://<base32 secret>`, without
+    /// any label, so the account gets a placeholder name.
+    fn from_steam_url(url: &Url) -> anyhow::Result<Self> {
+        let secret = url
+            .host_str()
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Steam uri must contain a secret"))?
+            .to_uppercase();
+        Ok(Self {
+            method: Method::Steam,
+            label: gettext("Unknown account"),
+            secret,
+            issuer: "Steam".to_owned(),
+            algorithm: Algorithm::SHA1,
+            digits: Some(OTP::STEAM_DEFAULT_DIGITS),
+            period: Some(OTP::STEAM_DEFAULT_PERIOD),
+            counter: None,
+            pin: None,
+            epoch: None,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        })
+    }
+
+    fn set_extension(&mut self, key: &str, value: String) {
+        match key {
+            "image" => self.image = Some(value),
+            "color" => self.color = Some(value),
+            "tags" => {
+                self.tags = value
+                    .split(',')
+                    .filter(|tag| !tag.is_empty())
+                    .map(ToOwned::to_owned)
+                    .collect();
+            }
+            _ => self.extra.push((key.to_owned(), value)),
+        }
+    }
+
+    fn extension_pairs(&self) -> Vec<(&str, String)> {
+        let mut pairs = Vec::new();
+        if let Some(ref image) = self.image {
+            pairs.push(("image", image.clone()));
+        }
+        if let Some(ref color) = self.color {
+            pairs.push(("color", color.clone()));
+        }
+        if !self.tags.is_empty() {
+            pairs.push(("tags", self.tags.join(",")));
+        }
+        for (key, value) in &self.extra {
+            pairs.push((key.as_str(), value.clone()));
+        }
+        pairs
+    }
+
+    /// The extension parameters as a query string, stored along the account
+    /// so they can be written back to the URI.
+    pub fn extensions(&self) -> Option<String> {
+        let pairs = self.extension_pairs();
+        if pairs.is_empty() {
+            return None;
+        }
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(pairs)
+            .finish();
+        Some(query)
+    }
+
+    /// Parses an otpauth URI, rejecting anything that would otherwise be
+    /// silently ignored and lead to a misconfigured account.
+    pub fn from_str_strict(uri: &str) -> Result<Self, OTPUriError> {
+        let url = Url::parse(uri).map_err(|_| OTPUriError(vec![OTPUriProblem::InvalidUri]))?;
+        let mut problems = Vec::new();
+
+        if url.scheme() == "steam" {
+            return match url.host_str() {
+                Some(secret) if OTP::from_str_steam(&secret.to_uppercase()).is_ok() => {
+                    Self::from_steam_url(&url)
+                        .map_err(|_| OTPUriError(vec![OTPUriProblem::MissingSecret]))
+                }
+                Some(secret) if !secret.is_empty() => {
+                    Err(OTPUriError(vec![OTPUriProblem::InvalidSecret]))
+                }
+                _ => Err(OTPUriError(vec![OTPUriProblem::MissingSecret])),
+            };
+        }
+        if url.scheme() != "otpauth" {
+            problems.push(OTPUriProblem::InvalidScheme(url.scheme().to_owned()));
+        }
+        let mut method = None;
+        match url.host_str() {
+            None | Some("") => problems.push(OTPUriProblem::MissingHost),
+            Some(host) => match Method::from_str(host) {
+                Ok(host_method) => method = Some(host_method),
+                Err(_) => problems.push(OTPUriProblem::UnsupportedMethod(host.to_owned())),
+            },
+        }
+
+        // The decoded query pairs replace invalid UTF-8, look at the raw issuer
+        let is_valid_issuer = url
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .filter(|(key, _)| *key == "issuer")
+            .all(|(_, value)| percent_decode_str(value).decode_utf8().is_ok());
+        if !is_valid_issuer {
+            problems.push(OTPUriProblem::InvalidLabel);
+        }
+
+        let mut secret = None;
+        let mut issuer = None;
+        let mut pin = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "secret" => secret = Some(value.into_owned()),
+                "issuer" => issuer = Some(value.into_owned()),
+                "pin" => pin = Some(value.into_owned()),
+                "algorithm" if Algorithm::from_str(&value).is_err() => {
+                    problems.push(OTPUriProblem::UnsupportedAlgorithm(value.into_owned()))
+                }
+                "digits" if !value.parse::<u32>().is_ok_and(OTP::is_valid_digits) => {
+                    problems.push(OTPUriProblem::InvalidDigits(value.into_owned()))
+                }
+                "period"
+                    if !value
+                        .parse::<u32>()
+                        .is_ok_and(|period| (1..=MAX_PERIOD).contains(&period)) =>
+                {
+                    problems.push(OTPUriProblem::InvalidPeriod(value.into_owned()))
+                }
+                "counter" if value.parse::<u32>().is_err() => {
+                    problems.push(OTPUriProblem::InvalidCounter(value.into_owned()))
+                }
+                _ => (),
+            }
+        }
+
+        match secret {
+            None => problems.push(OTPUriProblem::MissingSecret),
+            Some(ref secret)
+                if OTP::from_str(
+                    secret,
+                    SecretEncoding::Base32,
+                    Algorithm::default(),
+                    OTP::DEFAULT_DIGITS,
+                )
+                .is_err() =>
+            {
+                problems.push(OTPUriProblem::InvalidSecret)
+            }
+            Some(_) => (),
+        }
+        if method.is_some_and(Method::requires_pin) && pin.as_deref().unwrap_or_default().is_empty()
+        {
+            problems.push(OTPUriProblem::MissingPin);
+        }
+
+        let label = percent_decode_str(url.path().trim_start_matches('/'));
+        if label.clone().decode_utf8().is_err() {
+            problems.push(OTPUriProblem::InvalidLabel);
+        }
+        // "Provider:Account" labels must agree with the issuer parameter
+        let label = label.decode_utf8_lossy();
+        if let (Some((label_issuer, _)), Some(issuer)) = (label.split_once(':'), issuer) {
+            if label_issuer != issuer {
+                problems.push(OTPUriProblem::IssuerMismatch {
+                    label: label_issuer.to_owned(),
+                    parameter: issuer,
+                });
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(OTPUriError(problems));
+        }
+        // Every way the conversion can fail has been reported above, this is
+        // only reached for problems the strict checks missed
+        Self::try_from(url).map_err(|err| {
+            tracing::warn!("Strict checks accepted an invalid otpauth URI: {err}");
+            OTPUriError(vec![OTPUriProblem::InvalidUri])
         })
     }
 }
@@ -154,9 +488,13 @@ impl FromStr for OTPUri {
 
 impl From<OTPUri> for String {
     fn from(val: OTPUri) -> Self {
+        let method = match val.method {
+            Method::Yandex => "yaotp".to_owned(),
+            method => method.to_string(),
+        };
         let mut otp_uri = format!(
             "otpauth://{}/{}?secret={}&issuer={}&algorithm={}",
-            val.method.to_string(),
+            method,
             utf8_percent_encode(&val.label, NON_ALPHANUMERIC),
             val.secret,
             utf8_percent_encode(&val.issuer, NON_ALPHANUMERIC),
@@ -165,6 +503,14 @@ impl From<OTPUri> for String {
         if let Some(digits) = val.digits {
             write!(otp_uri, "&digits={digits}").unwrap();
         }
+        if let Some(ref pin) = val.pin {
+            write!(
+                otp_uri,
+                "&pin={}",
+                utf8_percent_encode(pin, NON_ALPHANUMERIC)
+            )
+            .unwrap();
+        }
         if val.method.is_event_based() {
             write!(
                 otp_uri,
@@ -179,6 +525,18 @@ impl From<OTPUri> for String {
                 val.period.unwrap_or(OTP::DEFAULT_PERIOD)
             )
             .unwrap();
+            if let Some(epoch) = val.epoch.filter(|epoch| *epoch != 0) {
+                write!(otp_uri, "&epoch={epoch}").unwrap();
+            }
+        }
+        for (key, value) in val.extension_pairs() {
+            write!(
+                otp_uri,
+                "&{}={}",
+                utf8_percent_encode(key, NON_ALPHANUMERIC),
+                utf8_percent_encode(&value, NON_ALPHANUMERIC)
+            )
+            .unwrap();
         }
         otp_uri
     }
@@ -186,7 +544,7 @@ impl From<OTPUri> for String {
 
 impl From<&Account> for OTPUri {
     fn from(a: &Account) -> Self {
-        Self {
+        let mut otp_uri = Self {
             method: a.provider().method(),
             label: a.name(),
             secret: a.otp().secret(),
@@ -195,7 +553,19 @@ impl From<&Account> for OTPUri {
             digits: Some(a.provider().digits()),
             period: Some(a.provider().period()),
             counter: Some(a.counter()),
+            pin: a.otp().pin().map(ToOwned::to_owned),
+            epoch: Some(a.provider().epoch()),
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        };
+        if let Some(params) = a.otpauth_params() {
+            for (key, value) in url::form_urlencoded::parse(params.as_bytes()) {
+                otp_uri.set_extension(&key, value.into_owned());
+            }
         }
+        otp_uri
     }
 }
 
@@ -203,7 +573,7 @@ impl From<&Account> for OTPUri {
 mod tests {
     use std::str::FromStr;
 
-    use super::OTPUri;
+    use super::{OTPUri, OTPUriProblem};
     use crate::{
         backup::RestorableItem,
         models::{Algorithm, Method},
@@ -235,6 +605,126 @@ mod tests {
         assert_eq!(uri.account(), "sbeve72");
         assert_eq!(uri.secret(), "[secret]");
         assert_eq!(uri.method(), Method::TOTP);
+
+        let uri =
+            OTPUri::from_str("otpauth://motp/VPN:alice?secret=4MKSV7XGEWM4Q&pin=1234&issuer=VPN")
+                .unwrap();
+        assert_eq!(uri.method(), Method::MOTP);
+        assert_eq!(uri.issuer(), "VPN");
+        assert_eq!(uri.account(), "alice");
+        assert_eq!(uri.secret(), "4MKSV7XGEWM4Q");
+        assert_eq!(uri.pin(), Some("1234".to_owned()));
+        assert_eq!(uri.period(), Some(10));
+
+        let uri = OTPUri::from_str(
+            "otpauth://yaotp/Yandex:alice?secret=6SB2IKNM6OBZPAVBVTOHDKS4FAAAAAAADFUTQMBTRY&pin=5239",
+        )
+        .unwrap();
+        assert_eq!(uri.method(), Method::Yandex);
+        assert_eq!(uri.issuer(), "Yandex");
+        assert_eq!(uri.account(), "alice");
+        assert_eq!(uri.pin(), Some("5239".to_owned()));
+        assert_eq!(uri.algorithm(), Algorithm::SHA256);
+        assert_eq!(uri.digits(), Some(8));
+
+        let uri = OTPUri::from_str(
+            "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example&epoch=1000",
+        )
+        .unwrap();
+        assert_eq!(uri.epoch(), Some(1000));
+        assert_eq!(uri.period(), None);
+
+        let uri = OTPUri::from_str("steam://AEBAGBAFAYDQQCIKBMGA2DQPCAIREEYU").unwrap();
+        assert_eq!(uri.method(), Method::Steam);
+        assert_eq!(uri.issuer(), "Steam");
+        assert_eq!(uri.secret(), "AEBAGBAFAYDQQCIKBMGA2DQPCAIREEYU");
+        assert_eq!(uri.account(), "Unknown account");
+        assert_eq!(uri.digits(), Some(5));
+        assert_eq!(uri.period(), Some(30));
+        assert_eq!(uri.algorithm(), Algorithm::SHA1);
+    }
+
+    #[test]
+    fn decode_strict() {
+        let uri = OTPUri::from_str_strict(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co&algorithm=SHA1&digits=6&period=30",
+        )
+        .unwrap();
+        assert_eq!(uri.issuer(), "ACME Co");
+        assert_eq!(uri.account(), "john.doe@email.com");
+
+        let err =
+            OTPUri::from_str_strict("otpauth://totp/GitLab:sbeve72?secret=[secret]&issuer=GitLab")
+                .err()
+                .unwrap();
+        assert_eq!(err.0, &[OTPUriProblem::InvalidSecret]);
+
+        let err = OTPUri::from_str_strict(
+            "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Other&algorithm=MD4&digits=12&period=0",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.0,
+            &[
+                OTPUriProblem::UnsupportedAlgorithm("MD4".to_owned()),
+                OTPUriProblem::InvalidDigits("12".to_owned()),
+                OTPUriProblem::InvalidPeriod("0".to_owned()),
+                OTPUriProblem::IssuerMismatch {
+                    label: "Example".to_owned(),
+                    parameter: "Other".to_owned(),
+                },
+            ]
+        );
+
+        let err = OTPUri::from_str_strict("otpauth:///alice?issuer=Example")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.0,
+            &[OTPUriProblem::MissingHost, OTPUriProblem::MissingSecret]
+        );
+
+        let err = OTPUri::from_str_strict("https://example.com/alice?secret=JBSWY3DPEHPK3PXP")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.0,
+            &[
+                OTPUriProblem::InvalidScheme("https".to_owned()),
+                OTPUriProblem::UnsupportedMethod("example.com".to_owned()),
+            ]
+        );
+
+        let err = OTPUri::from_str_strict("otpauth://totp/Example:%FF?secret=JBSWY3DPEHPK3PXP")
+            .err()
+            .unwrap();
+        assert_eq!(err.0, &[OTPUriProblem::InvalidLabel]);
+        let err = OTPUri::from_str_strict(
+            "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&issuer=Black%FFMesa",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.0, &[OTPUriProblem::InvalidLabel]);
+        // A literal percent sign is fine
+        let uri = OTPUri::from_str_strict(
+            "otpauth://totp/100%25%20Mesa:alice?secret=JBSWY3DPEHPK3PXP&issuer=100%25%20Mesa",
+        )
+        .unwrap();
+        assert_eq!(uri.account(), "alice");
+
+        let err = OTPUri::from_str_strict("otpauth://motp/alice?secret=JBSWY3DPEHPK3PXP")
+            .err()
+            .unwrap();
+        assert_eq!(err.0, &[OTPUriProblem::MissingPin]);
+        let uri = OTPUri::from_str_strict("otpauth://motp/alice?secret=JBSWY3DPEHPK3PXP&pin=1234")
+            .unwrap();
+        assert_eq!(uri.pin(), Some("1234".to_owned()));
+
+        let uri = OTPUri::from_str_strict("steam://NOT1VALID");
+        assert_eq!(uri.err().unwrap().0, &[OTPUriProblem::InvalidSecret]);
+        let uri = OTPUri::from_str_strict("steam://AEBAGBAFAYDQQCIKBMGA2DQPCAIREEYU").unwrap();
+        assert_eq!(uri.method(), Method::Steam);
     }
 
     #[test]
@@ -248,7 +738,89 @@ mod tests {
             digits: Some(6),
             period: Some(30),
             counter: None,
+            pin: None,
+            epoch: None,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
         };
         assert_eq!(String::from(uri), "otpauth://totp/account%20test?secret=dznF36H0IIg17rK&issuer=Test&algorithm=SHA1&digits=6&period=30");
+
+        let uri = OTPUri {
+            algorithm: Algorithm::SHA1,
+            label: "alice".to_owned(),
+            secret: "4MKSV7XGEWM4Q".to_owned(),
+            issuer: "VPN".to_owned(),
+            method: Method::MOTP,
+            digits: Some(6),
+            period: Some(10),
+            counter: None,
+            pin: Some("1234".to_owned()),
+            epoch: None,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        };
+        assert_eq!(String::from(uri), "otpauth://motp/alice?secret=4MKSV7XGEWM4Q&issuer=VPN&algorithm=SHA1&digits=6&pin=1234&period=10");
+
+        let uri = OTPUri {
+            algorithm: Algorithm::SHA256,
+            label: "alice".to_owned(),
+            secret: "6SB2IKNM6OBZPAVBVTOHDKS4FAAAAAAADFUTQMBTRY".to_owned(),
+            issuer: "Yandex".to_owned(),
+            method: Method::Yandex,
+            digits: Some(8),
+            period: Some(30),
+            counter: None,
+            pin: Some("5239".to_owned()),
+            epoch: None,
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        };
+        assert_eq!(String::from(uri), "otpauth://yaotp/alice?secret=6SB2IKNM6OBZPAVBVTOHDKS4FAAAAAAADFUTQMBTRY&issuer=Yandex&algorithm=SHA256&digits=8&pin=5239&period=30");
+
+        let uri = OTPUri {
+            algorithm: Algorithm::SHA1,
+            label: "alice".to_owned(),
+            secret: "JBSWY3DPEHPK3PXP".to_owned(),
+            issuer: "Example".to_owned(),
+            method: Method::TOTP,
+            digits: Some(6),
+            period: Some(45),
+            counter: None,
+            pin: None,
+            epoch: Some(1000),
+            image: None,
+            color: None,
+            tags: Vec::new(),
+            extra: Vec::new(),
+        };
+        assert_eq!(String::from(uri), "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&issuer=Example&algorithm=SHA1&digits=6&period=45&epoch=1000");
+    }
+
+    #[test]
+    fn extensions() {
+        let uri = OTPUri::from_str("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example&image=https%3A%2F%2Fexample.com%2Flogo.png&color=%23FF5722&tags=work,vpn&lock=false").unwrap();
+        assert_eq!(uri.image(), Some("https://example.com/logo.png".to_owned()));
+        assert_eq!(uri.color.as_deref(), Some("#FF5722"));
+        assert_eq!(uri.tags, &["work", "vpn"]);
+        assert_eq!(uri.extra, &[("lock".to_owned(), "false".to_owned())]);
+        assert_eq!(
+            uri.extensions().as_deref(),
+            Some("image=https%3A%2F%2Fexample.com%2Flogo.png&color=%23FF5722&tags=work%2Cvpn&lock=false")
+        );
+
+        let encoded = String::from(uri);
+        assert_eq!(encoded, "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&issuer=Example&algorithm=SHA1&period=30&image=https%3A%2F%2Fexample%2Ecom%2Flogo%2Epng&color=%23FF5722&tags=work%2Cvpn&lock=false");
+
+        let uri = OTPUri::from_str(&encoded).unwrap();
+        assert_eq!(uri.image(), Some("https://example.com/logo.png".to_owned()));
+        assert_eq!(uri.color.as_deref(), Some("#FF5722"));
+        assert_eq!(uri.tags, &["work", "vpn"]);
+        assert_eq!(uri.extra, &[("lock".to_owned(), "false".to_owned())]);
     }
 }
@@ -1,9 +1,10 @@
 use std::{
+    path::PathBuf,
     string::ToString,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use diesel::prelude::*;
 use gtk::{
     gdk_pixbuf, gio,
@@ -14,8 +15,12 @@ use gtk::{
 use url::Url;
 
 use crate::{
-    models::{database, Account, AccountsModel, Algorithm, Method, FAVICONS_PATH, OTP},
+    models::{
+        database, Account, AccountsModel, Algorithm, Method, OcraSuite, Rendering, FAVICONS_PATH,
+        OTP, SETTINGS,
+    },
     schema::providers,
+    utils::spawn_tokio,
 };
 
 pub struct ProviderPatch {
@@ -58,6 +63,11 @@ pub struct DieselProvider {
     pub default_counter: i32,
     pub algorithm: String,
     pub method: String,
+    pub ocra_suite: Option<String>,
+    pub time_offset: i32,
+    pub epoch: i64,
+    pub group_size: i32,
+    pub alphabet: Option<String>,
 }
 
 mod imp {
@@ -80,7 +90,8 @@ mod imp {
         pub default_counter: Cell<u32>,
         #[property(get, set, builder(Algorithm::default()))]
         pub algorithm: Cell<Algorithm>,
-        #[property(get, set, maximum = 1000, default = OTP::DEFAULT_DIGITS)]
+        #[property(get, set, minimum = OTP::MIN_DIGITS, maximum = OTP::MAX_DIGITS,
+                   default = OTP::DEFAULT_DIGITS)]
         pub digits: Cell<u32>,
         #[property(get, set)]
         pub website: RefCell<Option<String>>,
@@ -88,12 +99,27 @@ mod imp {
         pub help_url: RefCell<Option<String>>,
         #[property(get, set = Self::set_image_uri, explicit_notify)]
         pub image_uri: RefCell<Option<String>>,
+        #[property(get, set, construct_only)]
+        pub ocra_suite: RefCell<Option<String>>,
+        // Clock offset in seconds, for machines with a drifting clock
+        #[property(get, set, construct_only)]
+        pub time_offset: Cell<i64>,
+        // UNIX time from which the time steps are counted, T0 of RFC 6238
+        #[property(get, set, construct_only)]
+        pub epoch: Cell<u64>,
+        // Number of characters per group of the displayed codes, 0 to disable it
+        #[property(get, set, construct_only, default = Rendering::DEFAULT_GROUP_SIZE)]
+        pub group_size: Cell<u32>,
+        #[property(get, set, construct_only)]
+        pub alphabet: RefCell<Option<String>>,
         #[property(get, set)]
         pub remaining_time: Cell<u64>,
         #[property(get)]
         pub accounts_model: AccountsModel,
         pub filter_model: gtk::FilterListModel,
         pub tick_callback: RefCell<Option<glib::SourceId>>,
+        // The time step of the displayed codes
+        pub current_step: Cell<u64>,
     }
 
     #[glib::object_subclass]
@@ -112,11 +138,17 @@ mod imp {
                 website: RefCell::default(),
                 help_url: RefCell::default(),
                 image_uri: RefCell::default(),
+                ocra_suite: RefCell::default(),
+                time_offset: Cell::default(),
+                epoch: Cell::default(),
+                group_size: Cell::new(Rendering::DEFAULT_GROUP_SIZE),
+                alphabet: RefCell::default(),
                 method: Cell::new(Method::default()),
                 period: Cell::new(OTP::DEFAULT_PERIOD),
                 filter_model: gtk::FilterListModel::new(Some(model.clone()), None::<gtk::Filter>),
                 accounts_model: model,
                 tick_callback: RefCell::default(),
+                current_step: Cell::default(),
                 remaining_time: Cell::default(),
             }
         }
@@ -173,6 +205,10 @@ impl Provider {
         help_url: Option<String>,
         image_uri: Option<String>,
     ) -> Result<Self> {
+        anyhow::ensure!(
+            OTP::is_valid_digits(digits),
+            "Invalid number of digits {digits}"
+        );
         let db = database::connection();
         let mut conn = db.get()?;
 
@@ -226,6 +262,11 @@ impl Provider {
         website: Option<String>,
         help_url: Option<String>,
         image_uri: Option<String>,
+        ocra_suite: Option<String>,
+        time_offset: i64,
+        epoch: u64,
+        group_size: u32,
+        alphabet: Option<String>,
     ) -> Provider {
         glib::Object::builder()
             .property("id", id)
@@ -233,6 +274,11 @@ impl Provider {
             .property("website", website)
             .property("help-url", help_url)
             .property("image-uri", image_uri)
+            .property("ocra-suite", ocra_suite)
+            .property("time-offset", time_offset)
+            .property("epoch", epoch)
+            .property("group-size", group_size)
+            .property("alphabet", alphabet)
             .property("period", period)
             .property("method", method)
             .property("algorithm", algorithm)
@@ -250,54 +296,106 @@ impl Provider {
         let favicon = favicon_scrapper::Scrapper::from_url(&website_url).await?;
         tracing::debug!("Found the following icons {:#?} for {}", favicon, name);
 
+        let icon_name = Self::icon_name(id, &name);
+        if let Some(best_favicon) = favicon.find_best().await {
+            tracing::debug!("Largest favicon found is {:#?}", best_favicon);
+            let cache_path = FAVICONS_PATH.join(&*icon_name);
+            best_favicon.save(cache_path.clone()).await?;
+            let is_svg = best_favicon.metadata().format().is_svg();
+            Self::cache_variants(cache_path, &icon_name, is_svg).await?;
+            Ok(icon_name.to_string())
+        } else {
+            Err(Box::new(favicon_scrapper::Error::NoResults))
+        }
+    }
+
+    fn icon_name(id: u32, name: &str) -> glib::GString {
         let icon_name = format!("{id}_{}", name.replace(' ', "_"));
-        let icon_name = glib::base64_encode(icon_name.as_bytes());
+        glib::base64_encode(icon_name.as_bytes())
+    }
+
+    /// Replaces the image saved at `cache_path` by the variants used by
+    /// [`ProviderImage`](crate::widgets::ProviderImage).
+    async fn cache_variants(
+        cache_path: PathBuf,
+        icon_name: &str,
+        is_svg: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         let small_icon_name = format!("{icon_name}_32x32");
         let large_icon_name = format!("{icon_name}_96x96");
         // TODO: figure out why trying to grab icons at specific size causes stack size
         // errors We need two sizes:
         // - 32x32 for the accounts lists
         // - 96x96 elsewhere
-        if let Some(best_favicon) = favicon.find_best().await {
-            tracing::debug!("Largest favicon found is {:#?}", best_favicon);
-            let cache_path = FAVICONS_PATH.join(&*icon_name);
-            best_favicon.save(cache_path.clone()).await?;
-            // Don't try to scale down svg variants
-            if !best_favicon.metadata().format().is_svg() {
-                tracing::debug!("Creating scaled down variants for {:#?}", cache_path);
-                {
-                    let pixbuf = gdk_pixbuf::Pixbuf::from_file(cache_path.clone())?;
-                    tracing::debug!("Creating a 32x32 variant of the favicon");
-                    let small_pixbuf = pixbuf
-                        .scale_simple(32, 32, gdk_pixbuf::InterpType::Bilinear)
-                        .unwrap();
-
-                    let mut small_cache = cache_path.clone();
-                    small_cache.set_file_name(small_icon_name);
-                    small_pixbuf.savev(small_cache.clone(), "png", &[])?;
-
-                    tracing::debug!("Creating a 96x96 variant of the favicon");
-                    let large_pixbuf = pixbuf
-                        .scale_simple(96, 96, gdk_pixbuf::InterpType::Bilinear)
-                        .unwrap();
-                    let mut large_cache = cache_path.clone();
-                    large_cache.set_file_name(large_icon_name);
-                    large_pixbuf.savev(large_cache.clone(), "png", &[])?;
-                };
-                tokio::fs::remove_file(cache_path).await?;
-            } else {
+        // Don't try to scale down svg variants
+        if !is_svg {
+            tracing::debug!("Creating scaled down variants for {:#?}", cache_path);
+            {
+                let pixbuf = gdk_pixbuf::Pixbuf::from_file(cache_path.clone())?;
+                tracing::debug!("Creating a 32x32 variant of the favicon");
+                let small_pixbuf = pixbuf
+                    .scale_simple(32, 32, gdk_pixbuf::InterpType::Bilinear)
+                    .unwrap();
+
                 let mut small_cache = cache_path.clone();
                 small_cache.set_file_name(small_icon_name);
-                tokio::fs::symlink(&cache_path, small_cache).await?;
+                small_pixbuf.savev(small_cache.clone(), "png", &[])?;
 
+                tracing::debug!("Creating a 96x96 variant of the favicon");
+                let large_pixbuf = pixbuf
+                    .scale_simple(96, 96, gdk_pixbuf::InterpType::Bilinear)
+                    .unwrap();
                 let mut large_cache = cache_path.clone();
                 large_cache.set_file_name(large_icon_name);
-                tokio::fs::symlink(&cache_path, large_cache).await?;
-            }
-            Ok(icon_name.to_string())
+                large_pixbuf.savev(large_cache.clone(), "png", &[])?;
+            };
+            tokio::fs::remove_file(cache_path).await?;
         } else {
-            Err(Box::new(favicon_scrapper::Error::NoResults))
+            let mut small_cache = cache_path.clone();
+            small_cache.set_file_name(small_icon_name);
+            tokio::fs::symlink(&cache_path, small_cache).await?;
+
+            let mut large_cache = cache_path.clone();
+            large_cache.set_file_name(large_icon_name);
+            tokio::fs::symlink(&cache_path, large_cache).await?;
         }
+        Ok(())
+    }
+
+    /// Uses the image an otpauth URI points to as the provider image.
+    ///
+    /// Nothing is downloaded unless the user allowed fetching favicons.
+    pub async fn set_image_from_url(&self, url: &str) -> Result<()> {
+        let network_monitor = gio::NetworkMonitor::default();
+        if (network_monitor.is_network_metered() && !SETTINGS.download_favicons_metered())
+            || !SETTINGS.download_favicons()
+        {
+            tracing::debug!("Favicons download is disabled, ignoring the image {url}");
+            return Ok(());
+        }
+        let url = Url::parse(url)?;
+        anyhow::ensure!(
+            matches!(url.scheme(), "http" | "https"),
+            "Unsupported image URL {url}"
+        );
+
+        let icon_name = Self::icon_name(self.id(), &self.name());
+        let cache_path = FAVICONS_PATH.join(&*icon_name);
+        let cache_name = icon_name.to_string();
+        spawn_tokio(async move {
+            let response = reqwest::get(url).await?.error_for_status()?;
+            let is_svg = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .is_some_and(|content_type| content_type.as_bytes().starts_with(b"image/svg"));
+            tokio::fs::write(&cache_path, response.bytes().await?).await?;
+            Self::cache_variants(cache_path, &cache_name, is_svg)
+                .await
+                .map_err(|err| anyhow::anyhow!("{err}"))
+        })
+        .await?;
+        self.set_image_uri(icon_name.to_string());
+        Ok(())
     }
 
     pub fn delete(&self) -> Result<()> {
@@ -322,6 +420,11 @@ impl Provider {
         {
             return Ok(());
         }
+        anyhow::ensure!(
+            OTP::is_valid_digits(patch.digits as u32),
+            "Invalid number of digits {}",
+            patch.digits
+        );
 
         let db = database::connection();
         let mut conn = db.get()?;
@@ -366,6 +469,106 @@ impl Provider {
         Ok(())
     }
 
+    pub fn update_ocra_suite(&self, suite: Option<&str>) -> Result<()> {
+        if self.ocra_suite().as_deref() == suite {
+            return Ok(());
+        }
+        let db = database::connection();
+        let mut conn = db.get()?;
+
+        let target = providers::table.filter(providers::columns::id.eq(self.id() as i32));
+        diesel::update(target)
+            .set(providers::columns::ocra_suite.eq(suite))
+            .execute(&mut conn)?;
+
+        self.imp().ocra_suite.replace(suite.map(ToOwned::to_owned));
+        self.notify_ocra_suite();
+        Ok(())
+    }
+
+    pub fn update_time_offset(&self, offset: i64) -> Result<()> {
+        let db = database::connection();
+        let mut conn = db.get()?;
+
+        let target = providers::table.filter(providers::columns::id.eq(self.id() as i32));
+        diesel::update(target)
+            .set(providers::columns::time_offset.eq(i32::try_from(offset)?))
+            .execute(&mut conn)?;
+
+        self.imp().time_offset.set(offset);
+        self.notify_time_offset();
+        self.regenerate_otp();
+        Ok(())
+    }
+
+    pub fn update_epoch(&self, epoch: u64) -> Result<()> {
+        if self.epoch() == epoch {
+            return Ok(());
+        }
+        let db = database::connection();
+        let mut conn = db.get()?;
+
+        let target = providers::table.filter(providers::columns::id.eq(self.id() as i32));
+        diesel::update(target)
+            .set(providers::columns::epoch.eq(i64::try_from(epoch)?))
+            .execute(&mut conn)?;
+
+        self.imp().epoch.set(epoch);
+        self.notify_epoch();
+        let accounts = self.accounts_model();
+        for i in 0..accounts.n_items() {
+            let account = accounts.item(i).and_downcast::<Account>().unwrap();
+            account.otp().set_epoch(epoch);
+        }
+        self.regenerate_otp();
+        Ok(())
+    }
+
+    pub fn update_rendering(&self, group_size: u32, alphabet: Option<&str>) -> Result<()> {
+        if self.group_size() == group_size && self.alphabet().as_deref() == alphabet {
+            return Ok(());
+        }
+        if let Some(alphabet) = alphabet {
+            anyhow::ensure!(
+                Rendering::is_valid_alphabet(alphabet),
+                "Invalid alphabet {alphabet}"
+            );
+        }
+        let db = database::connection();
+        let mut conn = db.get()?;
+
+        let target = providers::table.filter(providers::columns::id.eq(self.id() as i32));
+        diesel::update(target)
+            .set((
+                providers::columns::group_size.eq(group_size as i32),
+                providers::columns::alphabet.eq(alphabet),
+            ))
+            .execute(&mut conn)?;
+
+        let imp = self.imp();
+        imp.group_size.set(group_size);
+        imp.alphabet.replace(alphabet.map(ToOwned::to_owned));
+        self.notify_group_size();
+        self.notify_alphabet();
+        self.regenerate_otp();
+        Ok(())
+    }
+
+    /// How the codes of the provider are displayed.
+    pub fn rendering(&self) -> Rendering {
+        Rendering {
+            group_size: self.group_size(),
+            alphabet: self.alphabet(),
+        }
+    }
+
+    /// The parsed OCRA suite of challenge-response providers.
+    pub fn ocra(&self) -> Result<OcraSuite> {
+        self.ocra_suite()
+            .context("The provider has no OCRA suite")?
+            .parse()
+    }
+
     pub fn open_help(&self) {
         if let Some(ref url) = self.help_url() {
             gio::AppInfo::launch_default_for_uri(url, None::<&gio::AppLaunchContext>).unwrap();
@@ -373,17 +576,32 @@ impl Provider {
     }
 
     fn tick(&self) {
+        let imp = self.imp();
         let period = self.period() as u64;
-        let remaining_time: u64 = period
-            - SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-                % period;
-        if period == remaining_time {
+        let timestamp = OTP::timestamp(self.time_offset()).saturating_sub(self.epoch());
+        // Regenerate whenever the step changes, even if a tick was delayed
+        let step = timestamp / period;
+        if imp.current_step.replace(step) != step {
             self.regenerate_otp();
         }
-        self.set_remaining_time(remaining_time);
+        self.set_remaining_time(period - timestamp % period);
+    }
+
+    // Ticks right after every second boundary, a periodic timer would drift
+    // away from the moment the codes change.
+    fn schedule_tick(&self) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let delay = Duration::from_secs(1) - Duration::from_nanos(now.subsec_nanos() as u64);
+        let source_id = glib::timeout_add_local_once(
+            delay,
+            clone!(@weak self as provider => move || {
+                // The source is done, forget it before scheduling the next one
+                provider.imp().tick_callback.borrow_mut().take();
+                provider.tick();
+                provider.schedule_tick();
+            }),
+        );
+        self.imp().tick_callback.replace(Some(source_id));
     }
 
     fn setup_tick_callback(&self) {
@@ -393,15 +611,9 @@ impl Provider {
         self.set_remaining_time(self.period() as u64);
 
         match self.method() {
-            Method::TOTP | Method::Steam => {
-                let source_id = glib::timeout_add_seconds_local(
-                    1,
-                    clone!(@weak self as provider => @default-return glib::ControlFlow::Break, move || {
-                        provider.tick();
-                        glib::ControlFlow::Continue
-                    }),
-                );
-                self.imp().tick_callback.replace(Some(source_id));
+            Method::TOTP | Method::Steam | Method::MOTP | Method::Yandex => {
+                self.tick();
+                self.schedule_tick();
             }
             _ => (),
         };
@@ -504,6 +716,11 @@ impl From<DieselProvider> for Provider {
             p.website,
             p.help_url,
             p.image_uri,
+            p.ocra_suite,
+            p.time_offset as i64,
+            p.epoch as u64,
+            p.group_size as u32,
+            p.alphabet,
         )
     }
 }
@@ -521,6 +738,11 @@ impl From<&Provider> for DieselProvider {
             website: p.website(),
             help_url: p.help_url(),
             image_uri: p.image_uri(),
+            ocra_suite: p.ocra_suite(),
+            time_offset: p.time_offset() as i32,
+            epoch: p.epoch() as i64,
+            group_size: p.group_size() as i32,
+            alphabet: p.alphabet(),
         }
     }
 }
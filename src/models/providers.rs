@@ -40,6 +40,12 @@ glib::wrapper! {
 }
 
 impl ProvidersModel {
+    /// Finds the provider called `name` or creates it.
+    ///
+    /// With an `epoch`, only a provider counting its time steps from it
+    /// matches, as changing the epoch of an existing provider would break the
+    /// codes of its other accounts. Another provider with the same name is
+    /// created instead.
     #[allow(clippy::too_many_arguments)]
     pub fn find_or_create(
         &self,
@@ -52,8 +58,13 @@ impl ProvidersModel {
         default_counter: Option<u32>,
         help_url: Option<String>,
         image_uri: Option<String>,
+        epoch: Option<u64>,
     ) -> Result<Provider> {
-        let provider = match self.find_by_name(name) {
+        let existing = match epoch {
+            Some(epoch) => self.find_by_name_and_epoch(name, epoch),
+            None => self.find_by_name(name),
+        };
+        let provider = match existing {
             Some(p) => {
                 // Update potenitally different properties than what we have in the pre-shipped
                 // database Note this does a comparaison first to avoid a
@@ -84,6 +95,9 @@ impl ProvidersModel {
                     help_url,
                     image_uri,
                 )?;
+                if let Some(epoch) = epoch.filter(|epoch| *epoch != 0) {
+                    p.update_epoch(epoch)?;
+                }
                 self.append(&p);
                 p
             }
@@ -91,10 +105,19 @@ impl ProvidersModel {
         Ok(provider)
     }
 
-    fn find_by_name(&self, name: &str) -> Option<Provider> {
+    pub fn find_by_name(&self, name: &str) -> Option<Provider> {
+        self.find(|provider| provider.name() == name)
+    }
+
+    /// The provider called `name` counting its time steps from `epoch`.
+    pub fn find_by_name_and_epoch(&self, name: &str, epoch: u64) -> Option<Provider> {
+        self.find(|provider| provider.name() == name && provider.epoch() == epoch)
+    }
+
+    fn find(&self, predicate: impl Fn(&Provider) -> bool) -> Option<Provider> {
         for pos in 0..self.n_items() {
             let provider = self.item(pos).and_downcast::<Provider>().unwrap();
-            if provider.name() == name {
+            if predicate(&provider) {
                 return Some(provider);
             }
         }
@@ -5,6 +5,7 @@ diesel::table! {
         counter -> Integer,
         token_id -> Text,
         provider_id -> Integer,
+        otpauth_params -> Nullable<Text>,
     }
 }
 
@@ -20,6 +21,11 @@ diesel::table! {
         default_counter -> Integer,
         algorithm -> Text,
         method -> Text,
+        ocra_suite -> Nullable<Text>,
+        time_offset -> Integer,
+        epoch -> BigInt,
+        group_size -> Integer,
+        alphabet -> Nullable<Text>,
     }
 }
 
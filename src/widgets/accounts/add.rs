@@ -8,7 +8,8 @@ use gtk::{
 
 use crate::{
     backup::RestorableItem,
-    models::{Account, OTPUri, Provider, ProvidersModel, OTP},
+    models::{Account, Method, OTPUri, Provider, ProvidersModel, SecretEncoding, OTP},
+    utils::spawn,
     widgets::{providers::ProviderPage, screenshot, Camera, ErrorRevealer, ProviderImage, UrlRow},
 };
 
@@ -27,6 +28,7 @@ mod imp {
         #[property(get, set, construct_only)]
         pub model: OnceCell<ProvidersModel>,
         pub selected_provider: RefCell<Option<Provider>>,
+        pub otpauth_params: RefCell<Option<String>>,
         #[template_child]
         pub camera: TemplateChild<Camera>,
         #[template_child]
@@ -42,6 +44,10 @@ mod imp {
         #[template_child]
         pub token_entry: TemplateChild<adw::PasswordEntryRow>,
         #[template_child]
+        pub encoding_comborow: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub pin_entry: TemplateChild<adw::PasswordEntryRow>,
+        #[template_child]
         pub more_list: TemplateChild<gtk::ListBox>,
         #[template_child]
         pub period_label: TemplateChild<gtk::Label>,
@@ -72,6 +78,7 @@ mod imp {
         type ParentType = adw::Dialog;
 
         fn class_init(klass: &mut Self::Class) {
+            SecretEncoding::static_type();
             klass.bind_template();
             klass.bind_template_instance_callbacks();
 
@@ -165,11 +172,22 @@ impl AccountAddDialog {
         let username = imp.username_entry.text();
         let token = imp.token_entry.text();
         let has_provider = imp.selected_provider.borrow().is_some();
-
-        let is_valid = !username.is_empty() && !token.is_empty() && has_provider;
+        let needs_pin = imp
+            .selected_provider
+            .borrow()
+            .as_ref()
+            .is_some_and(|p| p.method().requires_pin());
+        let has_pin = !needs_pin || !imp.pin_entry.text().is_empty();
+
+        let is_valid = !username.is_empty() && !token.is_empty() && has_provider && has_pin;
         self.action_set_enabled("add.save", is_valid);
     }
 
+    #[template_callback]
+    fn secret_encoding_to_locale_string(item: adw::EnumListItem) -> String {
+        SecretEncoding::from(item.value() as u32).to_locale_string()
+    }
+
     #[template_callback]
     fn match_selected(&self, store: gtk::ListStore, iter: gtk::TreeIter) -> ControlFlow {
         let provider_id = store.get::<u32>(&iter, 0);
@@ -202,12 +220,15 @@ impl AccountAddDialog {
 
     #[template_callback]
     fn camera_code_detected(&self, code: &str, _camera: Camera) {
-        match code.parse::<OTPUri>() {
+        match OTPUri::from_str_strict(code) {
             Ok(otp_uri) => {
                 self.set_from_otp_uri(&otp_uri);
             }
             Err(err) => {
                 tracing::error!("Failed to parse OTP uri code {err}");
+                let imp = self.imp();
+                imp.navigation_view.pop(); // Switch back the form view
+                imp.error_revealer.popup(&err.to_string());
             }
         }
     }
@@ -242,21 +263,38 @@ impl AccountAddDialog {
 
         imp.token_entry.set_text(&otp_uri.secret());
         imp.username_entry.set_text(&otp_uri.account());
-
-        let provider = self
-            .model()
-            .find_or_create(
-                &otp_uri.issuer(),
-                otp_uri.period(),
-                otp_uri.method(),
-                None,
-                otp_uri.algorithm(),
-                otp_uri.digits(),
-                otp_uri.counter(),
-                None,
-                None,
-            )
-            .ok();
+        imp.pin_entry.set_text(&otp_uri.pin().unwrap_or_default());
+
+        let provider = match self.model().find_or_create(
+            &otp_uri.issuer(),
+            otp_uri.period(),
+            otp_uri.method(),
+            None,
+            otp_uri.algorithm(),
+            otp_uri.digits(),
+            otp_uri.counter(),
+            None,
+            None,
+            otp_uri.epoch(),
+        ) {
+            Ok(provider) => Some(provider),
+            Err(err) => {
+                tracing::error!("Failed to find or create the provider {err}");
+                imp.error_revealer
+                    .popup(&gettext("The provider could not be created"));
+                None
+            }
+        };
+        if let (Some(provider), Some(image)) = (&provider, otp_uri.image()) {
+            if provider.image_uri().is_none() {
+                spawn(clone!(@weak provider => async move {
+                    if let Err(err) = provider.set_image_from_url(&image).await {
+                        tracing::warn!("Failed to set the provider image from {image}: {err}");
+                    }
+                }));
+            }
+        }
+        imp.otpauth_params.replace(otp_uri.otpauth_params());
 
         self.set_provider(provider);
     }
@@ -278,8 +316,13 @@ impl AccountAddDialog {
         let file = dialog.open_future(Some(&window)).await?;
         let (data, _) = file.load_contents_future().await?;
         let code = screenshot::scan(&data)?;
-        let uri = code.parse::<OTPUri>()?;
-        self.set_from_otp_uri(&uri);
+        match OTPUri::from_str_strict(&code) {
+            Ok(uri) => self.set_from_otp_uri(&uri),
+            Err(err) => {
+                self.imp().error_revealer.popup(&err.to_string());
+                return Err(err.into());
+            }
+        }
         Ok(())
     }
 
@@ -289,13 +332,38 @@ impl AccountAddDialog {
         if let Some(ref provider) = *imp.selected_provider.borrow() {
             let username = imp.username_entry.text();
             let token = imp.token_entry.text();
-            let token = token.trim_end_matches('=');
-            if !OTP::is_valid(token) {
-                imp.error_revealer.popup(&gettext("Invalid Token"));
-                anyhow::bail!("Token {} is not a valid Base32 secret", &token);
+            let mut encoding = SecretEncoding::from(imp.encoding_comborow.selected());
+            // mOTP secrets are usually handed out as hex strings
+            if encoding == SecretEncoding::Auto
+                && provider.method() == Method::MOTP
+                && hex::decode(token.trim()).is_ok()
+            {
+                encoding = SecretEncoding::Hex;
+            }
+            // Store the secret as Base32 whatever the encoding it was typed with
+            let token =
+                match OTP::from_str(&token, encoding, provider.algorithm(), provider.digits()) {
+                    Ok(otp) => otp.secret(),
+                    Err(err) => {
+                        if encoding == SecretEncoding::Auto
+                            && OTP::candidate_encodings(&token).len() > 1
+                        {
+                            imp.error_revealer
+                                .popup(&gettext("Ambiguous token, please select its encoding"));
+                            imp.encoding_comborow.grab_focus();
+                        } else {
+                            imp.error_revealer.popup(&gettext("Invalid Token"));
+                        }
+                        anyhow::bail!("Token is not a valid {encoding:?} secret: {err}");
+                    }
+                };
+            let pin = imp.pin_entry.text();
+            let pin = provider.method().requires_pin().then_some(pin.as_str());
+
+            let account = Account::create(&username, &token, pin, None, provider)?;
+            if let Some(params) = imp.otpauth_params.take() {
+                account.update_otpauth_params(Some(&params))?;
             }
-
-            let account = Account::create(&username, token, None, provider)?;
 
             self.model().add_account(&account, provider);
             self.emit_by_name::<()>("added", &[]);
@@ -323,6 +391,8 @@ impl AccountAddDialog {
 
             imp.digits_label.set_text(&provider.digits().to_string());
 
+            imp.pin_entry.set_visible(provider.method().requires_pin());
+
             if provider.method().is_time_based() {
                 imp.counter_spinbutton.set_visible(false);
                 imp.period_row.set_visible(true);
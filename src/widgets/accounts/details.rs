@@ -9,7 +9,7 @@ use gtk::{
 use super::{QRCodeData, QRCodePaintable};
 use crate::{
     models::{Account, Provider, ProvidersModel},
-    widgets::UrlRow,
+    widgets::{UrlRow, Window},
 };
 mod imp {
     use std::cell::{OnceCell, RefCell};
@@ -37,12 +37,20 @@ mod imp {
         #[template_child]
         pub counter_spinbutton: TemplateChild<adw::SpinRow>,
         #[template_child]
+        pub resync_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub calibrate_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
         pub period_label: TemplateChild<gtk::Label>,
         #[template_child]
         pub digits_label: TemplateChild<gtk::Label>,
         #[template_child]
         pub period_row: TemplateChild<adw::ActionRow>,
         #[template_child]
+        pub tags_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub tags_label: TemplateChild<gtk::Label>,
+        #[template_child]
         pub help_row: TemplateChild<UrlRow>,
         pub qrcode_paintable: QRCodePaintable,
         pub account: RefCell<Option<Account>>,
@@ -73,6 +81,14 @@ mod imp {
                 }
             });
 
+            klass.install_action("account.resync-counter", None, |page, _, _| {
+                page.resync_counter();
+            });
+
+            klass.install_action("account.calibrate", None, |page, _, _| {
+                page.calibrate();
+            });
+
             klass.install_action("account.back", None, |page, _, _| {
                 page.activate_action("win.back", None).unwrap();
             });
@@ -139,9 +155,118 @@ impl AccountDetailsPage {
         dialog.present(&parent);
     }
 
+    fn resync_counter(&self) {
+        let parent = self.root().and_downcast::<Window>().unwrap();
+
+        let first_entry = adw::EntryRow::builder()
+            .title(gettext("First Code"))
+            .input_purpose(gtk::InputPurpose::Digits)
+            .build();
+        let second_entry = adw::EntryRow::builder()
+            .title(gettext("Second Code"))
+            .input_purpose(gtk::InputPurpose::Digits)
+            .activates_default(true)
+            .build();
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        list.append(&first_entry);
+        list.append(&second_entry);
+
+        let dialog = adw::AlertDialog::builder()
+            .heading(gettext("Resynchronise Counter"))
+            .body(gettext(
+                "Type two consecutive codes generated by your token",
+            ))
+            .extra_child(&list)
+            .default_response("resync")
+            .close_response("cancel")
+            .build();
+        dialog.add_responses(&[
+            ("cancel", &gettext("_Cancel")),
+            ("resync", &gettext("_Resynchronise")),
+        ]);
+        dialog.set_response_appearance("resync", adw::ResponseAppearance::Suggested);
+        dialog.connect_response(
+            None,
+            clone!(@weak self as page, @weak parent => move |dialog, response| {
+                if response == "resync" {
+                    let account = page.imp().account.borrow().as_ref().unwrap().clone();
+                    match account.resync_counter(&first_entry.text(), &second_entry.text()) {
+                        Ok(_) => {
+                            page.imp().counter_spinbutton.set_value(account.counter() as f64);
+                            parent.add_toast(adw::Toast::new(&gettext("Counter resynchronised")));
+                        }
+                        Err(err) => {
+                            tracing::warn!("Failed to resynchronise the counter {err}");
+                            parent.add_toast(adw::Toast::new(&gettext("The codes don't match")));
+                        }
+                    }
+                }
+                dialog.close();
+            }),
+        );
+
+        dialog.present(&parent);
+    }
+
+    fn calibrate(&self) {
+        let parent = self.root().and_downcast::<Window>().unwrap();
+
+        let code_entry = adw::EntryRow::builder()
+            .title(gettext("Code"))
+            .activates_default(true)
+            .build();
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        list.append(&code_entry);
+
+        let dialog = adw::AlertDialog::builder()
+            .heading(gettext("Calibrate Clock"))
+            .body(gettext(
+                "Type the current code shown by the service or another device",
+            ))
+            .extra_child(&list)
+            .default_response("calibrate")
+            .close_response("cancel")
+            .build();
+        dialog.add_responses(&[
+            ("cancel", &gettext("_Cancel")),
+            ("calibrate", &gettext("C_alibrate")),
+        ]);
+        dialog.set_response_appearance("calibrate", adw::ResponseAppearance::Suggested);
+        dialog.connect_response(
+            None,
+            clone!(@weak self as page, @weak parent => move |dialog, response| {
+                if response == "calibrate" {
+                    let account = page.imp().account.borrow().as_ref().unwrap().clone();
+                    match account.calibrate(&code_entry.text()) {
+                        Ok(_) => {
+                            parent.add_toast(adw::Toast::new(&gettext("Clock calibrated")));
+                        }
+                        Err(err) => {
+                            tracing::warn!("Failed to calibrate the clock {err}");
+                            parent.add_toast(adw::Toast::new(&gettext("The code doesn't match")));
+                        }
+                    }
+                }
+                dialog.close();
+            }),
+        );
+
+        dialog.present(&parent);
+    }
+
     pub fn set_account(&self, account: &Account) {
         let imp = self.imp();
-        let qr_code = QRCodeData::from(String::from(account.otp_uri()));
+        let otp_uri = account.otp_uri();
+        // Like the categories of Authenticator Pro, restored along the account
+        imp.tags_row.set_visible(!otp_uri.tags.is_empty());
+        imp.tags_label.set_text(&otp_uri.tags.join(", "));
+        let qr_code = QRCodeData::from(String::from(otp_uri));
         imp.qrcode_paintable.set_qrcode(qr_code);
 
         if account.provider().method().is_event_based() {
@@ -168,12 +293,16 @@ impl AccountDetailsPage {
             .set_text(&provider.method().to_locale_string());
         if provider.method().is_event_based() {
             imp.counter_spinbutton.set_visible(true);
+            imp.resync_row.set_visible(true);
             imp.period_row.set_visible(false);
         } else {
             imp.counter_spinbutton.set_visible(false);
+            imp.resync_row.set_visible(false);
             imp.period_row.set_visible(true);
             imp.period_label.set_text(&provider.period().to_string());
         }
+        imp.calibrate_row
+            .set_visible(provider.method().is_time_based());
         imp.digits_label.set_text(&provider.digits().to_string());
         if let Some(help) = provider.help_url() {
             imp.help_row.set_uri(help);
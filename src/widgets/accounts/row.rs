@@ -1,16 +1,25 @@
-use gtk::{gdk, glib, prelude::*};
+use adw::prelude::*;
+use gettextrs::gettext;
+use gtk::{
+    gdk,
+    glib::{self, clone},
+};
 
-use crate::models::Account;
+use crate::{
+    models::{Account, Provider},
+    widgets::Window,
+};
+
+// Remaining seconds from which the upcoming code is previewed
+const NEXT_CODE_THRESHOLD: u64 = 5;
 
 mod imp {
-    use std::cell::OnceCell;
+    use std::cell::{OnceCell, RefCell};
 
     use adw::subclass::prelude::*;
-    use gettextrs::gettext;
     use glib::subclass;
 
     use super::*;
-    use crate::widgets::Window;
 
     #[derive(Default, gtk::CompositeTemplate, glib::Properties)]
     #[properties(wrapper_type = super::AccountRow)]
@@ -21,7 +30,13 @@ mod imp {
         #[template_child]
         pub increment_btn: TemplateChild<gtk::Button>,
         #[template_child]
+        pub challenge_btn: TemplateChild<gtk::Button>,
+        #[template_child]
         pub otp_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub next_otp_label: TemplateChild<gtk::Label>,
+        // The provider outlives the row, which is recreated on every change
+        pub remaining_time_handler: RefCell<Option<(Provider, glib::SignalHandlerId)>>,
     }
 
     #[glib::object_subclass]
@@ -40,10 +55,18 @@ mod imp {
             );
 
             klass.install_action("account.copy-otp", None, |row, _, _| {
-                row.account().copy_otp();
+                let account = row.account();
+                account.copy_otp();
                 let window = row.root().and_downcast::<Window>().unwrap();
                 let toast = adw::Toast::new(&gettext("One-Time password copied"));
                 toast.set_timeout(3);
+                // The copied code might expire before being used
+                if row.is_about_to_expire() {
+                    toast.set_button_label(Some(&gettext("Copy _Next")));
+                    toast.connect_button_clicked(clone!(@weak account => move |_| {
+                        account.copy_next_otp();
+                    }));
+                }
                 window.add_toast(toast);
             });
             klass.install_action("account.increment-counter", None, |row, _, _| {
@@ -52,6 +75,9 @@ mod imp {
                     Err(err) => tracing::error!("Failed to increment the counter {err}"),
                 };
             });
+            klass.install_action_async("account.respond-challenge", None, |row, _, _| async move {
+                row.respond_to_challenge().await;
+            });
         }
 
         fn instance_init(obj: &subclass::InitializingObject<Self>) {
@@ -80,9 +106,32 @@ mod imp {
                 .sync_create()
                 .build();
 
+            account
+                .bind_property("next-code", &*self.next_otp_label, "label")
+                .sync_create()
+                .build();
+
+            let provider = account.provider();
+            let handler_id = provider.connect_remaining_time_notify(clone!(@weak obj => move |_| {
+                obj.imp()
+                    .next_otp_label
+                    .set_visible(obj.is_about_to_expire());
+            }));
+            self.remaining_time_handler
+                .replace(Some((provider, handler_id)));
+
             // Only display the increment button if it is a HOTP account
             self.increment_btn
                 .set_visible(account.provider().method().is_event_based());
+            // Challenge-response accounts need the user to type the challenge first
+            self.challenge_btn
+                .set_visible(account.provider().method().is_challenge_based());
+        }
+
+        fn dispose(&self) {
+            if let Some((provider, handler_id)) = self.remaining_time_handler.take() {
+                provider.disconnect(handler_id);
+            }
         }
     }
     impl WidgetImpl for AccountRow {}
@@ -100,4 +149,78 @@ impl AccountRow {
     pub fn new(account: &Account) -> Self {
         glib::Object::builder().property("account", account).build()
     }
+
+    // Whether the displayed code expires in a few seconds
+    fn is_about_to_expire(&self) -> bool {
+        let provider = self.account().provider();
+        provider.method().is_time_based() && provider.remaining_time() <= NEXT_CODE_THRESHOLD
+    }
+
+    async fn respond_to_challenge(&self) {
+        let account = self.account();
+        let suite = match account.provider().ocra() {
+            Ok(suite) => suite,
+            Err(err) => {
+                tracing::error!("Failed to parse the OCRA suite {err}");
+                return;
+            }
+        };
+
+        let challenge_entry = adw::EntryRow::builder()
+            .title(gettext("Challenge"))
+            .activates_default(true)
+            .build();
+        // Only ask for the PIN if it wasn't stored alongside the secret
+        let pin_entry = adw::PasswordEntryRow::builder()
+            .title(gettext("PIN"))
+            .activates_default(true)
+            .visible(suite.requires_pin() && account.otp().pin().is_none())
+            .build();
+        let session_entry = adw::EntryRow::builder()
+            .title(gettext("Session Information"))
+            .activates_default(true)
+            .visible(suite.requires_session())
+            .build();
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        list.append(&challenge_entry);
+        list.append(&pin_entry);
+        list.append(&session_entry);
+
+        let dialog = adw::AlertDialog::builder()
+            .heading(gettext("Challenge"))
+            .body(gettext("Type the challenge shown by the service"))
+            .extra_child(&list)
+            .default_response("respond")
+            .close_response("cancel")
+            .build();
+        dialog.add_responses(&[
+            ("cancel", &gettext("_Cancel")),
+            ("respond", &gettext("_Respond")),
+        ]);
+        dialog.set_response_appearance("respond", adw::ResponseAppearance::Suggested);
+        // The entry can only be focused once the dialog is presented
+        dialog.set_focus_widget(Some(&challenge_entry));
+
+        if dialog.choose_future(self).await != "respond" {
+            return;
+        }
+
+        let pin = pin_entry.text();
+        let session = session_entry.text();
+        let pin = (!pin.is_empty()).then_some(pin.as_str());
+        let session = (!session.is_empty()).then_some(session.as_str());
+        let window = self.root().and_downcast::<Window>().unwrap();
+        if let Err(err) = account.respond_to_challenge(&challenge_entry.text(), session, pin) {
+            tracing::warn!("Failed to respond to the challenge {err}");
+            window.add_toast(adw::Toast::new(&gettext("Invalid challenge")));
+            return;
+        }
+        account.copy_otp();
+        let toast = adw::Toast::new(&gettext("One-Time password copied"));
+        toast.set_timeout(3);
+        window.add_toast(toast);
+    }
 }
@@ -0,0 +1,123 @@
+use adw::{prelude::*, subclass::navigation_page::*};
+use anyhow::Result;
+use gtk::{gio, glib, subclass::prelude::*};
+use zeroize::Zeroizing;
+
+use crate::{
+    backup::{Csv, CsvColumn},
+    models::OTPUri,
+};
+
+mod imp {
+    use std::cell::{OnceCell, RefCell};
+
+    use glib::subclass::InitializingObject;
+
+    use super::*;
+
+    #[derive(Default, gtk::CompositeTemplate, glib::Properties)]
+    #[properties(wrapper_type = super::CsvPage)]
+    #[template(resource = "/com/belmoussaoui/Authenticator/preferences_csv_page.ui")]
+    pub struct CsvPage {
+        #[property(get, set, construct_only)]
+        pub actions: OnceCell<gio::SimpleActionGroup>,
+        #[template_child]
+        pub columns_group: TemplateChild<adw::PreferencesGroup>,
+        pub rows: RefCell<Vec<adw::ComboRow>>,
+        pub data: RefCell<Zeroizing<Vec<u8>>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for CsvPage {
+        const NAME: &'static str = "CsvPage";
+        type Type = super::CsvPage;
+        type ParentType = adw::NavigationPage;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for CsvPage {}
+
+    impl WidgetImpl for CsvPage {}
+    impl NavigationPageImpl for CsvPage {
+        fn hidden(&self) {
+            self.parent_hidden();
+            // Don't keep the secrets around once the page is closed
+            self.data.take();
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct CsvPage(ObjectSubclass<imp::CsvPage>)
+        @extends gtk::Widget, adw::NavigationPage;
+}
+
+impl CsvPage {
+    pub fn new(actions: &gio::SimpleActionGroup) -> Self {
+        glib::Object::builder().property("actions", actions).build()
+    }
+
+    /// Shows a row per column of the CSV `data`, preset to the guessed role
+    /// of the column.
+    pub fn set_data(&self, data: Vec<u8>) -> Result<()> {
+        let imp = self.imp();
+        let data = Zeroizing::new(data);
+        let columns = Csv::guess_columns(&data)?;
+
+        for row in imp.rows.take() {
+            imp.columns_group.remove(&row);
+        }
+
+        let roles = CsvColumn::ALL
+            .iter()
+            .map(|column| column.to_locale_string())
+            .collect::<Vec<_>>();
+        let rows = columns
+            .into_iter()
+            .map(|(header, column)| {
+                let model =
+                    gtk::StringList::new(&roles.iter().map(String::as_str).collect::<Vec<_>>());
+                let selected = CsvColumn::ALL
+                    .iter()
+                    .position(|role| *role == column)
+                    .unwrap_or_default();
+                let row = adw::ComboRow::builder()
+                    .title(header)
+                    .model(&model)
+                    .selected(selected as u32)
+                    .build();
+                imp.columns_group.add(&row);
+                row
+            })
+            .collect();
+
+        imp.rows.replace(rows);
+        imp.data.replace(data);
+        Ok(())
+    }
+
+    /// Restores the CSV data with the roles picked by the user.
+    pub fn items(&self) -> Result<Vec<OTPUri>> {
+        let imp = self.imp();
+        let columns = imp
+            .rows
+            .borrow()
+            .iter()
+            .map(|row| {
+                CsvColumn::ALL
+                    .get(row.selected() as usize)
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        Csv::restore_with_columns(&imp.data.borrow(), &columns)
+    }
+}
@@ -1,5 +1,8 @@
 mod camera_page;
+mod csv_page;
 mod password_page;
+mod qrcodes_page;
+mod restore_page;
 mod window;
 
 pub use window::PreferencesWindow;
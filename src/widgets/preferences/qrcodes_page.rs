@@ -0,0 +1,192 @@
+use adw::{prelude::*, subclass::navigation_page::*};
+use anyhow::{Context, Result};
+use gettextrs::gettext;
+use gtk::{
+    gio,
+    glib::{self, clone},
+    subclass::prelude::*,
+};
+
+use crate::{
+    utils::spawn,
+    widgets::accounts::{QRCodeData, QRCodePaintable},
+};
+
+mod imp {
+    use std::cell::{OnceCell, RefCell};
+
+    use glib::subclass::InitializingObject;
+
+    use super::*;
+
+    #[derive(Default, gtk::CompositeTemplate, glib::Properties)]
+    #[properties(wrapper_type = super::QRCodesPage)]
+    #[template(resource = "/com/belmoussaoui/Authenticator/preferences_qrcodes_page.ui")]
+    pub struct QRCodesPage {
+        #[property(get, set, construct_only)]
+        pub actions: OnceCell<gio::SimpleActionGroup>,
+        #[template_child]
+        pub carousel: TemplateChild<adw::Carousel>,
+        pub paintables: RefCell<Vec<QRCodePaintable>>,
+        // Used to name the saved images
+        pub identifier: RefCell<String>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for QRCodesPage {
+        const NAME: &'static str = "QRCodesPage";
+        type Type = super::QRCodesPage;
+        type ParentType = adw::NavigationPage;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+            klass.bind_template_instance_callbacks();
+        }
+
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for QRCodesPage {
+        fn constructed(&self) {
+            self.parent_constructed();
+            self.obj().setup_actions();
+        }
+    }
+
+    impl WidgetImpl for QRCodesPage {}
+    impl NavigationPageImpl for QRCodesPage {}
+}
+
+glib::wrapper! {
+    pub struct QRCodesPage(ObjectSubclass<imp::QRCodesPage>)
+        @extends gtk::Widget, adw::NavigationPage;
+}
+
+#[gtk::template_callbacks]
+impl QRCodesPage {
+    // Size in pixels of the saved images
+    const IMAGE_SIZE: f64 = 512.0;
+
+    pub fn new(actions: &gio::SimpleActionGroup) -> Self {
+        glib::Object::builder().property("actions", actions).build()
+    }
+
+    /// Shows a QR code per line of `codes`.
+    pub fn set_codes(&self, identifier: &str, codes: &str) {
+        let imp = self.imp();
+        let carousel = &*imp.carousel;
+
+        while carousel.n_pages() > 0 {
+            carousel.remove(&carousel.nth_page(0));
+        }
+
+        let paintables = codes
+            .lines()
+            .filter(|code| !code.is_empty())
+            .map(|code| {
+                let paintable = QRCodePaintable::default();
+                paintable.set_qrcode(QRCodeData::from(code));
+
+                let picture = gtk::Picture::builder()
+                    .paintable(&paintable)
+                    .content_fit(gtk::ContentFit::Contain)
+                    .hexpand(true)
+                    .vexpand(true)
+                    .build();
+                carousel.append(&picture);
+                paintable
+            })
+            .collect::<Vec<_>>();
+
+        imp.paintables.replace(paintables);
+        imp.identifier.replace(identifier.to_owned());
+        self.update_navigation();
+    }
+
+    #[template_callback]
+    fn on_page_changed(&self, _index: u32) {
+        self.update_navigation();
+    }
+
+    fn update_navigation(&self) {
+        let carousel = &*self.imp().carousel;
+        let position = carousel.position().round() as u32;
+        let actions = self.actions();
+
+        for (name, enabled) in [
+            ("previous_qr_code", position > 0),
+            ("next_qr_code", position + 1 < carousel.n_pages()),
+        ] {
+            actions
+                .lookup_action(name)
+                .and_downcast::<gio::SimpleAction>()
+                .unwrap()
+                .set_enabled(enabled);
+        }
+    }
+
+    fn scroll_by(&self, delta: i32) {
+        let carousel = &*self.imp().carousel;
+        let position = carousel.position().round() as i32 + delta;
+        if (0..carousel.n_pages() as i32).contains(&position) {
+            carousel.scroll_to(&carousel.nth_page(position as u32), true);
+        }
+    }
+
+    fn setup_actions(&self) {
+        let previous_qr_code = gio::ActionEntry::builder("previous_qr_code")
+            .activate(clone!(@weak self as page => move |_, _, _| {
+                page.scroll_by(-1);
+            }))
+            .build();
+        let next_qr_code = gio::ActionEntry::builder("next_qr_code")
+            .activate(clone!(@weak self as page => move |_, _, _| {
+                page.scroll_by(1);
+            }))
+            .build();
+        let save_qr_codes = gio::ActionEntry::builder("save_qr_codes")
+            .activate(clone!(@weak self as page => move |_, _, _| {
+                spawn(clone!(@weak page => async move {
+                    if let Err(err) = page.save().await {
+                        tracing::error!("Failed to save the QR codes {err}");
+                    }
+                }));
+            }))
+            .build();
+        self.actions()
+            .add_action_entries([previous_qr_code, next_qr_code, save_qr_codes]);
+    }
+
+    /// Saves every QR code as a PNG image into a folder picked by the user.
+    async fn save(&self) -> Result<()> {
+        let imp = self.imp();
+        let window = self.root().and_downcast::<gtk::Window>().unwrap();
+
+        let dialog = gtk::FileDialog::builder()
+            .modal(true)
+            .title(gettext("Select Folder"))
+            .build();
+        let folder = dialog.select_folder_future(Some(&window)).await?;
+        let folder = folder.path().context("The folder is not a local one")?;
+
+        let renderer = window
+            .renderer()
+            .context("The window has no renderer yet")?;
+        let identifier = imp.identifier.borrow().clone();
+
+        for (index, paintable) in imp.paintables.borrow().iter().enumerate() {
+            let snapshot = gtk::Snapshot::new();
+            paintable.snapshot(&snapshot, Self::IMAGE_SIZE, Self::IMAGE_SIZE);
+            let node = snapshot.to_node().context("Failed to render the QR code")?;
+            let texture = renderer.render_texture(node, None);
+
+            let path = folder.join(format!("{identifier}-{}.png", index + 1));
+            tracing::debug!("Saving the QR code into {path:?}");
+            texture.save_to_png(path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use adw::{prelude::*, subclass::navigation_page::*};
+use gettextrs::gettext;
+use gtk::{gio, glib, subclass::prelude::*};
+
+use crate::{
+    backup::{RestorableItem, SkippedItem},
+    models::{i18n, keyring, ProvidersModel},
+    utils::spawn_tokio,
+};
+
+mod imp {
+    use std::cell::{OnceCell, RefCell};
+
+    use glib::subclass::InitializingObject;
+
+    use super::*;
+
+    #[derive(Default, gtk::CompositeTemplate, glib::Properties)]
+    #[properties(wrapper_type = super::RestorePage)]
+    #[template(resource = "/com/belmoussaoui/Authenticator/preferences_restore_page.ui")]
+    pub struct RestorePage {
+        #[property(get, set, construct_only)]
+        pub actions: OnceCell<gio::SimpleActionGroup>,
+        #[template_child]
+        pub items_group: TemplateChild<adw::PreferencesGroup>,
+        #[template_child]
+        pub skipped_group: TemplateChild<adw::PreferencesGroup>,
+        pub rows: RefCell<Vec<adw::ActionRow>>,
+        pub skipped_rows: RefCell<Vec<adw::ActionRow>>,
+        pub items: RefCell<Vec<(gtk::CheckButton, Box<dyn RestorableItem>)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for RestorePage {
+        const NAME: &'static str = "RestorePage";
+        type Type = super::RestorePage;
+        type ParentType = adw::NavigationPage;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    #[glib::derived_properties]
+    impl ObjectImpl for RestorePage {}
+
+    impl WidgetImpl for RestorePage {}
+    impl NavigationPageImpl for RestorePage {
+        fn hidden(&self) {
+            self.parent_hidden();
+            // Don't keep the secrets around once the page is closed
+            self.items.take();
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct RestorePage(ObjectSubclass<imp::RestorePage>)
+        @extends gtk::Widget, adw::NavigationPage;
+}
+
+impl RestorePage {
+    pub fn new(actions: &gio::SimpleActionGroup) -> Self {
+        glib::Object::builder().property("actions", actions).build()
+    }
+
+    /// Shows a row per item about to be restored. The items that were
+    /// already restored are unticked and can't be selected.
+    pub async fn set_items(
+        &self,
+        items: Vec<Box<dyn RestorableItem>>,
+        skipped: &[SkippedItem],
+        model: &ProvidersModel,
+    ) {
+        let tokens = items.iter().map(|item| item.token()).collect::<Vec<_>>();
+        let exists = spawn_tokio(async move { keyring::tokens_exist(&tokens).await })
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("Failed to look for the existing accounts {err}");
+                vec![false; items.len()]
+            });
+
+        let imp = self.imp();
+        for row in imp.rows.take() {
+            imp.items_group.remove(&row);
+        }
+        for row in imp.skipped_rows.take() {
+            imp.skipped_group.remove(&row);
+        }
+
+        let skipped_rows = skipped
+            .iter()
+            .map(|item| {
+                let row = adw::ActionRow::builder()
+                    .title(&item.name)
+                    .subtitle(&item.reason)
+                    .use_markup(false)
+                    .build();
+                imp.skipped_group.add(&row);
+                row
+            })
+            .collect();
+        imp.skipped_rows.replace(skipped_rows);
+        imp.skipped_group.set_visible(!skipped.is_empty());
+
+        let mut rows = Vec::with_capacity(items.len());
+        let items = items
+            .into_iter()
+            .zip(exists)
+            .map(|(item, exists)| {
+                let mut details = vec![item.account(), item.method().to_locale_string()];
+                if let Some(digits) = item.digits() {
+                    details.push(i18n::ni18n_f(
+                        "{} digit",
+                        "{} digits",
+                        digits,
+                        &[&digits.to_string()],
+                    ));
+                }
+                let check_button = gtk::CheckButton::builder()
+                    .active(!exists)
+                    .sensitive(!exists)
+                    .valign(gtk::Align::Center)
+                    .build();
+                let row = adw::ActionRow::builder()
+                    .title(item.issuer())
+                    .subtitle(details.join(" · "))
+                    .activatable_widget(&check_button)
+                    .build();
+                row.add_prefix(&check_button);
+
+                if exists {
+                    row.add_suffix(&Self::flag(
+                        &gettext("Duplicate"),
+                        &gettext("This account was already restored"),
+                    ));
+                }
+                if item.changes_provider(model) {
+                    row.add_suffix(&Self::flag(
+                        &gettext("Changes Provider"),
+                        &gettext(
+                            "Restoring it changes the period or the algorithm of the existing provider",
+                        ),
+                    ));
+                }
+
+                imp.items_group.add(&row);
+                rows.push(row);
+                (check_button, item)
+            })
+            .collect();
+
+        imp.rows.replace(rows);
+        imp.items.replace(items);
+    }
+
+    /// The items left ticked by the user.
+    pub fn selected_items(&self) -> Vec<Box<dyn RestorableItem>> {
+        self.imp()
+            .items
+            .take()
+            .into_iter()
+            .filter(|(check_button, _)| check_button.is_active())
+            .map(|(_, item)| item)
+            .collect()
+    }
+
+    fn flag(label: &str, tooltip: &str) -> gtk::Label {
+        gtk::Label::builder()
+            .label(label)
+            .tooltip_text(tooltip)
+            .valign(gtk::Align::Center)
+            .css_classes(["caption", "warning"])
+            .build()
+    }
+}
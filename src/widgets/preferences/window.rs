@@ -1,5 +1,5 @@
 use adw::prelude::*;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use gettextrs::gettext;
 use gtk::{
     gio,
@@ -7,14 +7,18 @@ use gtk::{
     subclass::prelude::*,
 };
 
-use super::{camera_page::CameraPage, password_page::PasswordPage};
+use super::{
+    camera_page::CameraPage, csv_page::CsvPage, password_page::PasswordPage,
+    qrcodes_page::QRCodesPage, restore_page::RestorePage,
+};
 use crate::{
     backup::{
-        Aegis, AndOTP, Backupable, Bitwarden, FreeOTP, FreeOTPJSON, Google, LegacyAuthenticator,
-        Operation, RaivoOTP, Restorable, RestorableItem,
+        Aegis, AndOTP, AuthenticatorPro, Backupable, Bitwarden, Csv, Ente, FreeOTP, FreeOTPBackup,
+        FreeOTPJSON, Google, KeePass, LegacyAuthenticator, MissingQRCodes, Native, Operation,
+        PasswordStore, RaivoOTP, Restorable, RestorableItem, SkippedItem, Steam, TwoFAS,
     },
-    models::{ProvidersModel, SETTINGS},
-    utils::spawn,
+    models::{i18n, ProvidersModel, SETTINGS},
+    utils::{spawn, spawn_tokio},
     widgets::screenshot,
 };
 
@@ -42,7 +46,10 @@ mod imp {
         pub backup_actions: gio::SimpleActionGroup,
         pub restore_actions: gio::SimpleActionGroup,
         pub camera_page: CameraPage,
+        pub csv_page: CsvPage,
         pub password_page: PasswordPage,
+        pub qrcodes_page: QRCodesPage,
+        pub restore_page: RestorePage,
         #[template_child]
         pub backup_group: TemplateChild<adw::PreferencesGroup>,
         #[template_child]
@@ -70,7 +77,10 @@ mod imp {
             Self {
                 has_set_password: Cell::default(), // Synced from the application
                 camera_page: CameraPage::new(&actions),
+                csv_page: CsvPage::new(&actions),
                 password_page: PasswordPage::new(&actions),
+                qrcodes_page: QRCodesPage::new(&actions),
+                restore_page: RestorePage::new(&actions),
                 actions,
                 model: OnceCell::default(),
                 backup_actions: gio::SimpleActionGroup::new(),
@@ -160,21 +170,39 @@ impl PreferencesWindow {
             .build();
 
         // FreeOTP is first in all of these lists, since its the way to backup
-        // Authenticator for use with Authenticator. Others are sorted
-        // alphabetically.
+        // Authenticator for use with Authenticator, followed by the native
+        // format keeping everything. Others are sorted alphabetically.
 
         self.register_backup::<FreeOTP>(&["text/plain"]);
+        self.register_backup::<Native>(&["application/octet-stream"]);
+        self.register_backup::<TwoFAS>(&["application/json"]);
         self.register_backup::<Aegis>(&["application/json"]);
         self.register_backup::<AndOTP>(&["application/json"]);
+        self.register_backup::<Csv>(&["text/csv"]);
+        self.register_backup::<Google>(&[]);
+        self.register_pass_backup();
 
         self.register_restore::<FreeOTP>(&["text/plain"]);
+        self.register_restore::<Native>(&["application/octet-stream"]);
         self.register_restore::<FreeOTPJSON>(&["application/json"]);
+        self.register_restore::<FreeOTPBackup>(&["application/xml", "application/octet-stream"]);
+        self.register_restore::<TwoFAS>(&["application/json"]);
         self.register_restore::<Aegis>(&["application/json"]);
-        self.register_restore::<AndOTP>(&["application/json"]);
+        self.register_restore::<AndOTP>(&["application/json", "application/octet-stream"]);
+        self.register_restore::<AuthenticatorPro>(&[
+            "application/json",
+            "application/octet-stream",
+        ]);
         self.register_restore::<Bitwarden>(&["application/json"]);
+        self.register_csv_restore(&["text/csv"]);
+        self.register_restore::<Ente>(&["text/plain"]);
         self.register_restore::<Google>(&[]);
+        self.register_restore::<KeePass>(&["application/x-keepass2"]);
         self.register_restore::<LegacyAuthenticator>(&["application/json"]);
+        self.register_pass_restore();
         self.register_restore::<RaivoOTP>(&["application/zip"]);
+        // maFiles have no MIME type of their own
+        self.register_restore::<Steam>(&["*.maFile", "application/zip"]);
     }
 
     fn register_backup<T: Backupable>(&self, filters: &'static [&str]) {
@@ -218,19 +246,41 @@ impl PreferencesWindow {
             imp.backup_group.add(&row);
         }
 
-        let action = gio::ActionEntry::builder(T::IDENTIFIER)
-            .activate(clone!(@weak self as win => move |_, _,_| {
-                spawn(clone!(@weak win => async move {
-                    if let Err(err) = win.backup_into_file::<T>(filters).await {
-                        tracing::error!("Failed to backup into a file {err}");
+        let action = if T::SCANNABLE {
+            gio::ActionEntry::builder(T::IDENTIFIER)
+                .activate(clone!(@weak self as win => move |_, _, _| {
+                    if let Err(err) = win.backup_into_qr_codes::<T>() {
+                        tracing::error!("Failed to backup into QR codes {err}");
                         win.add_toast(adw::Toast::new(&gettext("Failed to create a backup")));
                     }
-                }));
-            }))
-            .build();
+                }))
+                .build()
+        } else {
+            gio::ActionEntry::builder(T::IDENTIFIER)
+                .activate(clone!(@weak self as win => move |_, _,_| {
+                    spawn(clone!(@weak win => async move {
+                        if let Err(err) = win.backup_into_file::<T>(filters).await {
+                            tracing::error!("Failed to backup into a file {err}");
+                            win.add_toast(adw::Toast::new(&gettext("Failed to create a backup")));
+                        }
+                    }));
+                }))
+                .build()
+        };
         imp.backup_actions.add_action_entries([action]);
     }
 
+    fn backup_into_qr_codes<T: Backupable>(&self) -> Result<()> {
+        let imp = self.imp();
+        let model = self.model();
+        let content = T::backup(&model, None)?;
+        let codes = String::from_utf8(content)?;
+        imp.qrcodes_page.set_codes(T::IDENTIFIER, &codes);
+        self.push_subpage(&imp.qrcodes_page);
+        self.notify_skipped_accounts::<T>(&model);
+        Ok(())
+    }
+
     async fn backup_into_file<T: Backupable>(&self, filters: &'static [&str]) -> Result<()> {
         let model = self.model();
         let file = self.select_file(filters, Operation::Backup).await?;
@@ -246,9 +296,22 @@ impl PreferencesWindow {
         )
         .await
         .map_err(|e| e.1)?;
+        self.notify_skipped_accounts::<T>(&model);
         Ok(())
     }
 
+    fn notify_skipped_accounts<T: Backupable>(&self, model: &ProvidersModel) {
+        let skipped = T::skipped_accounts(model);
+        if skipped > 0 {
+            self.add_toast(adw::Toast::new(&i18n::ni18n_f(
+                "{} account is not supported by this format and was left out",
+                "{} accounts are not supported by this format and were left out",
+                skipped,
+                &[&skipped.to_string()],
+            )));
+        }
+    }
+
     fn register_restore<T: Restorable>(&self, filters: &'static [&str]) {
         let imp = self.imp();
         if T::ENCRYPTABLE {
@@ -378,6 +441,159 @@ impl PreferencesWindow {
             imp.restore_actions.add_action_entries([action]);
         };
     }
+
+    /// CSV files have no fixed set of columns, the user confirms the role of
+    /// each of them before restoring.
+    fn register_csv_restore(&self, filters: &'static [&str]) {
+        let imp = self.imp();
+        let row = adw::ActionRow::builder()
+            .title(<Csv as Restorable>::title())
+            .subtitle(<Csv as Restorable>::subtitle())
+            .activatable(true)
+            .use_underline(true)
+            .action_name(format!("restore.{}", <Csv as Restorable>::IDENTIFIER))
+            .build();
+        imp.restore_group.add(&row);
+
+        let action = gio::ActionEntry::builder(<Csv as Restorable>::IDENTIFIER)
+            .activate(clone!(@weak self as win => move |_, _, _| {
+                spawn(clone!(@weak win => async move {
+                    if let Err(err) = win.select_csv_columns(filters).await {
+                        tracing::error!("Failed to restore from a file {err}");
+                        win.add_toast(adw::Toast::new(&gettext("Failed to restore from a file")));
+                    }
+                }));
+            }))
+            .build();
+        imp.restore_actions.add_action_entries([action]);
+    }
+
+    async fn select_csv_columns(&self, filters: &'static [&str]) -> Result<()> {
+        let imp = self.imp();
+        let file = self.select_file(filters, Operation::Restore).await?;
+        let (content, _) = file.load_contents_future().await?;
+        imp.csv_page.set_data(content.into())?;
+        self.push_subpage(&imp.csv_page);
+        Ok(())
+    }
+
+    async fn restore_from_csv(&self) -> Result<()> {
+        let items = self.imp().csv_page.items()?;
+        self.pop_subpage();
+        self.restore_items::<Csv>(items, Vec::new()).await;
+        Ok(())
+    }
+
+    /// A password store is a directory, the entries are decrypted by gpg
+    /// instead of going through `restore_from_data`.
+    fn register_pass_restore(&self) {
+        let imp = self.imp();
+        let row = adw::ActionRow::builder()
+            .title(PasswordStore::title())
+            .subtitle(PasswordStore::subtitle())
+            .activatable(true)
+            .use_underline(true)
+            .action_name(format!("restore.{}", PasswordStore::IDENTIFIER))
+            .build();
+        imp.restore_group.add(&row);
+
+        let action = gio::ActionEntry::builder(PasswordStore::IDENTIFIER)
+            .activate(clone!(@weak self as win => move |_, _, _| {
+                spawn(clone!(@weak win => async move {
+                    if let Err(err) = win.restore_from_pass().await {
+                        tracing::error!("Failed to restore from a password store {err}");
+                        win.add_toast(adw::Toast::new(&gettext("Failed to restore from a password store")));
+                    }
+                }));
+            }))
+            .build();
+        imp.restore_actions.add_action_entries([action]);
+    }
+
+    async fn restore_from_pass(&self) -> Result<()> {
+        let folder = self.select_folder(Operation::Restore).await?;
+        let store = PasswordStore::new(folder.path().context("The folder is not local")?);
+        // gpg might wait for the user to type the passphrase of the key
+        let (items, skipped) =
+            spawn_tokio(async move { tokio::task::spawn_blocking(move || store.restore()).await })
+                .await??;
+        self.restore_items::<PasswordStore>(items, skipped).await;
+        Ok(())
+    }
+
+    fn register_pass_backup(&self) {
+        let imp = self.imp();
+        let row = adw::ExpanderRow::builder()
+            .title(PasswordStore::backup_title())
+            .subtitle(PasswordStore::backup_subtitle())
+            .show_enable_switch(false)
+            .enable_expansion(true)
+            .use_underline(true)
+            .build();
+        let recipient_entry = adw::EntryRow::builder()
+            .title(gettext("GnuPG Key ID or Email"))
+            .build();
+        row.add_row(&recipient_entry);
+
+        let button_row = adw::ActionRow::builder()
+            // Translators: The password store lists the keys to encrypt for in its .gpg-id file.
+            .subtitle(gettext(
+                "Leave it empty to use the keys of the password store",
+            ))
+            .build();
+        let folder_button = gtk::Button::builder()
+            .valign(gtk::Align::Center)
+            .halign(gtk::Align::End)
+            .label(gettext("Select Folder"))
+            .action_name(format!("backup.{}", PasswordStore::IDENTIFIER))
+            .build();
+        button_row.add_suffix(&folder_button);
+        row.add_row(&button_row);
+        imp.backup_group.add(&row);
+
+        let action = gio::ActionEntry::builder(PasswordStore::IDENTIFIER)
+            .activate(
+                clone!(@weak self as win, @weak recipient_entry => move |_, _, _| {
+                    spawn(clone!(@weak win, @weak recipient_entry => async move {
+                        let recipient = recipient_entry.text();
+                        if let Err(err) = win.backup_into_pass(&recipient).await {
+                            tracing::error!("Failed to backup into a password store {err}");
+                            win.add_toast(adw::Toast::new(&gettext("Failed to create a backup")));
+                        }
+                    }));
+                }),
+            )
+            .build();
+        imp.backup_actions.add_action_entries([action]);
+    }
+
+    async fn backup_into_pass(&self, recipient: &str) -> Result<()> {
+        let folder = self.select_folder(Operation::Backup).await?;
+        let store = PasswordStore::new(folder.path().context("The folder is not local")?);
+        let entries = store.backup_entries(&self.model());
+        let recipient = recipient.to_owned();
+        // gpg runs once per account
+        let (count, skipped) = spawn_tokio(async move {
+            tokio::task::spawn_blocking(move || store.backup(&entries, Some(&recipient))).await
+        })
+        .await??;
+        self.add_toast(adw::Toast::new(&i18n::ni18n_f(
+            "{} account was saved into the password store",
+            "{} accounts were saved into the password store",
+            count as u32,
+            &[&count.to_string()],
+        )));
+        if skipped > 0 {
+            self.add_toast(adw::Toast::new(&i18n::ni18n_f(
+                "{} account was left out as its entry already exists",
+                "{} accounts were left out as their entry already exists",
+                skipped as u32,
+                &[&skipped.to_string()],
+            )));
+        }
+        Ok(())
+    }
+
     async fn restore_from_file<T: Restorable<Item = Q>, Q: RestorableItem>(
         &self,
         filters: &'static [&str],
@@ -387,16 +603,34 @@ impl PreferencesWindow {
             .then(|| self.encryption_key(Operation::Restore, T::IDENTIFIER))
             .flatten();
         let content = file.load_contents_future().await?;
-        let items = T::restore_from_data(&content.0, key.as_deref())?;
-        self.restore_items::<T, T::Item>(items);
+        let (items, skipped) = T::restore_from_data_with_skipped(&content.0, key.as_deref())?;
+        self.restore_items::<T>(items, skipped).await;
         Ok(())
     }
 
     async fn restore_from_camera<T: Restorable<Item = Q>, Q: RestorableItem>(&self) -> Result<()> {
-        let code = self.imp().camera_page.scan_from_camera().await?;
-        let items = T::restore_from_data(code.as_bytes(), None)?;
-        self.restore_items::<T, T::Item>(items);
+        // Some exports are split across several QR codes, keep scanning until
+        // all of them were seen
+        let mut codes = Vec::new();
+        let (items, skipped) = loop {
+            let code = self.imp().camera_page.scan_from_camera().await?;
+            codes.push(code);
+            match T::restore_from_data_with_skipped(codes.join("\n").as_bytes(), None) {
+                Ok(restored) => break restored,
+                Err(err) => match err.downcast_ref::<MissingQRCodes>() {
+                    Some(missing) => {
+                        self.add_toast(adw::Toast::new(&i18n::i18n_f(
+                            "Scanned {} of {} QR codes",
+                            &[&missing.scanned.to_string(), &missing.total.to_string()],
+                        )));
+                    }
+                    None => return Err(err),
+                },
+            }
+        };
+        // Leave the camera before showing the accounts to restore
         self.imp().actions.activate_action("close_page", None);
+        self.restore_items::<T>(items, skipped).await;
         Ok(())
     }
 
@@ -404,8 +638,8 @@ impl PreferencesWindow {
         &self,
     ) -> Result<()> {
         let code = self.imp().camera_page.scan_from_screenshot().await?;
-        let items = T::restore_from_data(code.as_bytes(), None)?;
-        self.restore_items::<T, T::Item>(items);
+        let (items, skipped) = T::restore_from_data_with_skipped(code.as_bytes(), None)?;
+        self.restore_items::<T>(items, skipped).await;
         Ok(())
     }
 
@@ -426,8 +660,8 @@ impl PreferencesWindow {
         let file = dialog.open_future(Some(&window)).await?;
         let (data, _) = file.load_contents_future().await?;
         let code = screenshot::scan(&data)?;
-        let items = T::restore_from_data(code.as_bytes(), None)?;
-        self.restore_items::<T, T::Item>(items);
+        let (items, skipped) = T::restore_from_data_with_skipped(code.as_bytes(), None)?;
+        self.restore_items::<T>(items, skipped).await;
         Ok(())
     }
 
@@ -443,18 +677,48 @@ impl PreferencesWindow {
             .map(|entry| entry.text())
     }
 
-    fn restore_items<T: Restorable<Item = Q>, Q: RestorableItem>(&self, items: Vec<Q>) {
+    /// Lets the user pick the items to restore, see `restore_selected`. The
+    /// `skipped` entries are listed so the user knows what is missing.
+    async fn restore_items<T: Restorable>(&self, items: Vec<T::Item>, skipped: Vec<SkippedItem>) {
+        let imp = self.imp();
+        let items = items
+            .into_iter()
+            .map(|item| Box::new(item) as Box<dyn RestorableItem>)
+            .collect();
+        imp.restore_page
+            .set_items(items, &skipped, &self.model())
+            .await;
+        self.push_subpage(&imp.restore_page);
+    }
+
+    /// The accounts that already exist can't be selected, they are not looked
+    /// up again.
+    fn restore_selected(&self) {
+        let items = self.imp().restore_page.selected_items();
+        self.pop_subpage();
         let model = self.model();
-        items
+        let failed = items
             .iter()
-            .map(move |item| item.restore(&model))
-            .for_each(|item| {
+            .map(move |item| item.restore_unchecked(&model))
+            .filter(|item| {
                 if let Err(err) = item {
                     tracing::warn!("Failed to restore item {}", err);
                 }
-            });
+                item.is_err()
+            })
+            .count();
         self.emit_by_name::<()>("restore-completed", &[]);
-        self.close();
+        if failed == 0 {
+            self.close();
+        } else {
+            // Stay open so the user notices the accounts that are missing
+            self.add_toast(adw::Toast::new(&i18n::ni18n_f(
+                "{} account could not be restored",
+                "{} accounts could not be restored",
+                failed as u32,
+                &[&failed.to_string()],
+            )));
+        }
     }
 
     async fn select_file(
@@ -466,7 +730,12 @@ impl PreferencesWindow {
         let window = self.root().and_downcast::<gtk::Window>().unwrap();
         filters.iter().for_each(|f| {
             let filter = gtk::FileFilter::new();
-            filter.add_mime_type(f);
+            // Glob patterns for the files without a MIME type of their own
+            if f.starts_with('*') {
+                filter.add_pattern(f);
+            } else {
+                filter.add_mime_type(f);
+            }
             filter.set_name(Some(f));
             filters_model.append(&filter);
         });
@@ -491,6 +760,16 @@ impl PreferencesWindow {
         }
     }
 
+    async fn select_folder(&self, operation: Operation) -> Result<gio::File, glib::Error> {
+        let window = self.root().and_downcast::<gtk::Window>().unwrap();
+        let title = match operation {
+            Operation::Backup => gettext("Backup"),
+            Operation::Restore => gettext("Restore"),
+        };
+        let dialog = gtk::FileDialog::builder().modal(true).title(title).build();
+        dialog.select_folder_future(Some(&window)).await
+    }
+
     fn setup_actions(&self) {
         let imp = self.imp();
 
@@ -514,6 +793,36 @@ impl PreferencesWindow {
                 win.set_search_enabled(true);
             }));
 
+        imp.csv_page
+            .connect_map(clone!(@weak self as win => move |_| {
+                win.set_search_enabled(false);
+            }));
+
+        imp.csv_page
+            .connect_unmap(clone!(@weak self as win => move |_| {
+                win.set_search_enabled(true);
+            }));
+
+        imp.qrcodes_page
+            .connect_map(clone!(@weak self as win => move |_| {
+                win.set_search_enabled(false);
+            }));
+
+        imp.qrcodes_page
+            .connect_unmap(clone!(@weak self as win => move |_| {
+                win.set_search_enabled(true);
+            }));
+
+        imp.restore_page
+            .connect_map(clone!(@weak self as win => move |_| {
+                win.set_search_enabled(false);
+            }));
+
+        imp.restore_page
+            .connect_unmap(clone!(@weak self as win => move |_| {
+                win.set_search_enabled(true);
+            }));
+
         let show_camera_page = gio::ActionEntry::builder("show_camera_page")
             .activate(clone!(@weak self as win => move |_, _, _| {
                 win.push_subpage(&win.imp().camera_page);
@@ -532,8 +841,30 @@ impl PreferencesWindow {
             }))
             .build();
 
-        imp.actions
-            .add_action_entries([show_camera_page, show_password_page, close_page]);
+        let restore_csv = gio::ActionEntry::builder("restore_csv")
+            .activate(clone!(@weak self as win => move |_, _, _| {
+                spawn(clone!(@weak win => async move {
+                    if let Err(err) = win.restore_from_csv().await {
+                        tracing::error!("Failed to restore from a CSV file {err}");
+                        win.add_toast(adw::Toast::new(&gettext("Failed to restore from a file")));
+                    }
+                }));
+            }))
+            .build();
+
+        let restore_selected = gio::ActionEntry::builder("restore_selected")
+            .activate(clone!(@weak self as win => move |_, _, _| {
+                win.restore_selected();
+            }))
+            .build();
+
+        imp.actions.add_action_entries([
+            show_camera_page,
+            show_password_page,
+            close_page,
+            restore_csv,
+            restore_selected,
+        ]);
 
         self.insert_action_group("preferences", Some(&imp.actions));
         self.insert_action_group("backup", Some(&imp.backup_actions));
@@ -9,7 +9,9 @@ use gtk::{
 };
 
 use crate::{
-    models::{i18n, Algorithm, Method, Provider, ProviderPatch, FAVICONS_PATH, OTP},
+    models::{
+        i18n, Algorithm, Method, OcraSuite, Provider, ProviderPatch, Rendering, FAVICONS_PATH, OTP,
+    },
     widgets::{ErrorRevealer, ProviderImage},
 };
 
@@ -47,6 +49,12 @@ mod imp {
         #[template_child]
         pub algorithm_comborow: TemplateChild<adw::ComboRow>,
         #[template_child]
+        pub ocra_suite_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub group_size_spinbutton: TemplateChild<adw::SpinRow>,
+        #[template_child]
+        pub alphabet_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
         pub delete_button: TemplateChild<gtk::Button>,
         pub selected_provider: RefCell<Option<Provider>>,
         pub selected_image: RefCell<Option<gio::File>>,
@@ -74,6 +82,9 @@ mod imp {
                 provider_help_entry: TemplateChild::default(),
                 method_comborow: TemplateChild::default(),
                 algorithm_comborow: TemplateChild::default(),
+                ocra_suite_entry: TemplateChild::default(),
+                group_size_spinbutton: TemplateChild::default(),
+                alphabet_entry: TemplateChild::default(),
                 delete_button: TemplateChild::default(),
                 methods_model,
                 algorithms_model,
@@ -182,6 +193,12 @@ impl ProviderPage {
                 imp.methods_model
                     .find_position(provider.method().into_glib()),
             );
+            imp.ocra_suite_entry
+                .set_text(&provider.ocra_suite().unwrap_or_default());
+            imp.group_size_spinbutton
+                .set_value(provider.group_size() as f64);
+            imp.alphabet_entry
+                .set_text(&provider.alphabet().unwrap_or_default());
             imp.image.set_provider(Some(&provider));
             self.set_title(&i18n::i18n_f("Editing Provider: {}", &[&provider.name()]));
             imp.selected_provider.replace(Some(provider));
@@ -205,6 +222,10 @@ impl ProviderPage {
                 imp.methods_model
                     .find_position(Method::default().into_glib()),
             );
+            imp.ocra_suite_entry.set_text("");
+            imp.group_size_spinbutton
+                .set_value(Rendering::DEFAULT_GROUP_SIZE as f64);
+            imp.alphabet_entry.set_text("");
             imp.image.set_provider(None::<Provider>);
             self.set_title(&gettext("New Provider"));
             imp.selected_provider.replace(None);
@@ -223,10 +244,23 @@ impl ProviderPage {
         let website = imp.provider_website_entry.text().to_string();
         let help_url = imp.provider_help_entry.text().to_string();
         let period = imp.period_spinbutton.value() as u32;
-        let digits = imp.digits_spinbutton.value() as u32;
+        let mut digits = imp.digits_spinbutton.value() as u32;
         let method = Method::from(imp.method_comborow.selected());
-        let algorithm = Algorithm::from(imp.algorithm_comborow.selected());
+        let mut algorithm = Algorithm::from(imp.algorithm_comborow.selected());
         let default_counter = imp.default_counter_spinbutton.value() as u32;
+        let group_size = imp.group_size_spinbutton.value() as u32;
+        let alphabet = imp.alphabet_entry.text();
+        let alphabet = (!alphabet.is_empty()).then_some(alphabet.as_str());
+
+        // The OCRA suite defines the algorithm and the length of the responses
+        let ocra_suite = if method.is_challenge_based() {
+            let suite = imp.ocra_suite_entry.text().parse::<OcraSuite>()?;
+            algorithm = suite.algorithm;
+            digits = suite.digits;
+            Some(suite.to_string())
+        } else {
+            None
+        };
 
         let image_uri = if let Some(file) = imp.selected_image.borrow().as_ref() {
             let basename = file.basename().unwrap();
@@ -267,6 +301,8 @@ impl ProviderPage {
                 method: method.to_string(),
                 is_backup_restore: false,
             })?;
+            provider.update_ocra_suite(ocra_suite.as_deref())?;
+            provider.update_rendering(group_size, alphabet)?;
             self.emit_by_name::<()>("updated", &[provider]);
         } else {
             let provider = Provider::create(
@@ -280,6 +316,8 @@ impl ProviderPage {
                 Some(help_url),
                 image_uri,
             )?;
+            provider.update_ocra_suite(ocra_suite.as_deref())?;
+            provider.update_rendering(group_size, alphabet)?;
             self.emit_by_name::<()>("created", &[&provider]);
         }
         Ok(())
@@ -344,9 +382,15 @@ impl ProviderPage {
         let provider_website = imp.provider_website_entry.text();
         let provider_help_url = imp.provider_help_entry.text();
 
+        let is_challenge_based = Method::from(imp.method_comborow.selected()).is_challenge_based();
+        let ocra_suite = imp.ocra_suite_entry.text();
+        let alphabet = imp.alphabet_entry.text();
+
         let is_valid = !provider_name.is_empty()
             && (provider_website.is_empty() || url::Url::parse(&provider_website).is_ok())
-            && (provider_help_url.is_empty() || url::Url::parse(&provider_help_url).is_ok());
+            && (provider_help_url.is_empty() || url::Url::parse(&provider_help_url).is_ok())
+            && (!is_challenge_based || ocra_suite.parse::<OcraSuite>().is_ok())
+            && (alphabet.is_empty() || Rendering::is_valid_alphabet(&alphabet));
 
         self.action_set_enabled("providers.save", is_valid);
     }
@@ -380,14 +424,51 @@ impl ProviderPage {
                 imp.algorithm_comborow
                     .set_selected(Algorithm::default().into_glib() as u32);
             }
+            Method::MOTP => {
+                imp.default_counter_spinbutton.set_visible(false);
+                imp.period_spinbutton.set_visible(true);
+                imp.digits_spinbutton
+                    .set_value(OTP::MOTP_DEFAULT_DIGITS as f64);
+                imp.period_spinbutton
+                    .set_value(OTP::MOTP_DEFAULT_PERIOD as f64);
+                imp.algorithm_comborow
+                    .set_selected(Algorithm::default().into_glib() as u32);
+            }
+            Method::Yandex => {
+                imp.default_counter_spinbutton.set_visible(false);
+                imp.period_spinbutton.set_visible(true);
+                imp.digits_spinbutton
+                    .set_value(OTP::YANDEX_DEFAULT_DIGITS as f64);
+                imp.period_spinbutton
+                    .set_value(OTP::YANDEX_DEFAULT_PERIOD as f64);
+                imp.algorithm_comborow
+                    .set_selected(Algorithm::SHA256.into_glib() as u32);
+            }
+            Method::OCRA => {
+                imp.default_counter_spinbutton.set_visible(true);
+                imp.period_spinbutton.set_visible(false);
+                imp.default_counter_spinbutton
+                    .set_value(OTP::DEFAULT_COUNTER as f64);
+            }
         }
-
-        imp.algorithm_comborow
-            .set_sensitive(selected != Method::Steam);
-        imp.period_spinbutton
-            .set_sensitive(selected != Method::Steam);
-        imp.digits_spinbutton
-            .set_sensitive(selected != Method::Steam);
+        imp.ocra_suite_entry
+            .set_visible(selected.is_challenge_based());
+        // The other methods have their own way of displaying the codes
+        let is_numeric = matches!(selected, Method::TOTP | Method::HOTP);
+        imp.group_size_spinbutton.set_visible(is_numeric);
+        imp.alphabet_entry.set_visible(is_numeric);
+
+        // Steam, mOTP and Yandex have a fixed algorithm, period and length
+        let is_fixed = matches!(selected, Method::Steam | Method::MOTP | Method::Yandex);
+        imp.algorithm_comborow.set_sensitive(!is_fixed);
+        imp.period_spinbutton.set_sensitive(!is_fixed);
+        imp.digits_spinbutton.set_sensitive(!is_fixed);
+        // The OCRA suite already defines them
+        if selected.is_challenge_based() {
+            imp.algorithm_comborow.set_sensitive(false);
+            imp.digits_spinbutton.set_sensitive(false);
+        }
+        self.entry_validate(imp.ocra_suite_entry.get());
     }
 
     #[template_callback]